@@ -0,0 +1,166 @@
+// benches/fanout.rs
+
+// 广播扇出的分配次数基准：`cargo bench --bench fanout`
+//
+// 对比两种把一条广播投递给 N 个接收者的方式：
+//   per-recipient  每个接收者各自 clone 一份 ServerMessage，各自序列化（旧做法）
+//   shared         只构造一个 SharedMessage，接收者之间共享消息和编码结果
+// 每次广播都会走完整的路径：入队 -> 出队 -> 编码进连接的写缓冲区。
+//
+// 参考结果（release，三种协议各占三分之一；耗时一列随机器而变，分配次数是确定的）：
+//
+//     strategy       recipients     allocs/bcast      bytes/bcast       us/bcast
+//     per-recipient          10             65.0           4547.6            7.8
+//     shared                 10              8.0            564.4            2.1
+//     per-recipient         100            665.0          46208.6           86.2
+//     shared                100              8.0            564.4           13.9
+//     per-recipient         500           3330.0         231287.1          418.9
+//     shared                500              8.0            564.4           66.9
+
+use bytes::BytesMut;
+use std::alloc::{ GlobalAlloc, Layout, System };
+use std::hint::black_box;
+use std::sync::Arc;
+use std::sync::atomic::{ AtomicUsize, Ordering };
+use std::time::Instant;
+use websocket::codec::{ Codec, JsonCodec, LineCodec, SharedMessage, WebSocketCodec };
+use websocket::connection::queue::{
+    ClientReceiver,
+    ClientSender,
+    QueueMetrics,
+    SlowConsumerPolicy,
+    client_queue,
+};
+use websocket::protocol::ServerMessage;
+
+/// 统计分配次数和字节数的分配器
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(new_size, Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+const RECIPIENTS: [usize; 3] = [10, 100, 500];
+const ROUNDS: usize = 200;
+
+/// 一个模拟的接收者：队列 + 编解码器 + 复用的写缓冲区（与 Transport 相同）
+struct Recipient {
+    tx: ClientSender,
+    rx: ClientReceiver,
+    codec: Box<dyn Codec>,
+    write_buf: BytesMut,
+}
+
+fn recipients(n: usize) -> Vec<Recipient> {
+    let metrics = Arc::new(QueueMetrics::default());
+    (0..n)
+        .map(|i| {
+            let (tx, rx) = client_queue(16, usize::MAX, SlowConsumerPolicy::DropNewest, metrics.clone());
+            // 三种线路协议的客户端各占三分之一
+            let mut codec: Box<dyn Codec> = match i % 3 {
+                0 => Box::new(JsonCodec::new()),
+                1 => Box::new(WebSocketCodec::new()),
+                _ => Box::new(LineCodec::new()),
+            };
+            let mut write_buf = BytesMut::with_capacity(4096);
+            // 行协议登录后才会走编码缓存
+            let welcome = ServerMessage::Welcome { username: format!("user{i}") };
+            codec.encode(&welcome, &mut write_buf).unwrap();
+            Recipient { tx, rx, codec, write_buf }
+        })
+        .collect()
+}
+
+fn message(round: usize) -> ServerMessage {
+    ServerMessage::NewMessage {
+        from: "alice".to_string(),
+        content: format!("message #{round}: the quick brown fox jumps over the lazy dog"),
+    }
+}
+
+/// 每个接收者把队列里的消息取出并编码，模拟连接任务的写路径
+fn drain(recipients: &mut [Recipient]) {
+    for r in recipients.iter_mut() {
+        while let Some(msg) = r.rx.try_recv() {
+            r.codec.encode_shared(&msg, &mut r.write_buf).unwrap();
+        }
+        black_box(&r.write_buf);
+        r.write_buf.clear();
+    }
+}
+
+fn per_recipient(recipients: &mut [Recipient], msg: ServerMessage) {
+    for r in recipients.iter() {
+        r.tx.try_send(msg.clone());
+    }
+    drain(recipients);
+}
+
+fn shared(recipients: &mut [Recipient], msg: ServerMessage) {
+    let msg = SharedMessage::new(msg);
+    for r in recipients.iter() {
+        r.tx.try_send(msg.clone());
+    }
+    drain(recipients);
+}
+
+fn measure(name: &str, n: usize, fan_out: fn(&mut [Recipient], ServerMessage)) {
+    let mut recipients = recipients(n);
+    // 预热：让写缓冲区和队列的容量稳定下来
+    for round in 0..10 {
+        fan_out(&mut recipients, message(round));
+    }
+
+    let messages: Vec<ServerMessage> = (0..ROUNDS).map(message).collect();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let bytes = ALLOCATED_BYTES.load(Ordering::Relaxed);
+    let started = Instant::now();
+    for msg in messages {
+        fan_out(&mut recipients, msg);
+    }
+    let elapsed = started.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+    let bytes = ALLOCATED_BYTES.load(Ordering::Relaxed) - bytes;
+
+    println!(
+        "{name:<14} {n:>10} {:>16.1} {:>16.1} {:>14.1}",
+        (allocations as f64) / (ROUNDS as f64),
+        (bytes as f64) / (ROUNDS as f64),
+        elapsed.as_secs_f64() * 1e6 / (ROUNDS as f64)
+    );
+}
+
+fn main() {
+    println!(
+        "{:<14} {:>10} {:>16} {:>16} {:>14}",
+        "strategy",
+        "recipients",
+        "allocs/bcast",
+        "bytes/bcast",
+        "us/bcast"
+    );
+    for n in RECIPIENTS {
+        measure("per-recipient", n, per_recipient);
+        measure("shared", n, shared);
+    }
+}
@@ -1,3 +1,3 @@
-cargo run --bin actor_server --release
+cargo run --bin chat_server --release -- --backend actor
 
-cargo run --bin mutex_server --release
\ No newline at end of file
+cargo run --bin chat_server --release -- --backend mutex
\ No newline at end of file
@@ -0,0 +1,28 @@
+#!/bin/sh
+# 生成本地测试用的自签名证书：一个 CA、由它签发的服务器证书（localhost / 127.0.0.1），
+# 以及每个参数一张客户端证书（CN 即用户名，用于双向 TLS）。
+#   ./gen_certs.sh alice bob
+# 然后：cargo run -- --tls-cert certs/server.pem --tls-key certs/server.key --listen json+tls=127.0.0.1:8443
+set -e
+DIR=certs
+mkdir -p "$DIR"
+cd "$DIR"
+
+if [ ! -f ca.pem ]; then
+    openssl req -x509 -newkey rsa:2048 -nodes -days 365 -subj "/CN=chat-test-ca" \
+        -keyout ca.key -out ca.pem
+fi
+
+openssl req -newkey rsa:2048 -nodes -subj "/CN=localhost" -keyout server.key -out server.csr
+printf "subjectAltName=DNS:localhost,IP:127.0.0.1\nextendedKeyUsage=serverAuth\n" > server.ext
+openssl x509 -req -in server.csr -CA ca.pem -CAkey ca.key -CAcreateserial -days 365 \
+    -extfile server.ext -out server.pem
+rm -f server.csr server.ext
+
+for name in "$@"; do
+    openssl req -newkey rsa:2048 -nodes -subj "/CN=$name" -keyout "$name.key" -out "$name.csr"
+    printf "extendedKeyUsage=clientAuth\n" > "$name.ext"
+    openssl x509 -req -in "$name.csr" -CA ca.pem -CAkey ca.key -CAcreateserial -days 365 \
+        -extfile "$name.ext" -out "$name.pem"
+    rm -f "$name.csr" "$name.ext"
+done
@@ -1,17 +1,66 @@
 // press_test/src/client.rs
 
-use crate::config::TestStep;
+use crate::config::{TestStep, TlsConfig};
 use crate::metrics::{LocalMetrics, SharedMetrics};
 use rand::{rngs::SmallRng, Rng, SeedableRng};
+use serde_json::{json, Value};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio::net::TcpStream;
+use tokio_rustls::rustls::crypto::ring;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName};
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+use tokio_rustls::TlsConnector;
 use tracing::error;
 
+/// 所有客户端共享的 TLS 设置
+#[derive(Clone)]
+pub struct TlsTarget {
+    connector: TlsConnector,
+    server_name: ServerName<'static>,
+}
+
+impl TlsTarget {
+    pub fn new(config: &TlsConfig, host: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut roots = RootCertStore::empty();
+        for cert in CertificateDer::pem_file_iter(&config.ca_path)? {
+            roots.add(cert?)?;
+        }
+        let client_config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let server_name = config.server_name.as_deref().unwrap_or(host);
+        Ok(TlsTarget {
+            connector: TlsConnector::from(Arc::new(client_config)),
+            server_name: ServerName::try_from(server_name.to_string())?,
+        })
+    }
+}
+
+// 服务器使用 4 字节大端长度前缀 + JSON 的分帧协议
+async fn send_frame<W: AsyncWrite + Unpin>(writer: &mut W, msg: &Value) -> std::io::Result<()> {
+    let payload = serde_json::to_vec(msg)?;
+    let mut buf = Vec::with_capacity(4 + payload.len());
+    buf.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    buf.extend_from_slice(&payload);
+    writer.write_all(&buf).await
+}
+
+async fn recv_frame<R: AsyncRead + Unpin>(reader: &mut R) -> std::io::Result<Value> {
+    let len = reader.read_u32().await? as usize;
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload).await?;
+    Ok(serde_json::from_slice(&payload)?)
+}
+
 pub async fn run_client(
     client_id: usize,
     host: String,
     port: u16,
+    tls: Option<TlsTarget>,
     step: TestStep,
     metrics: SharedMetrics,
 ) {
@@ -28,42 +77,47 @@ pub async fn run_client(
             return;
         }
     };
-    let (reader, mut writer) = stream.into_split();
-    let mut reader = BufReader::new(reader);
-    let mut line = String::new();
+
+    match tls {
+        None => run_session(stream, client_id, &step, &mut local_metrics).await,
+        Some(tls) => match tls.connector.connect(tls.server_name, stream).await {
+            Ok(stream) => run_session(stream, client_id, &step, &mut local_metrics).await,
+            Err(e) => {
+                error!("Client {} TLS handshake failed: {}", client_id, e);
+                local_metrics.login_failures += 1;
+            }
+        },
+    }
+
+    // 4. 循环结束，将本地数据合并到全局
+    metrics.merge(local_metrics);
+}
+
+// 在已经建立的连接（TCP 或 TLS）上登录并收发消息
+async fn run_session<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: S,
+    client_id: usize,
+    step: &TestStep,
+    local_metrics: &mut LocalMetrics,
+) {
+    let (mut reader, mut writer) = tokio::io::split(stream);
 
     // 1. 登录握手
     let username = format!("TestUser-{}", client_id);
+    let login = json!({ "type": "Login", "username": username });
 
-    if reader.read_line(&mut line).await.is_err() {
+    if send_frame(&mut writer, &login).await.is_err() {
         local_metrics.login_failures += 1;
-        metrics.merge(local_metrics);
         return;
     }
-    line.clear();
 
-    if writer
-        .write_all(format!("{}\n", username).as_bytes())
-        .await
-        .is_err()
-    {
-        local_metrics.login_failures += 1;
-        metrics.merge(local_metrics);
-        return;
-    }
-
-    if let Ok(bytes_read) = reader.read_line(&mut line).await {
-        if bytes_read == 0 || line.contains("已被占用") {
+    match recv_frame(&mut reader).await {
+        Ok(reply) if reply["type"] == "Welcome" => {}
+        _ => {
             local_metrics.login_failures += 1;
-            metrics.merge(local_metrics);
             return;
         }
-    } else {
-        local_metrics.login_failures += 1;
-        metrics.merge(local_metrics);
-        return;
     }
-    line.clear();
 
     // 2. 消息收发循环
     let start_time = Instant::now();
@@ -75,32 +129,26 @@ pub async fn run_client(
         let think_time = rng.gen_range(min_think..=max_think);
         tokio::time::sleep(Duration::from_millis(think_time)).await;
 
-        let msg = format!("hello from {}", client_id);
+        let msg = json!({ "type": "Broadcast", "content": format!("hello from {}", client_id) });
         let send_time = Instant::now();
 
-        if writer
-            .write_all(format!("{}\n", msg).as_bytes())
-            .await
-            .is_err()
-        {
+        if send_frame(&mut writer, &msg).await.is_err() {
             local_metrics.send_errors += 1;
             break;
         }
         local_metrics.messages_sent += 1;
 
-        match reader.read_line(&mut line).await {
-            Ok(0) => break,
+        match recv_frame(&mut reader).await {
             Ok(_) => {
                 let latency = send_time.elapsed().as_micros() as u64;
                 // 忽略记录失败的情况 (对于高精度直方图，失败概率极低)
                 let _ = local_metrics.latencies.record(latency);
                 local_metrics.messages_received += 1;
-                line.clear();
             }
             Err(_) => break,
         }
     }
 
-    // 3. 循环结束，将本地数据合并到全局
-    metrics.merge(local_metrics);
+    // 3. 主动退出，服务器不会为断线重连保留这个用户名，下一轮测试可以直接复用
+    let _ = send_frame(&mut writer, &json!({ "type": "Quit" })).await;
 }
@@ -11,10 +11,23 @@ pub struct TestStep {
     pub think_time_ms: [u64; 2],
 }
 
+/// 连接服务器的 TLS 端口时使用
+#[derive(Debug, Deserialize, Clone)]
+pub struct TlsConfig {
+    /// 信任的 CA 证书（PEM），用来校验服务器证书，本地测试时就是 gen_certs.sh 生成的 ca.pem
+    pub ca_path: String,
+    /// 校验服务器证书时使用的名字，不设置时使用 host
+    #[serde(default)]
+    pub server_name: Option<String>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct TestConfig {
     pub host: String,
     pub port: u16,
+    /// 设置后通过 TLS 连接
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
     pub steps: Vec<TestStep>,
 }
 
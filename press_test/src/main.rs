@@ -36,7 +36,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let config = load_config("config.toml")?;
     info!("--- 🚀 Starting Chat Server Performance Test ---");
     let target_server = format!("{}:{}", config.host, config.port);
-    info!("Target: {}\n", target_server);
+    let tls = match &config.tls {
+        Some(tls_config) => Some(client::TlsTarget::new(tls_config, &config.host)?),
+        None => None,
+    };
+    info!("Target: {} (TLS: {})\n", target_server, tls.is_some());
 
     // 创建一个向量来收集所有步骤的报告
     let mut step_reports: Vec<StepReport> = Vec::new();
@@ -56,11 +60,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         for i in 0..step.concurrency {
             let host = config.host.clone();
             let port = config.port;
+            let tls = tls.clone();
             let step_clone = step.clone();
             let metrics_clone = global_metrics.clone();
 
             let handle = tokio::spawn(async move {
-                client::run_client(i, host, port, step_clone, metrics_clone).await;
+                client::run_client(i, host, port, tls, step_clone, metrics_clone).await;
             });
             handles.push(handle);
         }
@@ -1 +1,3 @@
-cargo run -q --release
\ No newline at end of file
+cargo run --release -- --backend actor
+
+cargo run --release -- --backend mutex
\ No newline at end of file
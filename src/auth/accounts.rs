@@ -0,0 +1,403 @@
+// src/auth/accounts.rs
+
+// 注册账号：用户名 + argon2 密码哈希，保存在一个 JSON 文件里。
+//
+// 认证方式选择 accounts（见 authenticator.rs）时使用：可以注册账号，已注册的名字只能凭密码登录，
+// 是否还允许不带密码的访客由 allow_guests 决定。
+// 账号按用户名的规范化键（见 rules.rs）索引，所以 `Alice` 注册之后 `alice`、`аlice` 也都被占用。
+
+use super::authenticator::{ AuthFuture, Authenticator, Identity, LoginRequest };
+use super::rules::UsernameRules;
+use anyhow::{ Context, Result, anyhow };
+use argon2::password_hash::{ PasswordHash, PasswordHasher, PasswordVerifier, SaltString };
+use argon2::Argon2;
+use rand_core::OsRng;
+use serde::{ Deserialize, Serialize };
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{ Path, PathBuf };
+use std::sync::Mutex;
+use std::time::{ SystemTime, UNIX_EPOCH };
+use tokio::sync::Semaphore;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AccountConfig {
+    /// 账号文件路径，不存在时会在第一次注册时创建
+    pub path: PathBuf,
+    /// 是否还允许不带密码的访客登录
+    pub allow_guests: bool,
+    /// 密码最少字符数
+    pub min_password_length: usize,
+    /// 最多同时计算多少个 argon2 哈希（注册和登录）。每个约占 19 MiB 内存，超出的请求排队等待
+    pub max_concurrent_hashes: usize,
+}
+
+impl Default for AccountConfig {
+    fn default() -> Self {
+        AccountConfig {
+            path: PathBuf::from("accounts.json"),
+            allow_guests: true,
+            min_password_length: 8,
+            max_concurrent_hashes: 4,
+        }
+    }
+}
+
+/// 账号文件中的一条记录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Account {
+    username: String,
+    /// PHC 格式的 argon2 哈希（`$argon2id$v=19$...`），盐和参数都在里面
+    password_hash: String,
+    /// 注册时间（Unix 秒）
+    created_at: u64,
+}
+
+/// 账号文件的格式
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct AccountFile {
+    accounts: Vec<Account>,
+}
+
+/// 所有账号，启动时从文件加载，每次注册后整个文件重写一次
+#[derive(Debug)]
+pub struct AccountStore {
+    config: AccountConfig,
+    rules: UsernameRules,
+    /// 规范化键 -> 账号
+    accounts: Mutex<HashMap<String, Account>>,
+    /// 串行化文件写入，保证后写入的一定是更新的快照
+    save_lock: tokio::sync::Mutex<()>,
+    /// 限制同时进行的 argon2 计算，防止大量 /register、/login 占满阻塞线程池和内存
+    hashing: Semaphore,
+}
+
+impl AccountStore {
+    /// 按配置加载账号文件。`rules` 用来计算账号的规范化键
+    pub fn open(config: AccountConfig, rules: &UsernameRules) -> Result<Self> {
+        let mut accounts = HashMap::new();
+        if config.path.exists() {
+            let content = fs::read_to_string(&config.path)
+                .with_context(|| format!("failed to read account file {}", config.path.display()))?;
+            let file: AccountFile = serde_json::from_str(&content)
+                .with_context(|| format!("failed to parse account file {}", config.path.display()))?;
+            for account in file.accounts {
+                accounts.insert(rules.key(&account.username), account);
+            }
+        }
+        Ok(AccountStore {
+            hashing: Semaphore::new(config.max_concurrent_hashes.max(1)),
+            config,
+            rules: rules.clone(),
+            accounts: Mutex::new(accounts),
+            save_lock: tokio::sync::Mutex::new(()),
+        })
+    }
+
+    pub fn config(&self) -> &AccountConfig {
+        &self.config
+    }
+
+    /// 已注册的账号数
+    pub fn len(&self) -> usize {
+        self.accounts.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// `name`（或与它规范化键相同的名字）是否已经注册
+    pub fn is_registered(&self, name: &str) -> bool {
+        self.accounts.lock().unwrap().contains_key(&self.rules.key(name))
+    }
+
+    /// 注册新账号并写入文件。内层 `Err` 是回复给客户端的原因，外层 `Err` 是文件写入失败
+    pub async fn create(&self, name: &str, password: &str) -> Result<Result<(), String>> {
+        let min = self.config.min_password_length;
+        if password.chars().count() < min {
+            return Ok(Err(format!("Password must be at least {min} characters long.")));
+        }
+        let key = self.rules.key(name);
+        if let Some(existing) = self.existing(&key) {
+            return Ok(Err(format!("Username '{existing}' is already registered.")));
+        }
+
+        // argon2 故意很慢，放到阻塞线程池里算，不占用异步工作线程
+        let _permit = self.hashing.acquire().await?;
+        let password = password.to_string();
+        let password_hash = tokio::task::spawn_blocking(move || {
+            let salt = SaltString::generate(&mut OsRng);
+            Argon2::default()
+                .hash_password(password.as_bytes(), &salt)
+                .map(|hash| hash.to_string())
+                .map_err(|e| anyhow!("failed to hash password: {e}"))
+        }).await??;
+
+        let account = Account {
+            username: name.to_string(),
+            password_hash,
+            created_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs()),
+        };
+        {
+            let mut accounts = self.accounts.lock().unwrap();
+            // 哈希期间可能有人抢先注册了同一个键
+            if let Some(existing) = accounts.get(&key) {
+                return Ok(Err(format!("Username '{}' is already registered.", existing.username)));
+            }
+            accounts.insert(key.clone(), account);
+        }
+
+        if let Err(e) = self.save().await {
+            self.accounts.lock().unwrap().remove(&key);
+            return Err(e);
+        }
+        Ok(Ok(()))
+    }
+
+    /// 校验密码，成功时返回注册时的用户名（登录时输入的大小写、全角形式可能不同）
+    pub async fn verify(&self, name: &str, password: &str) -> Option<String> {
+        let account = self.accounts.lock().unwrap().get(&self.rules.key(name)).cloned()?;
+        let _permit = self.hashing.acquire().await.ok()?;
+        let password = password.to_string();
+        let valid = tokio::task::spawn_blocking(move || {
+            PasswordHash::new(&account.password_hash).is_ok_and(|hash| {
+                Argon2::default().verify_password(password.as_bytes(), &hash).is_ok()
+            })
+        }).await.unwrap_or(false);
+        valid.then_some(account.username)
+    }
+
+    fn existing(&self, key: &str) -> Option<String> {
+        self.accounts
+            .lock()
+            .unwrap()
+            .get(key)
+            .map(|account| account.username.clone())
+    }
+
+    /// 把当前所有账号写入文件
+    async fn save(&self) -> Result<()> {
+        let _guard = self.save_lock.lock().await;
+        let mut accounts: Vec<Account> = self.accounts.lock().unwrap().values().cloned().collect();
+        accounts.sort_by_key(|account| account.created_at);
+        let content = serde_json::to_string_pretty(&AccountFile { accounts })?;
+        let path = self.config.path.clone();
+        tokio::task::spawn_blocking(move || write_atomically(&path, &content)).await?
+    }
+}
+
+impl Authenticator for AccountStore {
+    fn name(&self) -> &'static str {
+        "accounts"
+    }
+
+    fn authenticate<'a>(&'a self, request: &'a LoginRequest) -> AuthFuture<'a> {
+        Box::pin(async move {
+            let username = &request.username;
+            Ok(match &request.password {
+                // 登录后用注册时的写法，不用这次输入的大小写 / 全角形式
+                Some(password) => match self.verify(username, password).await {
+                    Some(registered) => Ok(Identity::verified(registered)),
+                    None => Err("Invalid username or password.".to_string()),
+                },
+                None if self.is_registered(username) => Err(format!(
+                    "Username '{username}' is registered, log in with a password (/login <name> <password>)."
+                )),
+                None if !self.config.allow_guests => Err(
+                    "Guest access is disabled, please /register <name> <password> or /login <name> <password>."
+                        .to_string()
+                ),
+                None => Ok(Identity::guest(username.clone())),
+            })
+        })
+    }
+
+    fn register<'a>(&'a self, request: &'a LoginRequest) -> AuthFuture<'a> {
+        Box::pin(async move {
+            let Some(password) = &request.password else {
+                return Ok(Err("Please choose a password.".to_string()));
+            };
+            let created = self.create(&request.username, password).await?;
+            Ok(created.map(|()| Identity::verified(request.username.clone())))
+        })
+    }
+}
+
+/// 先写临时文件再改名，中途崩溃也不会留下半个文件。
+/// 文件里有密码哈希，在 unix 上只允许所有者读写（0600）
+pub(super) fn write_atomically(path: &Path, content: &str) -> Result<()> {
+    let tmp = path.with_extension("tmp");
+    // 上次崩溃留下的临时文件可能权限更宽，删掉后重新创建
+    let _ = fs::remove_file(&tmp);
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+        .open(&tmp)
+        .and_then(|mut file| {
+            file.write_all(content.as_bytes())?;
+            file.sync_all()
+        })
+        .with_context(|| format!("failed to write {}", tmp.display()))?;
+    fs::rename(&tmp, path).with_context(|| format!("failed to replace {}", path.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::peer::PeerAddr;
+    use std::sync::atomic::{ AtomicUsize, Ordering };
+
+    /// 每个测试用自己的账号文件，测试结束时删除
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new() -> Self {
+            static NEXT: AtomicUsize = AtomicUsize::new(0);
+            let n = NEXT.fetch_add(1, Ordering::Relaxed);
+            let name = format!("accounts-test-{}-{n}.json", std::process::id());
+            TempFile(std::env::temp_dir().join(name))
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn config(path: &Path, allow_guests: bool) -> AccountConfig {
+        AccountConfig { path: path.to_path_buf(), allow_guests, ..AccountConfig::default() }
+    }
+
+    fn store(path: &Path, allow_guests: bool) -> AccountStore {
+        AccountStore::open(config(path, allow_guests), &UsernameRules::default()).unwrap()
+    }
+
+    fn request(username: &str, password: Option<&str>) -> LoginRequest {
+        LoginRequest {
+            username: username.to_string(),
+            password: password.map(str::to_string),
+            addr: PeerAddr::Tcp("127.0.0.1:4000".parse().unwrap()),
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_short_password() {
+        let file = TempFile::new();
+        let store = store(&file.0, true);
+        let err = store.create("alice", "short").await.unwrap().unwrap_err();
+        assert_eq!(err, "Password must be at least 8 characters long.");
+        // 按字符数而不是字节数计算
+        assert!(store.create("alice", "密码密码密码密").await.unwrap().is_err());
+        assert!(store.is_empty());
+        assert!(!file.0.exists());
+    }
+
+    #[tokio::test]
+    async fn rejects_duplicate_and_lookalike_names() {
+        let file = TempFile::new();
+        let store = store(&file.0, true);
+        store.create("Alice", "password1").await.unwrap().unwrap();
+        assert!(store.is_registered("alice"));
+
+        // 西里尔字母 а、全角字母都与 Alice 的规范化键相同
+        for name in ["Alice", "ALICE", "аlice", "Ａｌｉｃｅ"] {
+            let err = store.create(name, "password2").await.unwrap().unwrap_err();
+            assert_eq!(err, "Username 'Alice' is already registered.", "{name}");
+        }
+        assert_eq!(store.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn verifies_password() {
+        let file = TempFile::new();
+        let store = store(&file.0, true);
+        store.create("Alice", "password1").await.unwrap().unwrap();
+
+        // 返回注册时的写法
+        assert_eq!(store.verify("alice", "password1").await.as_deref(), Some("Alice"));
+        assert_eq!(store.verify("alice", "password2").await, None);
+        assert_eq!(store.verify("bob", "password1").await, None);
+    }
+
+    #[tokio::test]
+    async fn reloads_saved_accounts() {
+        let file = TempFile::new();
+        store(&file.0, true).create("alice", "password1").await.unwrap().unwrap();
+
+        let reopened = store(&file.0, true);
+        assert_eq!(reopened.len(), 1);
+        assert_eq!(reopened.verify("ALICE", "password1").await.as_deref(), Some("alice"));
+        // 文件里不能有明文密码
+        assert!(!fs::read_to_string(&file.0).unwrap().contains("password1"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn account_file_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let file = TempFile::new();
+        store(&file.0, true).create("alice", "password1").await.unwrap().unwrap();
+        let mode = fs::metadata(&file.0).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[tokio::test]
+    async fn failed_save_rolls_back() {
+        // 目录不存在，写入一定失败
+        let dir = format!("accounts-test-missing-{}", std::process::id());
+        let store = store(&std::env::temp_dir().join(dir).join("accounts.json"), true);
+        assert!(store.create("alice", "password1").await.is_err());
+        // 没有写进文件的账号不能留在内存里，否则重启后就消失了
+        assert!(!store.is_registered("alice"));
+        assert_eq!(store.verify("alice", "password1").await, None);
+    }
+
+    #[tokio::test]
+    async fn guests_allowed() {
+        let file = TempFile::new();
+        let store = store(&file.0, true);
+        store.create("alice", "password1").await.unwrap().unwrap();
+
+        let guest = store.authenticate(&request("bob", None)).await.unwrap().unwrap();
+        assert_eq!(guest.username, "bob");
+        assert!(!guest.verified);
+        // 已注册的名字（包括同形写法）必须带密码
+        let err = store.authenticate(&request("ALICE", None)).await.unwrap().unwrap_err();
+        assert!(err.starts_with("Username 'ALICE' is registered"), "{err}");
+
+        let user = store.authenticate(&request("ALICE", Some("password1"))).await.unwrap().unwrap();
+        assert_eq!(user.username, "alice");
+        assert!(user.verified);
+        let wrong = request("alice", Some("nope-nope"));
+        let err = store.authenticate(&wrong).await.unwrap().unwrap_err();
+        assert_eq!(err, "Invalid username or password.");
+    }
+
+    #[tokio::test]
+    async fn guests_disabled() {
+        let file = TempFile::new();
+        let store = store(&file.0, false);
+        let err = store.authenticate(&request("bob", None)).await.unwrap().unwrap_err();
+        assert!(err.starts_with("Guest access is disabled"), "{err}");
+
+        // 注册不受影响，注册后凭密码登录
+        let identity = store.register(&request("bob", Some("password1"))).await.unwrap().unwrap();
+        assert!(identity.verified);
+        assert!(store.authenticate(&request("bob", Some("password1"))).await.unwrap().is_ok());
+        let err = store.register(&request("carol", None)).await.unwrap().unwrap_err();
+        assert_eq!(err, "Please choose a password.");
+    }
+}
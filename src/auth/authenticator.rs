@@ -0,0 +1,165 @@
+// src/auth/authenticator.rs
+
+// 可替换的身份认证：登录流程（收消息、清理和校验用户名、封禁检查、占用名字、在后端注册）由
+// username.rs 负责，"这个人是不是他声称的那个人" 交给 Authenticator 决定。
+//
+// 内置四种，由配置中的 [auth] method 选择：
+//   open       只要用户名合法就能登录，忽略密码
+//   accounts   账号文件 + 密码，可以 /register（见 accounts.rs）
+//   allowlist  只有名单里的用户名可以登录
+//   token      用 HS256 签名的 JWT 作为密码（见 token.rs）
+// 嵌入方可以实现自己的 Authenticator，通过 ChatServerBuilder::authenticator 接入，不需要改动连接处理。
+
+use super::moderation::Role;
+use super::rules::UsernameRules;
+use super::token::TokenConfig;
+use crate::connection::peer::PeerAddr;
+use anyhow::{ Result, anyhow };
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::str::FromStr;
+
+/// 一次登录或注册请求
+#[derive(Debug, Clone)]
+pub struct LoginRequest {
+    /// 已经清理过控制字符并通过用户名规则校验
+    pub username: String,
+    /// 密码或令牌，客户端没有提供时为 `None`
+    pub password: Option<String>,
+    pub addr: PeerAddr,
+}
+
+/// 认证通过的身份
+#[derive(Debug, Clone)]
+pub struct Identity {
+    /// 登录后使用的用户名，可以与请求中的写法不同（例如注册时的大小写）
+    pub username: String,
+    pub role: Role,
+    /// 身份是否经过验证（密码、令牌等）。只有验证过的用户才会按 [moderation] 名单成为管理员或版主
+    pub verified: bool,
+}
+
+impl Identity {
+    /// 验证过身份的用户
+    pub fn verified(username: impl Into<String>) -> Self {
+        Identity { username: username.into(), role: Role::Member, verified: true }
+    }
+
+    /// 没有验证身份、但也不算访客的用户
+    pub fn unverified(username: impl Into<String>) -> Self {
+        Identity { username: username.into(), role: Role::Member, verified: false }
+    }
+
+    /// 访客
+    pub fn guest(username: impl Into<String>) -> Self {
+        Identity { username: username.into(), role: Role::Guest, verified: false }
+    }
+
+    /// 指定角色，例如令牌里带的角色
+    pub fn with_role(mut self, role: Role) -> Self {
+        self.role = role;
+        self
+    }
+}
+
+/// `Authenticator` 返回的 future。
+/// 内层 `Err` 是回复给客户端的原因，客户端可以重试；外层 `Err` 表示认证服务本身出了问题。
+pub type AuthFuture<'a> = Pin<Box<dyn Future<Output = Result<Result<Identity, String>>> + Send + 'a>>;
+
+/// 身份认证方式
+pub trait Authenticator: fmt::Debug + Send + Sync + 'static {
+    /// 名称，用于日志
+    fn name(&self) -> &'static str;
+
+    /// 校验 `Login` 请求
+    fn authenticate<'a>(&'a self, request: &'a LoginRequest) -> AuthFuture<'a>;
+
+    /// 注册新账号并登录。调用前用户名已经被这个连接占用；默认不支持注册
+    fn register<'a>(&'a self, _request: &'a LoginRequest) -> AuthFuture<'a> {
+        Box::pin(async { Ok(Err("Registration is not supported on this server.".to_string())) })
+    }
+}
+
+/// 只要用户名合法就能登录，谁都可以使用任何名字
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OpenAuthenticator;
+
+impl Authenticator for OpenAuthenticator {
+    fn name(&self) -> &'static str {
+        "open"
+    }
+
+    fn authenticate<'a>(&'a self, request: &'a LoginRequest) -> AuthFuture<'a> {
+        Box::pin(async move { Ok(Ok(Identity::unverified(request.username.clone()))) })
+    }
+}
+
+/// 只有名单中的用户名可以登录（按规范化键比较），不校验密码
+#[derive(Debug, Default)]
+pub struct AllowlistAuthenticator {
+    rules: UsernameRules,
+    keys: HashSet<String>,
+}
+
+impl AllowlistAuthenticator {
+    pub fn new(names: &[String], rules: &UsernameRules) -> Self {
+        AllowlistAuthenticator {
+            rules: rules.clone(),
+            keys: names.iter().map(|name| rules.key(name)).collect(),
+        }
+    }
+}
+
+impl Authenticator for AllowlistAuthenticator {
+    fn name(&self) -> &'static str {
+        "allowlist"
+    }
+
+    fn authenticate<'a>(&'a self, request: &'a LoginRequest) -> AuthFuture<'a> {
+        Box::pin(async move {
+            let username = &request.username;
+            if !self.keys.contains(&self.rules.key(username)) {
+                return Ok(Err(format!("Username '{username}' is not allowed on this server.")));
+            }
+            Ok(Ok(Identity::unverified(username.clone())))
+        })
+    }
+}
+
+/// 内置的认证方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthMethod {
+    #[default]
+    Open,
+    Accounts,
+    Allowlist,
+    Token,
+}
+
+impl FromStr for AuthMethod {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "open" => Ok(AuthMethod::Open),
+            "accounts" => Ok(AuthMethod::Accounts),
+            "allowlist" => Ok(AuthMethod::Allowlist),
+            "token" => Ok(AuthMethod::Token),
+            _ => Err(anyhow!("unknown auth method '{s}' (expected open, accounts, allowlist or token)")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct AuthConfig {
+    pub method: AuthMethod,
+    /// allowlist 方式下允许登录的用户名
+    pub allowlist: Vec<String>,
+    /// token 方式的参数
+    pub token: TokenConfig,
+}
@@ -1 +1,6 @@
-pub mod username;
\ No newline at end of file
+pub mod accounts;
+pub mod authenticator;
+pub mod moderation;
+pub mod rules;
+pub mod token;
+pub mod username;
@@ -0,0 +1,513 @@
+// src/auth/moderation.rs
+
+// 角色和管理：每个登录的用户都有一个角色，管理命令（/kick、/mute、/ban 等）按角色检查权限。
+//
+// 角色从高到低是 admin、moderator、member、guest。admins / moderators 名单写在配置里，只对凭密码
+// 登录的用户生效：不开启账号模式时谁都可以自称任何名字，所有人都是 member；开启后不带密码登录的是 guest。
+//
+// 禁言只保存在内存里，按用户名的规范化键记录，断线重连也不会解除；
+// 封禁（按用户名或 IP）保存在一个 JSON 文件里，登录时检查。
+
+use super::accounts::write_atomically;
+use super::rules::UsernameRules;
+use crate::connection::rate_limit::MAX_MUTE_SECS;
+use crate::protocol::ServerMessage;
+use anyhow::{ Context, Result };
+use serde::{ Deserialize, Serialize };
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::fmt;
+use std::fs;
+use std::hash::Hash;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{ Duration, Instant, SystemTime, UNIX_EPOCH };
+
+/// 用户的角色，可以直接比较高低
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// 开启账号模式后不带密码登录的用户
+    Guest,
+    /// 普通用户
+    Member,
+    /// 可以踢人、禁言
+    Moderator,
+    /// 还可以封禁用户名和 IP
+    Admin,
+}
+
+impl Role {
+    /// 能否对角色为 `target` 的用户使用管理命令：只能管理角色比自己低的用户
+    pub fn outranks(self, target: Role) -> bool {
+        self > target
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Role::Guest => "guest",
+            Role::Member => "member",
+            Role::Moderator => "moderator",
+            Role::Admin => "admin",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ModerationConfig {
+    /// 管理员的用户名
+    pub admins: Vec<String>,
+    /// 版主的用户名
+    pub moderators: Vec<String>,
+    /// 封禁名单文件路径，不存在时会在第一次封禁时创建
+    pub bans_path: PathBuf,
+}
+
+impl Default for ModerationConfig {
+    fn default() -> Self {
+        ModerationConfig {
+            admins: Vec::new(),
+            moderators: Vec::new(),
+            bans_path: PathBuf::from("bans.json"),
+        }
+    }
+}
+
+/// 封禁的对象
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BanTarget {
+    User(String),
+    Ip(IpAddr),
+}
+
+impl BanTarget {
+    /// 能解析成 IP 地址的按 IP，否则按用户名
+    pub fn parse(arg: &str) -> Self {
+        match arg.parse::<IpAddr>() {
+            Ok(ip) => BanTarget::Ip(ip.to_canonical()),
+            Err(_) => BanTarget::User(arg.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for BanTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BanTarget::User(name) => f.write_str(name),
+            BanTarget::Ip(ip) => write!(f, "{ip}"),
+        }
+    }
+}
+
+/// 封禁名单中的一条记录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Ban {
+    /// 用户名或 IP 地址
+    pub target: String,
+    /// 执行封禁的管理员
+    pub by: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// 封禁时间（Unix 秒）
+    pub created_at: u64,
+}
+
+impl Ban {
+    /// 被拒绝登录时发给客户端的说明
+    pub fn notice(&self) -> String {
+        match &self.reason {
+            Some(reason) => format!("You are banned from this server: {reason}"),
+            None => "You are banned from this server.".to_string(),
+        }
+    }
+}
+
+/// 封禁名单文件的格式
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct BanFile {
+    #[serde(default)]
+    users: Vec<Ban>,
+    #[serde(default)]
+    ips: Vec<Ban>,
+}
+
+#[derive(Debug, Default)]
+struct Bans {
+    /// 规范化键 -> 封禁记录
+    users: HashMap<String, Ban>,
+    ips: HashMap<IpAddr, Ban>,
+}
+
+/// 角色名单、封禁名单和禁言状态，所有连接共享
+#[derive(Debug, Default)]
+pub struct Moderation {
+    config: ModerationConfig,
+    rules: UsernameRules,
+    /// 规范化键 -> 配置中的角色
+    roles: HashMap<String, Role>,
+    bans: Mutex<Bans>,
+    /// 规范化键 -> 禁言到期时间
+    mutes: Mutex<HashMap<String, Instant>>,
+    /// 串行化文件写入，保证后写入的一定是更新的快照
+    save_lock: tokio::sync::Mutex<()>,
+}
+
+impl Moderation {
+    /// 按配置加载封禁名单。`rules` 用来计算用户名的规范化键
+    pub fn open(config: ModerationConfig, rules: &UsernameRules) -> Result<Self> {
+        let mut bans = Bans::default();
+        if config.bans_path.exists() {
+            let path = config.bans_path.display();
+            let content = fs::read_to_string(&config.bans_path)
+                .with_context(|| format!("failed to read ban list {path}"))?;
+            let file: BanFile = serde_json::from_str(&content)
+                .with_context(|| format!("failed to parse ban list {path}"))?;
+            for ban in file.users {
+                bans.users.insert(rules.key(&ban.target), ban);
+            }
+            for ban in file.ips {
+                let ip = ban.target
+                    .parse::<IpAddr>()
+                    .with_context(|| format!("invalid IP address '{}' in {path}", ban.target))?;
+                // 与 BanTarget::parse 一样，`::ffff:1.2.3.4` 按 `1.2.3.4` 记录，才能匹配 PeerAddr::ip()
+                bans.ips.insert(ip.to_canonical(), ban);
+            }
+        }
+
+        // 同时出现在两个名单里的按 admin 算
+        let mut roles = HashMap::new();
+        for name in &config.moderators {
+            roles.insert(rules.key(name), Role::Moderator);
+        }
+        for name in &config.admins {
+            roles.insert(rules.key(name), Role::Admin);
+        }
+
+        Ok(Moderation {
+            config,
+            rules: rules.clone(),
+            roles,
+            bans: Mutex::new(bans),
+            mutes: Mutex::default(),
+            save_lock: tokio::sync::Mutex::new(()),
+        })
+    }
+
+    pub fn config(&self) -> &ModerationConfig {
+        &self.config
+    }
+
+    pub fn rules(&self) -> &UsernameRules {
+        &self.rules
+    }
+
+    /// 凭密码登录的 `name` 的角色：在管理员 / 版主名单里的按名单，否则是 member
+    pub fn role_of(&self, name: &str) -> Role {
+        self.roles.get(&self.rules.key(name)).copied().unwrap_or(Role::Member)
+    }
+
+    /// 名单里是否配置了管理员或版主
+    pub fn has_staff(&self) -> bool {
+        !self.roles.is_empty()
+    }
+
+    /// 封禁名单的条目数
+    pub fn ban_count(&self) -> usize {
+        let bans = self.bans.lock().unwrap();
+        bans.users.len() + bans.ips.len()
+    }
+
+    /// `name`（或与它规范化键相同的名字）被封禁时返回封禁记录
+    pub fn user_ban(&self, name: &str) -> Option<Ban> {
+        self.bans.lock().unwrap().users.get(&self.rules.key(name)).cloned()
+    }
+
+    /// `ip` 被封禁时返回封禁记录
+    pub fn ip_ban(&self, ip: IpAddr) -> Option<Ban> {
+        self.bans.lock().unwrap().ips.get(&ip).cloned()
+    }
+
+    /// 全部封禁记录，先用户名后 IP，各自按时间排序
+    pub fn bans(&self) -> Vec<Ban> {
+        let bans = self.bans.lock().unwrap();
+        let mut users: Vec<Ban> = bans.users.values().cloned().collect();
+        let mut ips: Vec<Ban> = bans.ips.values().cloned().collect();
+        users.sort_by_key(|ban| ban.created_at);
+        ips.sort_by_key(|ban| ban.created_at);
+        users.extend(ips);
+        users
+    }
+
+    /// 封禁并写入文件，已经封禁过时返回 `Ok(false)`。写入失败时撤销这次封禁
+    pub async fn ban(&self, target: &BanTarget, by: &str, reason: Option<&str>) -> Result<bool> {
+        let ban = Ban {
+            target: target.to_string(),
+            by: by.to_string(),
+            reason: reason.map(str::to_string),
+            created_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs()),
+        };
+        {
+            let mut bans = self.bans.lock().unwrap();
+            let added = match target {
+                BanTarget::User(name) => insert_new(&mut bans.users, self.rules.key(name), ban),
+                BanTarget::Ip(ip) => insert_new(&mut bans.ips, *ip, ban),
+            };
+            if !added {
+                return Ok(false);
+            }
+        }
+
+        if let Err(e) = self.save().await {
+            self.remove(target);
+            return Err(e);
+        }
+        Ok(true)
+    }
+
+    /// 解除封禁并写入文件，没有封禁时返回 `Ok(false)`。写入失败时恢复这条封禁
+    pub async fn unban(&self, target: &BanTarget) -> Result<bool> {
+        let Some(ban) = self.remove(target) else {
+            return Ok(false);
+        };
+        if let Err(e) = self.save().await {
+            let mut bans = self.bans.lock().unwrap();
+            match target {
+                BanTarget::User(name) => bans.users.insert(self.rules.key(name), ban),
+                BanTarget::Ip(ip) => bans.ips.insert(*ip, ban),
+            };
+            return Err(e);
+        }
+        Ok(true)
+    }
+
+    /// 禁言 `name` 一段时间，已经禁言时重新计时。到期时间超出 Instant 的范围时返回 false
+    pub fn mute(&self, name: &str, duration: Duration) -> bool {
+        let now = Instant::now();
+        // 在加锁之前计算，溢出时不会在持有锁的时候 panic
+        let Some(until) = now.checked_add(duration) else {
+            return false;
+        };
+        let mut mutes = self.mutes.lock().unwrap();
+        // 顺便清掉已经到期、本人又一直没再发言的记录
+        mutes.retain(|_, until| *until > now);
+        mutes.insert(self.rules.key(name), until);
+        true
+    }
+
+    /// 解除禁言，没有被禁言时返回 false
+    pub fn unmute(&self, name: &str) -> bool {
+        let now = Instant::now();
+        self.mutes
+            .lock()
+            .unwrap()
+            .remove(&self.rules.key(name))
+            .is_some_and(|until| until > now)
+    }
+
+    /// `name` 被禁言时返回剩余时间
+    pub fn muted_for(&self, name: &str) -> Option<Duration> {
+        let mut mutes = self.mutes.lock().unwrap();
+        if mutes.is_empty() {
+            return None;
+        }
+        let key = self.rules.key(name);
+        let remaining = mutes.get(&key)?.checked_duration_since(Instant::now());
+        if remaining.is_none() {
+            mutes.remove(&key);
+        }
+        remaining
+    }
+
+    fn remove(&self, target: &BanTarget) -> Option<Ban> {
+        let mut bans = self.bans.lock().unwrap();
+        match target {
+            BanTarget::User(name) => bans.users.remove(&self.rules.key(name)),
+            BanTarget::Ip(ip) => bans.ips.remove(ip),
+        }
+    }
+
+    /// 把当前封禁名单写入文件
+    async fn save(&self) -> Result<()> {
+        let _guard = self.save_lock.lock().await;
+        let content = {
+            let bans = self.bans.lock().unwrap();
+            let mut file = BanFile {
+                users: bans.users.values().cloned().collect(),
+                ips: bans.ips.values().cloned().collect(),
+            };
+            file.users.sort_by_key(|ban| ban.created_at);
+            file.ips.sort_by_key(|ban| ban.created_at);
+            serde_json::to_string_pretty(&file)?
+        };
+        let path = self.config.bans_path.clone();
+        tokio::task::spawn_blocking(move || write_atomically(&path, &content)).await?
+    }
+}
+
+/// 键不存在时插入并返回 true，已存在时保留旧值
+fn insert_new<K: Eq + Hash>(map: &mut HashMap<K, Ban>, key: K, ban: Ban) -> bool {
+    match map.entry(key) {
+        Entry::Occupied(_) => false,
+        Entry::Vacant(entry) => {
+            entry.insert(ban);
+            true
+        }
+    }
+}
+
+/// 被禁言的用户发言时的回复
+pub fn muted_notice(remaining: Duration) -> ServerMessage {
+    ServerMessage::error(format!("You are muted for another {}.", format_duration(remaining)))
+}
+
+/// 解析 `/mute` 的时长：`90`（秒）、`90s`、`15m`、`2h`、`1d`，不能为 0，也不能超过 365 天
+pub fn parse_duration(arg: &str) -> Option<Duration> {
+    let (number, unit) = match arg.find(|c: char| !c.is_ascii_digit()) {
+        Some(split) => arg.split_at(split),
+        None => (arg, "s"),
+    };
+    let scale = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return None,
+    };
+    let secs = number.parse::<u64>().ok()?.checked_mul(scale)?;
+    (1..=MAX_MUTE_SECS).contains(&secs).then(|| Duration::from_secs(secs))
+}
+
+/// 把时长写成 `1h30m`、`45s` 这样的形式，不足一秒的部分向上取整
+pub fn format_duration(duration: Duration) -> String {
+    let mut secs = duration.as_secs() + u64::from(duration.subsec_nanos() > 0);
+    let mut text = String::new();
+    for (unit, scale) in [("d", 24 * 60 * 60), ("h", 60 * 60), ("m", 60)] {
+        if secs >= scale {
+            text.push_str(&format!("{}{unit}", secs / scale));
+            secs %= scale;
+        }
+    }
+    if secs > 0 || text.is_empty() {
+        text.push_str(&format!("{secs}s"));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("moderation-test-{}-{name}.json", std::process::id()))
+    }
+
+        fn moderation() -> Moderation {
+        let config = ModerationConfig {
+            bans_path: temp_path("unused"),
+            ..ModerationConfig::default()
+        };
+        Moderation::open(config, &UsernameRules::default()).unwrap()
+    }
+
+    #[test]
+    fn parse_duration_units() {
+        assert_eq!(parse_duration("90"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_duration("90s"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_duration("15m"), Some(Duration::from_secs(15 * 60)));
+        assert_eq!(parse_duration("2h"), Some(Duration::from_secs(2 * 60 * 60)));
+        assert_eq!(parse_duration("1d"), Some(Duration::from_secs(24 * 60 * 60)));
+    }
+
+    #[test]
+    fn parse_duration_rejects_invalid() {
+        for arg in ["", "0", "0s", "s", "10x", "1.5h", "-5m", "1h30m", "10 m"] {
+            assert_eq!(parse_duration(arg), None, "{arg:?}");
+        }
+    }
+
+    #[test]
+    fn parse_duration_caps_at_a_year() {
+        assert_eq!(parse_duration("365d"), Some(Duration::from_secs(MAX_MUTE_SECS)));
+        assert_eq!(parse_duration("366d"), None);
+        assert_eq!(parse_duration(&(MAX_MUTE_SECS + 1).to_string()), None);
+        // 乘法溢出和超出 u64 的数字同样拒绝
+        assert_eq!(parse_duration(&format!("{}d", u64::MAX / 2)), None);
+        assert_eq!(parse_duration("99999999999999999999999"), None);
+    }
+
+    #[test]
+    fn format_duration_units() {
+        assert_eq!(format_duration(Duration::ZERO), "0s");
+        assert_eq!(format_duration(Duration::from_secs(45)), "45s");
+        assert_eq!(format_duration(Duration::from_secs(60)), "1m");
+        assert_eq!(format_duration(Duration::from_secs(90 * 60)), "1h30m");
+        assert_eq!(format_duration(Duration::from_secs(24 * 60 * 60 + 5)), "1d5s");
+        // 不足一秒向上取整
+        assert_eq!(format_duration(Duration::from_millis(1500)), "2s");
+        assert_eq!(format_duration(Duration::from_millis(1)), "1s");
+    }
+
+    #[test]
+    fn format_round_trips_parse() {
+        for arg in ["45s", "10m", "2h", "1d", "365d"] {
+            assert_eq!(format_duration(parse_duration(arg).unwrap()), arg);
+        }
+    }
+
+    #[test]
+    fn mute_uses_normalized_key() {
+        let moderation = moderation();
+        assert!(moderation.mute("Alice", Duration::from_secs(60)));
+        assert!(moderation.muted_for("ALICE").is_some_and(|left| left <= Duration::from_secs(60)));
+        assert!(moderation.muted_for("bob").is_none());
+
+        assert!(moderation.unmute("alice"));
+        assert!(moderation.muted_for("Alice").is_none());
+        assert!(!moderation.unmute("alice"));
+    }
+
+    #[test]
+    fn mute_overflow_is_rejected_without_poisoning() {
+        let moderation = moderation();
+        assert!(moderation.mute("bob", Duration::from_secs(60)));
+
+        assert!(!moderation.mute("alice", Duration::MAX));
+        assert!(moderation.muted_for("alice").is_none());
+        // 锁没有中毒，其他人的禁言照常生效
+        assert!(!moderation.mutes.is_poisoned());
+        assert!(moderation.muted_for("bob").is_some());
+        assert!(moderation.mute("alice", Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn parse_ban_target() {
+        assert_eq!(BanTarget::parse("alice"), BanTarget::User("alice".to_string()));
+        assert_eq!(BanTarget::parse("10.0.0.1"), BanTarget::Ip([10, 0, 0, 1].into()));
+        assert_eq!(BanTarget::parse("::ffff:10.0.0.1"), BanTarget::Ip([10, 0, 0, 1].into()));
+        assert_eq!(BanTarget::parse("::1"), BanTarget::Ip("::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn loaded_ip_bans_are_canonical() {
+        let path = temp_path("mapped");
+        let content = r#"{
+            "users": [{ "target": "Alice", "by": "boss", "created_at": 1 }],
+            "ips": [{ "target": "::ffff:1.2.3.4", "by": "boss", "created_at": 2 }]
+        }"#;
+        fs::write(&path, content).unwrap();
+        let config = ModerationConfig { bans_path: path.clone(), ..ModerationConfig::default() };
+        let moderation = Moderation::open(config, &UsernameRules::default());
+        fs::remove_file(&path).unwrap();
+        let moderation = moderation.unwrap();
+
+        assert!(moderation.ip_ban([1, 2, 3, 4].into()).is_some());
+        assert!(moderation.user_ban("ALICE").is_some());
+        assert_eq!(moderation.ban_count(), 2);
+    }
+}
@@ -0,0 +1,297 @@
+// src/auth/rules.rs
+
+// 用户名规则：长度、允许的字符、保留名，以及判断两个用户名是否 “看起来一样” 的规范化键。
+//
+// 用户名先做 NFKC 规范化（全角 `ａｌｉｃｅ` -> `alice`），注册的是规范化后的形式；
+// 唯一性按规范化键判断：转小写后再取 Unicode 同形字骨架 (UTS #39 skeleton)，
+// 所以 `Alice`、`ALICE` 和用西里尔字母 `а` 拼出的 `аlice` 都算同一个名字。
+
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::{ Arc, Mutex };
+use unicode_normalization::UnicodeNormalization;
+use unicode_security::skeleton;
+
+/// 用户名中允许出现的一类字符
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CharClass {
+    /// 任意语言的字母（包括汉字）
+    Letters,
+    /// 只允许 ASCII 字母
+    AsciiLetters,
+    /// ASCII 数字
+    Digits,
+}
+
+impl CharClass {
+    fn contains(self, c: char) -> bool {
+        match self {
+            CharClass::Letters => c.is_alphabetic(),
+            CharClass::AsciiLetters => c.is_ascii_alphabetic(),
+            CharClass::Digits => c.is_ascii_digit(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct UsernameRules {
+    /// 最少字符数（按 Unicode 字符计，不是字节）
+    pub min_length: usize,
+    /// 最多字符数
+    pub max_length: usize,
+    /// 允许的字符类别
+    pub allowed: Vec<CharClass>,
+    /// 除字符类别之外额外允许的字符
+    pub extra_chars: String,
+    /// 是否先做 NFKC 规范化
+    pub normalize: bool,
+    /// 唯一性是否忽略大小写
+    pub case_insensitive: bool,
+    /// 是否把同形字（如西里尔字母 `а` 和拉丁字母 `a`）视为同一个字符
+    pub confusables: bool,
+    /// 不能使用的名字，按规范化键比较，所以 `ADMIN`、`аdmin` 也不能用
+    pub reserved: Vec<String>,
+}
+
+impl Default for UsernameRules {
+    fn default() -> Self {
+        UsernameRules {
+            min_length: 2,
+            max_length: 32,
+            allowed: vec![CharClass::Letters, CharClass::Digits],
+            extra_chars: "_-.".to_string(),
+            normalize: true,
+            case_insensitive: true,
+            confusables: true,
+            reserved: ["server", "admin", "administrator", "root", "system", "moderator"]
+                .map(String::from)
+                .to_vec(),
+        }
+    }
+}
+
+impl UsernameRules {
+    /// 规范化并校验用户名，返回要注册的名字；不合法时返回回复给客户端的原因
+    pub fn validate(&self, raw: &str) -> Result<String, String> {
+        let name: String = if self.normalize {
+            raw.trim().nfkc().collect()
+        } else {
+            raw.trim().to_string()
+        };
+
+        if name.is_empty() {
+            return Err("Username cannot be empty.".to_string());
+        }
+        let length = name.chars().count();
+        if length < self.min_length || length > self.max_length {
+            return Err(
+                format!(
+                    "Username must be between {} and {} characters long.",
+                    self.min_length,
+                    self.max_length
+                )
+            );
+        }
+        if let Some(c) = name.chars().find(|c| !self.is_allowed(*c)) {
+            return Err(format!("Username cannot contain '{}'.", c.escape_default()));
+        }
+
+        let key = self.key(&name);
+        if self.reserved.iter().any(|reserved| self.key(reserved) == key) {
+            return Err(format!("Username '{name}' is reserved."));
+        }
+        Ok(name)
+    }
+
+    /// 规范化键：键相同的两个用户名不能同时在线
+    pub fn key(&self, name: &str) -> String {
+        let mut key: String = if self.normalize { name.nfkc().collect() } else { name.to_string() };
+        if self.case_insensitive {
+            key = key.to_lowercase();
+        }
+        if self.confusables {
+            key = skeleton(&key).collect();
+            // 骨架的原型字符不一定是小写，再统一一次大小写
+            if self.case_insensitive {
+                key = key.to_lowercase();
+            }
+        }
+        key
+    }
+
+    fn is_allowed(&self, c: char) -> bool {
+        self.allowed.iter().any(|class| class.contains(c)) || self.extra_chars.contains(c)
+    }
+}
+
+/// 在线用户名的规范化键登记表，所有连接共享。
+/// 后端只保证用户名本身不重复，这里保证 “看起来一样” 的名字也不会同时在线
+#[derive(Debug, Default)]
+pub struct UsernameRegistry {
+    rules: UsernameRules,
+    /// 规范化键 -> 正在使用它的用户名
+    names: Mutex<HashMap<String, String>>,
+}
+
+impl UsernameRegistry {
+    pub fn new(rules: UsernameRules) -> Self {
+        UsernameRegistry { rules, names: Mutex::default() }
+    }
+
+    pub fn rules(&self) -> &UsernameRules {
+        &self.rules
+    }
+
+    /// 正在使用与 `name` 规范化键相同的名字的用户，例如 `ALICE` 找到在线的 `alice`
+    pub fn holder(&self, name: &str) -> Option<String> {
+        self.names.lock().unwrap().get(&self.rules.key(name)).cloned()
+    }
+
+    /// 把消息的收件人换成在线用户的实际用户名；没有人占用时原样返回，由后端回复“不在线”
+    pub fn resolve(&self, name: &str) -> String {
+        self.holder(name).unwrap_or_else(|| name.to_string())
+    }
+
+    /// 占用 `name` 的规范化键；已被占用时返回占用者的用户名。
+    /// 返回的 NameReservation 被 drop 时释放
+    pub fn reserve(self: &Arc<Self>, name: &str) -> Result<NameReservation, String> {
+        let key = self.rules.key(name);
+        let mut names = self.names.lock().unwrap();
+        if let Some(existing) = names.get(&key) {
+            return Err(existing.clone());
+        }
+        names.insert(key.clone(), name.to_string());
+        Ok(NameReservation { registry: self.clone(), key })
+    }
+}
+
+/// 占用中的用户名，连接结束（从后端注销）后 drop
+#[derive(Debug)]
+pub struct NameReservation {
+    registry: Arc<UsernameRegistry>,
+    key: String,
+}
+
+impl Drop for NameReservation {
+    fn drop(&mut self) {
+        self.registry.names.lock().unwrap().remove(&self.key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookalike_names_share_a_key() {
+        let rules = UsernameRules::default();
+        let key = rules.key("alice");
+        // 大小写、全角字符、西里尔字母 а (U+0430) 和 е (U+0435)
+        for name in ["Alice", "ALICE", "ａｌｉｃｅ", "\u{430}lice", "ALIC\u{415}", "\u{430}lic\u{435}"] {
+            assert_eq!(rules.key(name), key, "{name:?}");
+        }
+        assert_ne!(rules.key("alicia"), key);
+        // 同形字骨架中 `rn` 和 `m` 相同
+        assert_eq!(rules.key("rnoon"), rules.key("moon"));
+    }
+
+    #[test]
+    fn lookalike_names_cannot_be_online_together() {
+        let registry = Arc::new(UsernameRegistry::new(UsernameRules::default()));
+        let alice = registry.reserve("alice").unwrap();
+        assert_eq!(registry.reserve("\u{430}lice").unwrap_err(), "alice");
+        assert_eq!(registry.reserve("ALICE").unwrap_err(), "alice");
+        assert!(registry.reserve("bob").is_ok());
+
+        // 释放后可以再用
+        drop(alice);
+        assert!(registry.reserve("\u{430}lice").is_ok());
+    }
+
+    #[test]
+    fn concurrent_reservations_of_lookalikes() {
+        let registry = Arc::new(UsernameRegistry::new(UsernameRules::default()));
+        for _ in 0..50 {
+            let barrier = Arc::new(std::sync::Barrier::new(2));
+            let attempts: Vec<_> = ["alice", "ALICE"]
+                .into_iter()
+                .map(|name| {
+                    let (registry, barrier) = (registry.clone(), barrier.clone());
+                    std::thread::spawn(move || {
+                        barrier.wait();
+                        registry.reserve(name)
+                    })
+                })
+                .collect();
+            let results: Vec<_> = attempts.into_iter().map(|t| t.join().unwrap()).collect();
+            // 只有一个拿到，另一个看到的是拿到的那个名字
+            let (won, lost): (Vec<_>, Vec<_>) = results.into_iter().partition(Result::is_ok);
+            assert_eq!((won.len(), lost.len()), (1, 1));
+            let winner = registry.holder("alice").unwrap();
+            assert_eq!(lost[0].as_ref().unwrap_err(), &winner);
+        }
+    }
+
+    #[test]
+    fn resolves_lookalike_names() {
+        let registry = Arc::new(UsernameRegistry::new(UsernameRules::default()));
+        let _alice = registry.reserve("alice").unwrap();
+        assert_eq!(registry.resolve("ALICE"), "alice");
+        assert_eq!(registry.resolve("ａｌｉｃｅ"), "alice");
+        assert_eq!(registry.resolve("Bob"), "Bob");
+    }
+
+    #[test]
+    fn validate_normalizes_with_nfkc() {
+        let rules = UsernameRules::default();
+        assert_eq!(rules.validate("  ａｌｉｃｅ  ").unwrap(), "alice");
+        // 大小写保留，只在比较时忽略
+        assert_eq!(rules.validate("Alice").unwrap(), "Alice");
+        // ﬁ 连字拆成 f + i
+        assert_eq!(rules.validate("\u{fb01}sh").unwrap(), "fish");
+        assert_eq!(rules.validate("小明").unwrap(), "小明");
+    }
+
+    #[test]
+    fn rejects_reserved_names_and_lookalikes() {
+        let rules = UsernameRules::default();
+        for name in ["admin", "ADMIN", "\u{430}dmin", "ａｄｍｉｎ", "Server", "r\u{43e}\u{43e}t"] {
+            let err = rules.validate(name).unwrap_err();
+            assert!(err.contains("reserved"), "{name:?}: {err}");
+        }
+        assert!(rules.validate("admins").is_ok());
+    }
+
+    #[test]
+    fn rejects_invalid_names() {
+        let rules = UsernameRules::default();
+        for name in ["", "   ", "a", &"x".repeat(33), "bob smith", "bob!", "tab\there", "emoji😀"] {
+            assert!(rules.validate(name).is_err(), "{name:?}");
+        }
+        // 长度按字符计，不按字节
+        assert!(rules.validate(&"名".repeat(32)).is_ok());
+        assert!(rules.validate("a_b-c.d").is_ok());
+    }
+
+    #[test]
+    fn respects_configured_rules() {
+        let rules = UsernameRules {
+            allowed: vec![CharClass::AsciiLetters],
+            extra_chars: String::new(),
+            normalize: false,
+            case_insensitive: false,
+            confusables: false,
+            reserved: vec!["Guest".to_string()],
+            ..UsernameRules::default()
+        };
+        assert!(rules.validate("小明").is_err());
+        assert!(rules.validate("bob1").is_err());
+        assert!(rules.validate("ａｌｉｃｅ").is_err());
+        assert!(rules.validate("Guest").is_err());
+        assert!(rules.validate("guest").is_ok());
+        assert_ne!(rules.key("Alice"), rules.key("alice"));
+        assert_ne!(rules.key("\u{430}lice"), rules.key("alice"));
+    }
+}
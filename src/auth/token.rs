@@ -0,0 +1,340 @@
+// src/auth/token.rs
+
+// 令牌认证：客户端把外部身份服务签发的 JWT 当作密码发送（/login <name> <token>）。
+//
+// 只支持 HS256（HMAC-SHA256，共享密钥）。校验签名、exp / nbf（允许 leeway_secs 秒的时钟误差）、
+// 可选的 iss，并要求 sub 与登录名相同（按规范化键比较）。令牌里可以带一个 role 声明。
+
+use super::authenticator::{ AuthFuture, Authenticator, Identity, LoginRequest };
+use super::moderation::Role;
+use super::rules::UsernameRules;
+use anyhow::{ Result, bail };
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64;
+use hmac::{ Hmac, Mac };
+use serde::Deserialize;
+use sha2::Sha256;
+use std::fmt;
+use std::time::{ SystemTime, UNIX_EPOCH };
+use tracing::debug;
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TokenConfig {
+    /// HS256 签名密钥，与签发令牌的服务共享
+    pub secret: Option<String>,
+    /// 设置后只接受 iss 与之相同的令牌
+    pub issuer: Option<String>,
+    /// 检查 exp / nbf 时允许的时钟误差（秒）
+    pub leeway_secs: u64,
+}
+
+impl Default for TokenConfig {
+    fn default() -> Self {
+        TokenConfig { secret: None, issuer: None, leeway_secs: 30 }
+    }
+}
+
+#[derive(Deserialize)]
+struct Header {
+    alg: String,
+}
+
+#[derive(Deserialize)]
+struct Claims {
+    sub: String,
+    #[serde(default)]
+    exp: Option<u64>,
+    #[serde(default)]
+    nbf: Option<u64>,
+    #[serde(default)]
+    iss: Option<String>,
+    #[serde(default)]
+    role: Option<Role>,
+}
+
+pub struct TokenAuthenticator {
+    key: Vec<u8>,
+    issuer: Option<String>,
+    leeway_secs: u64,
+    rules: UsernameRules,
+}
+
+/// 不打印签名密钥
+impl fmt::Debug for TokenAuthenticator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TokenAuthenticator")
+            .field("issuer", &self.issuer)
+            .field("leeway_secs", &self.leeway_secs)
+            .finish_non_exhaustive()
+    }
+}
+
+impl TokenAuthenticator {
+    /// `rules` 用来比较令牌中的 sub 和登录名
+    pub fn new(config: &TokenConfig, rules: &UsernameRules) -> Result<Self> {
+        let Some(secret) = config.secret.as_deref().filter(|secret| !secret.is_empty()) else {
+            bail!("token authentication needs a secret ([auth.token] secret)");
+        };
+        Ok(TokenAuthenticator {
+            key: secret.as_bytes().to_vec(),
+            issuer: config.issuer.clone(),
+            leeway_secs: config.leeway_secs,
+            rules: rules.clone(),
+        })
+    }
+
+    /// 校验令牌，返回其中的声明；失败时返回原因（只写日志，不发给客户端）
+    fn verify(&self, token: &str) -> Result<Claims, &'static str> {
+        // header.payload.signature，签名覆盖 header.payload
+        let Some((signing_input, signature)) = token.trim().rsplit_once('.') else {
+            return Err("malformed token");
+        };
+        let Some((header, payload)) = signing_input.split_once('.') else {
+            return Err("malformed token");
+        };
+
+        let decode = |part: &str| BASE64.decode(part).map_err(|_| "malformed token");
+        let header: Header = serde_json::from_slice(&decode(header)?).map_err(|_| "malformed header")?;
+        // 只认 HS256，不能让令牌自己选择 none 之类的算法
+        if header.alg != "HS256" {
+            return Err("unsupported algorithm");
+        }
+
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("HMAC accepts keys of any length");
+        mac.update(signing_input.as_bytes());
+        // verify_slice 是常数时间比较
+        mac.verify_slice(&decode(signature)?).map_err(|_| "bad signature")?;
+
+        let claims: Claims = serde_json::from_slice(&decode(payload)?).map_err(|_| "malformed claims")?;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs());
+        if claims.exp.is_some_and(|exp| now > exp.saturating_add(self.leeway_secs)) {
+            return Err("token expired");
+        }
+        if claims.nbf.is_some_and(|nbf| now.saturating_add(self.leeway_secs) < nbf) {
+            return Err("token not yet valid");
+        }
+        if let Some(issuer) = &self.issuer
+            && claims.iss.as_ref() != Some(issuer)
+        {
+            return Err("wrong issuer");
+        }
+        Ok(claims)
+    }
+}
+
+impl Authenticator for TokenAuthenticator {
+    fn name(&self) -> &'static str {
+        "token"
+    }
+
+    fn authenticate<'a>(&'a self, request: &'a LoginRequest) -> AuthFuture<'a> {
+        Box::pin(async move {
+            let Some(token) = &request.password else {
+                return Ok(Err("Please log in with a token (/login <name> <token>).".to_string()));
+            };
+            let claims = match self.verify(token) {
+                Ok(claims) => claims,
+                Err(reason) => {
+                    debug!(peer_addr = %request.addr, reason, "Token rejected.");
+                    return Ok(Err("Invalid or expired token.".to_string()));
+                }
+            };
+            if self.rules.key(&claims.sub) != self.rules.key(&request.username) {
+                return Ok(Err("Token does not match the username.".to_string()));
+            }
+
+            let identity = Identity::verified(request.username.clone());
+            Ok(Ok(match claims.role {
+                Some(role) => identity.with_role(role),
+                None => identity,
+            }))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::peer::PeerAddr;
+    use serde_json::{ Value, json };
+
+    const SECRET: &str = "test-secret";
+    const LEEWAY: u64 = 30;
+
+    fn authenticator(issuer: Option<&str>) -> TokenAuthenticator {
+        let config = TokenConfig {
+            secret: Some(SECRET.to_string()),
+            issuer: issuer.map(str::to_string),
+            leeway_secs: LEEWAY,
+        };
+        TokenAuthenticator::new(&config, &UsernameRules::default()).unwrap()
+    }
+
+    fn now() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+    }
+
+    fn encode(value: &Value) -> String {
+        BASE64.encode(serde_json::to_vec(value).unwrap())
+    }
+
+    /// 用 `key` 签名任意 header 和 claims
+    fn sign_with(key: &str, header: Value, claims: Value) -> String {
+        let signing_input = format!("{}.{}", encode(&header), encode(&claims));
+        let mut mac = HmacSha256::new_from_slice(key.as_bytes()).unwrap();
+        mac.update(signing_input.as_bytes());
+        format!("{signing_input}.{}", BASE64.encode(mac.finalize().into_bytes()))
+    }
+
+    fn sign(claims: Value) -> String {
+        sign_with(SECRET, json!({ "alg": "HS256", "typ": "JWT" }), claims)
+    }
+
+    /// 校验失败的原因，通过时为 None
+    fn rejection(auth: &TokenAuthenticator, token: &str) -> Option<&'static str> {
+        auth.verify(token).err()
+    }
+
+    async fn login(
+        auth: &TokenAuthenticator,
+        username: &str,
+        token: Option<String>
+    ) -> Result<Identity, String> {
+        let request = LoginRequest {
+            username: username.to_string(),
+            password: token,
+            addr: PeerAddr::Tcp("127.0.0.1:4000".parse().unwrap()),
+        };
+        auth.authenticate(&request).await.unwrap()
+    }
+
+    #[test]
+    fn needs_a_secret() {
+        let rules = UsernameRules::default();
+        assert!(TokenAuthenticator::new(&TokenConfig::default(), &rules).is_err());
+        let empty = TokenConfig { secret: Some(String::new()), ..TokenConfig::default() };
+        assert!(TokenAuthenticator::new(&empty, &rules).is_err());
+    }
+
+    #[test]
+    fn accepts_valid_token() {
+        let auth = authenticator(None);
+        assert_eq!(rejection(&auth, &sign(json!({ "sub": "alice" }))), None);
+        let token = sign(json!({ "sub": "alice", "exp": now() + 60, "nbf": now() - 60 }));
+        assert_eq!(rejection(&auth, &token), None);
+        // 首尾空白不影响
+        assert_eq!(rejection(&auth, &format!("  {token}\n")), None);
+    }
+
+    #[test]
+    fn rejects_alg_none() {
+        let auth = authenticator(None);
+        let claims = json!({ "sub": "alice" });
+        // 不带签名
+        let unsigned = format!("{}.{}.", encode(&json!({ "alg": "none" })), encode(&claims));
+        assert_eq!(rejection(&auth, &unsigned), Some("unsupported algorithm"));
+        // 即使签名本身正确，也不能由令牌自己选择算法
+        for alg in ["none", "None", "HS512", "RS256", "hs256"] {
+            let token = sign_with(SECRET, json!({ "alg": alg }), claims.clone());
+            assert_eq!(rejection(&auth, &token), Some("unsupported algorithm"), "{alg}");
+        }
+    }
+
+    #[test]
+    fn rejects_tampered_token() {
+        let auth = authenticator(None);
+        let token = sign(json!({ "sub": "alice", "role": "member" }));
+        let (header, rest) = token.split_once('.').unwrap();
+        let (_, signature) = rest.split_once('.').unwrap();
+
+        let payload = encode(&json!({ "sub": "alice", "role": "admin" }));
+        let tampered = format!("{header}.{payload}.{signature}");
+        assert_eq!(rejection(&auth, &tampered), Some("bad signature"));
+
+        let header = encode(&json!({ "alg": "HS256", "kid": "other" }));
+        let tampered = format!("{header}.{rest}");
+        assert_eq!(rejection(&auth, &tampered), Some("bad signature"));
+
+        let header = json!({ "alg": "HS256" });
+        let forged = sign_with("other-secret", header, json!({ "sub": "alice" }));
+        assert_eq!(rejection(&auth, &forged), Some("bad signature"));
+    }
+
+    #[test]
+    fn rejects_malformed_token() {
+        let auth = authenticator(None);
+        for token in ["", "abc", "a.b", "!!.!!.!!", "a.b.c.d"] {
+            assert!(rejection(&auth, token).is_some(), "{token:?}");
+        }
+        let not_json = format!("{}.{}.", BASE64.encode("{"), BASE64.encode("{}"));
+        assert_eq!(rejection(&auth, &not_json), Some("malformed header"));
+        // 签名正确但缺少 sub
+        assert_eq!(rejection(&auth, &sign(json!({ "exp": now() + 60 }))), Some("malformed claims"));
+    }
+
+    #[test]
+    fn exp_allows_leeway() {
+        let auth = authenticator(None);
+        let expired = sign(json!({ "sub": "alice", "exp": now() - LEEWAY - 60 }));
+        assert_eq!(rejection(&auth, &expired), Some("token expired"));
+        // 过期时间还在允许的时钟误差之内
+        let within_leeway = sign(json!({ "sub": "alice", "exp": now() - LEEWAY / 2 }));
+        assert_eq!(rejection(&auth, &within_leeway), None);
+    }
+
+    #[test]
+    fn nbf_allows_leeway() {
+        let auth = authenticator(None);
+        let early = sign(json!({ "sub": "alice", "nbf": now() + LEEWAY + 60 }));
+        assert_eq!(rejection(&auth, &early), Some("token not yet valid"));
+        let within_leeway = sign(json!({ "sub": "alice", "nbf": now() + LEEWAY / 2 }));
+        assert_eq!(rejection(&auth, &within_leeway), None);
+    }
+
+    #[test]
+    fn checks_issuer() {
+        let auth = authenticator(Some("https://login.example.com"));
+        let missing = sign(json!({ "sub": "alice" }));
+        assert_eq!(rejection(&auth, &missing), Some("wrong issuer"));
+        let other = sign(json!({ "sub": "alice", "iss": "https://evil.example.com" }));
+        assert_eq!(rejection(&auth, &other), Some("wrong issuer"));
+        let good = sign(json!({ "sub": "alice", "iss": "https://login.example.com" }));
+        assert_eq!(rejection(&auth, &good), None);
+
+        // 没有配置 issuer 时不检查
+        assert_eq!(rejection(&authenticator(None), &other), None);
+    }
+
+    #[tokio::test]
+    async fn login_with_token() {
+        let auth = authenticator(None);
+        let identity = login(&auth, "Alice", Some(sign(json!({ "sub": "alice" })))).await.unwrap();
+        // sub 按规范化键比较，用户名保持登录时的写法
+        assert_eq!(identity.username, "Alice");
+        assert_eq!(identity.role, Role::Member);
+        assert!(identity.verified);
+
+        let token = sign(json!({ "sub": "boss", "role": "admin" }));
+        assert_eq!(login(&auth, "boss", Some(token)).await.unwrap().role, Role::Admin);
+    }
+
+    #[tokio::test]
+    async fn login_rejects_mismatched_sub() {
+        let auth = authenticator(None);
+        let token = sign(json!({ "sub": "alice" }));
+        let err = login(&auth, "mallory", Some(token)).await.unwrap_err();
+        assert_eq!(err, "Token does not match the username.");
+    }
+
+    #[tokio::test]
+    async fn login_rejects_bad_or_missing_token() {
+        let auth = authenticator(None);
+        assert!(login(&auth, "alice", None).await.unwrap_err().contains("log in with a token"));
+        let expired = sign(json!({ "sub": "alice", "exp": now() - LEEWAY - 60 }));
+        let err = login(&auth, "alice", Some(expired)).await.unwrap_err();
+        assert_eq!(err, "Invalid or expired token.");
+    }
+}
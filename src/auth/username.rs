@@ -1,48 +1,282 @@
 // src/auth/username.rs
 
-use crate::connection::SharedContacts;
-use crate::utils::color::{ RED, RESET };
-use tokio::io::{ AsyncBufReadExt, AsyncWriteExt, BufReader };
-use tokio::net::tcp::{ OwnedReadHalf, OwnedWriteHalf };
-use anyhow::{ bail, Result };
-// 用于验证和注册用户名的异步函数``
-pub async fn validate_and_register_username(
-    writer: &mut OwnedWriteHalf,
-    reader: &mut BufReader<OwnedReadHalf>,
-    contact: &SharedContacts
-) -> Result<String> {
-    let mut line = String::new();
+use super::authenticator::{ Identity, LoginRequest };
+use super::moderation::{ Moderation, Role };
+use super::rules::{ NameReservation, UsernameRegistry };
+use crate::backend::{ Backend, RegisterResult };
+use crate::codec::{ Codec, Transport };
+use crate::connection::ConnectionSettings;
+use crate::connection::peer::PeerAddr;
+use crate::connection::queue::ClientSender;
+use crate::connection::session::ParkedSession;
+use crate::protocol::{ ClientMessage, ServerMessage };
+use crate::utils::sanitize::sanitize_owned;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{ AsyncRead, AsyncWrite };
+use tracing::{ error, info, warn };
+use anyhow::Result;
+
+/// 密码错误后多久才回复，拖慢在线猜密码
+const LOGIN_FAILURE_DELAY: Duration = Duration::from_secs(1);
+
+/// 登录结果
+pub enum LoggedIn {
+    /// 新登录，已经在后端注册
+    New {
+        username: String,
+        role: Role,
+        reservation: NameReservation,
+    },
+    /// 用会话令牌接回了断线前的会话，后端里的注册一直都在
+    Resumed(ParkedSession),
+}
+
+// 用于验证和注册用户名的异步函数
+// 客户端必须先发送 `ClientMessage::Login`（或 `Register`、`Resume`），失败时回复 `ServerMessage::Error` 并等待重试
+// 注册成功后 `sender` 就归后端所有，用来把消息发回给这个客户端
+// 用户名会显示在所有人的终端里，注册前先按 `settings.sanitize` 清理其中的控制字符，再按用户名规则校验；
+// 然后交给 `settings.authenticator` 认证，被封禁的名字不能登录也不能注册。
+// 出示了客户端证书（双向 TLS，`certificate` 是证书的 CN）的连接不经过认证方式，用户名必须与 CN 相同。
+// 验证过身份的用户按管理员 / 版主名单提升角色，其余用户使用认证方式给出的角色。
+// 返回的 NameReservation 要一直持有到从后端注销之后
+pub async fn validate_and_register_username<S, C, B>(
+    transport: &mut Transport<S, C>,
+    backend: &B,
+    addr: &PeerAddr,
+    certificate: Option<&str>,
+    sender: &ClientSender,
+    settings: &ConnectionSettings
+) -> Result<LoggedIn>
+    where S: AsyncRead + AsyncWrite + Unpin, C: Codec, B: Backend
+{
+    let authenticator = &settings.authenticator;
+    let usernames = &settings.usernames;
+    let moderation = &settings.moderation;
     loop {
-        line.clear();
-        writer.write_all(b"Enter username: \n").await?;
-        writer.flush().await?;
-
-        if reader.read_line(&mut line).await? == 0 {
-            // 客户端在登录时断开连接，这是一个明确的错误/退出条件
-            //return Err("Client disconnected during login".into());
-            anyhow::bail!("Client disconnected during login");
-        }
+        let msg = match transport.recv().await? {
+            Some(msg) => msg,
+            None => {
+                // 客户端在登录时断开连接，这是一个明确的错误/退出条件
+                anyhow::bail!("Client disconnected during login");
+            }
+        };
+        let (username, password, register) = match msg {
+            Ok(ClientMessage::Login { username, password }) => (username, password, false),
+            Ok(ClientMessage::Register { username, password }) => (username, Some(password), true),
+            Ok(ClientMessage::Resume { token }) => {
+                if !settings.sessions.enabled() {
+                    let err_msg = "Session resumption is disabled on this server.";
+                    transport.send(&ServerMessage::error(err_msg)).await?;
+                    continue;
+                }
+                let claim = match settings.sessions.verify(&token) {
+                    Ok(claim) => claim,
+                    Err(reason) => {
+                        transport.send(&ServerMessage::error(reason)).await?;
+                        continue;
+                    }
+                };
+                // 和新登录一样检查证书和封禁：断线期间被封禁的用户不能凭令牌回来
+                if let Some(cn) = certificate
+                    && usernames.rules().key(cn) != usernames.rules().key(claim.username())
+                {
+                    let err_msg = format!(
+                        "Your client certificate is for '{cn}', not '{}'.",
+                        claim.username()
+                    );
+                    transport.send(&ServerMessage::error(err_msg)).await?;
+                    continue;
+                }
+                if refuse_banned(transport, moderation, addr, claim.username()).await? {
+                    continue;
+                }
+                match settings.sessions.resume(claim).await {
+                    Ok(session) => return Ok(LoggedIn::Resumed(session)),
+                    Err(reason) => {
+                        transport.send(&ServerMessage::error(reason)).await?;
+                        continue;
+                    }
+                }
+            }
+            Ok(ClientMessage::Ping) => {
+                transport.send(&ServerMessage::Pong).await?;
+                continue;
+            }
+            Ok(ClientMessage::Pong) => continue,
+            Ok(ClientMessage::Quit) => anyhow::bail!("Client quit during login"),
+            Ok(_) => {
+                transport.send(&ServerMessage::error("Please log in first.")).await?;
+                continue;
+            }
+            Err(reason) => {
+                transport.send(&ServerMessage::error(format!("Malformed message: {reason}"))).await?;
+                continue;
+            }
+        };
 
-        let username = line.trim().to_string();
-        if username.is_empty() {
-            let err_msg = format!("{RED}Username cannot be empty.{RESET}\n");
-            writer.write_all(err_msg.as_bytes()).await?;
+        let Some(username) = sanitize_owned(username, settings.sanitize) else {
+            transport.send(&ServerMessage::error("Username cannot contain control characters.")).await?;
             continue;
-        }
+        };
+        let username = match usernames.rules().validate(&username) {
+            Ok(username) => username,
+            Err(reason) => {
+                transport.send(&ServerMessage::error(reason)).await?;
+                continue;
+            }
+        };
+        let request = LoginRequest { username, password, addr: addr.clone() };
 
-        // 使用一个作用域来限制锁的持有时间
-        let is_unique = {
-            let guard = contact.lock().unwrap();
-            !guard.contains_key(&username)
+        let (identity, reservation): (Identity, NameReservation) = if let Some(cn) = certificate {
+            // 证书已经在 TLS 握手时验证过，它就是这个连接的身份
+            if register {
+                let err_msg = "You are logged in with a client certificate, registration is not needed.";
+                transport.send(&ServerMessage::error(err_msg)).await?;
+                continue;
+            }
+            if usernames.rules().key(cn) != usernames.rules().key(&request.username) {
+                let err_msg = format!("Your client certificate is for '{cn}', please log in with that name.");
+                transport.send(&ServerMessage::error(err_msg)).await?;
+                continue;
+            }
+            if refuse_banned(transport, moderation, addr, &request.username).await? {
+                continue;
+            }
+            let Some(reservation) = reserve(transport, usernames, &request.username).await? else {
+                continue;
+            };
+            (Identity::verified(request.username.clone()), reservation)
+        } else if register {
+            // 占用名字之后再注册账号，不能把在线访客正在用的名字注册走
+            if refuse_banned(transport, moderation, addr, &request.username).await? {
+                continue;
+            }
+            let Some(reservation) = reserve(transport, usernames, &request.username).await? else {
+                continue;
+            };
+            match authenticator.register(&request).await {
+                Ok(Ok(identity)) => {
+                    info!(username = %identity.username, "Account registered.");
+                    (identity, reservation)
+                }
+                Ok(Err(reason)) => {
+                    transport.send(&ServerMessage::error(reason)).await?;
+                    continue;
+                }
+                Err(e) => {
+                    error!(username = %request.username, error = ?e, "Failed to register account");
+                    let err_msg = "Could not register the account, please try again later.";
+                    transport.send(&ServerMessage::error(err_msg)).await?;
+                    continue;
+                }
+            }
+        } else {
+            let identity = match authenticator.authenticate(&request).await {
+                Ok(Ok(identity)) => identity,
+                Ok(Err(reason)) => {
+                    // 带了密码或令牌却没通过，拖慢在线猜测
+                    if request.password.is_some() {
+                        warn!(
+                            peer_addr = %addr,
+                            username = %request.username,
+                            auth = authenticator.name(),
+                            "Failed login."
+                        );
+                        tokio::time::sleep(LOGIN_FAILURE_DELAY).await;
+                    }
+                    transport.send(&ServerMessage::error(reason)).await?;
+                    continue;
+                }
+                Err(e) => {
+                    error!(
+                        username = %request.username,
+                        auth = authenticator.name(),
+                        error = ?e,
+                        "Authentication failed"
+                    );
+                    let err_msg = "Could not log in right now, please try again later.";
+                    transport.send(&ServerMessage::error(err_msg)).await?;
+                    continue;
+                }
+            };
+            // 认证后的名字可能与请求中的写法不同（例如账号注册时的大小写），封禁和占用都按它来
+            if refuse_banned(transport, moderation, addr, &identity.username).await? {
+                continue;
+            }
+            let Some(reservation) = reserve(transport, usernames, &identity.username).await? else {
+                continue;
+            };
+            (identity, reservation)
         };
 
-        if is_unique {
-            // 成功找到唯一用户名，返回
-            return Ok(username);
+        let role = if identity.verified {
+            identity.role.max(moderation.role_of(&identity.username))
         } else {
-            let err_msg =
-                format!("{RED}Username '{}' is taken, please try another.{RESET}\n", username);
-            writer.write_all(err_msg.as_bytes()).await?;
+            identity.role
+        };
+        let username = identity.username;
+
+        match backend.register(username.clone(), addr.clone(), role, sender.clone()).await? {
+            RegisterResult::Success => {
+                // 成功找到唯一用户名，返回
+                return Ok(LoggedIn::New { username, role, reservation });
+            }
+            RegisterResult::UsernameTaken => {
+                let err_msg = ServerMessage::error(
+                    format!("Username '{}' is taken, please try another.", username)
+                );
+                transport.send(&err_msg).await?;
+            }
+        }
+    }
+}
+
+/// 名字或地址被封禁时回复客户端并返回 true。
+/// 连接建立时已经检查过 IP，这里再查一次是为了拦住在登录过程中才被封禁的地址
+async fn refuse_banned<S, C>(
+    transport: &mut Transport<S, C>,
+    moderation: &Moderation,
+    addr: &PeerAddr,
+    username: &str
+) -> Result<bool>
+    where S: AsyncRead + AsyncWrite + Unpin, C: Codec
+{
+    if let Some(ban) = addr.ip().and_then(|ip| moderation.ip_ban(ip)) {
+        info!(peer_addr = %addr, username = %username, "Banned address refused.");
+        transport.send(&ServerMessage::error(ban.notice())).await?;
+        return Ok(true);
+    }
+    let Some(ban) = moderation.user_ban(username) else {
+        return Ok(false);
+    };
+    info!(peer_addr = %addr, username = %username, "Banned username refused.");
+    let err_msg = match &ban.reason {
+        Some(reason) => format!("Username '{username}' is banned from this server: {reason}"),
+        None => format!("Username '{username}' is banned from this server."),
+    };
+    transport.send(&ServerMessage::error(err_msg)).await?;
+    Ok(true)
+}
+
+/// 占用名字的规范化键，与在线用户大小写不同或是同形字的名字在这里被拒绝（回复客户端并返回 None）
+async fn reserve<S, C>(
+    transport: &mut Transport<S, C>,
+    usernames: &Arc<UsernameRegistry>,
+    username: &str
+) -> Result<Option<NameReservation>>
+    where S: AsyncRead + AsyncWrite + Unpin, C: Codec
+{
+    match usernames.reserve(username) {
+        Ok(reservation) => Ok(Some(reservation)),
+        Err(existing) => {
+            let err_msg = if existing == username {
+                format!("Username '{username}' is taken, please try another.")
+            } else {
+                format!("Username '{username}' is too similar to '{existing}', please try another.")
+            };
+            transport.send(&ServerMessage::error(err_msg)).await?;
+            Ok(None)
         }
     }
 }
@@ -0,0 +1,281 @@
+// src/backend/actor/hub.rs
+
+use super::models::{ Client, HubCommand };
+use crate::auth::moderation::Role;
+use crate::backend::{ RegisterResult, UserInfo };
+use crate::protocol::ServerMessage;
+use std::collections::{ BTreeSet, HashMap };
+use crate::codec::SharedMessage;
+use crate::connection::peer::PeerAddr;
+use crate::connection::queue::ClientSender;
+use tokio::sync::mpsc;
+use tracing::info;
+
+/// Hub 结构体，作为应用的状态和业务逻辑核心
+pub struct Hub {
+    /// Hub 从所有客户端任务接收命令的入口
+    receiver: mpsc::Receiver<HubCommand>,
+    /// 存储所有已连接的客户端信息，键为用户名
+    clients: HashMap<String, Client>,
+    /// 房间名 -> 成员用户名，最后一个成员离开时删除房间
+    rooms: HashMap<String, BTreeSet<String>>,
+}
+
+impl Hub {
+    pub fn new(receiver: mpsc::Receiver<HubCommand>) -> Self {
+        Hub {
+            receiver,
+            clients: HashMap::new(),
+            rooms: HashMap::new(),
+        }
+    }
+
+    /// 运行 Hub 的主事件循环。
+    pub async fn run(&mut self) {
+        info!("[Hub] Started processing commands.");
+        while let Some(command) = self.receiver.recv().await {
+            match command {
+                HubCommand::Register { username, addr, role, sender, responder } =>
+                    self.register(username, addr, role, sender, responder),
+
+                HubCommand::Deregister { username } => self.deregister(&username),
+
+                // 注意：这里不再需要 await，因为 broadcast 变成了同步非阻塞
+                HubCommand::Broadcast { from, content } => self.broadcast(&from, content),
+
+                HubCommand::Whisper { from, to, content } => self.whisper(from, &to, content),
+
+                HubCommand::ListUsers { responder } => {
+                    let _ = responder.send(self.users());
+                }
+
+                HubCommand::JoinRoom { username, room } => self.join_room(username, room),
+
+                HubCommand::PartRoom { username, room } => self.part_room(&username, &room),
+
+                HubCommand::RoomMessage { from, room, content } =>
+                    self.room_message(from, room, content),
+
+                HubCommand::RoomMembers { room, responder } => {
+                    let members = self.rooms.get(&room).map(|m| m.iter().cloned().collect());
+                    let _ = responder.send(members);
+                }
+
+                HubCommand::ListRooms { responder } => {
+                    let mut rooms: Vec<String> = self.rooms.keys().cloned().collect();
+                    rooms.sort();
+                    let _ = responder.send(rooms);
+                }
+
+                HubCommand::UserInfo { username, responder } => {
+                    let _ = responder.send(self.clients.get(&username).map(user_info));
+                }
+
+                HubCommand::ListClients { responder } => {
+                    let mut clients: Vec<UserInfo> = self.clients.values().map(user_info).collect();
+                    clients.sort_by(|a, b| a.username.cmp(&b.username));
+                    let _ = responder.send(clients);
+                }
+
+                HubCommand::UsersAt { ip, responder } => {
+                    let users = self.clients
+                        .values()
+                        .filter(|client| client.addr.ip() == Some(ip))
+                        .map(user_info)
+                        .collect();
+                    let _ = responder.send(users);
+                }
+
+                HubCommand::Notify { username, msg } => self.send_to(&username, msg),
+
+                HubCommand::Kick { username, reason } => {
+                    if let Some(client) = self.clients.get(&username) {
+                        client.sender.kick(reason);
+                    }
+                }
+            }
+        }
+        info!("[Hub] Channel closed, shutting down.");
+    }
+
+    fn register(
+        &mut self,
+        username: String,
+        addr: PeerAddr,
+        role: Role,
+        sender: ClientSender,
+        responder: tokio::sync::oneshot::Sender<RegisterResult>
+    ) {
+        if self.clients.contains_key(&username) {
+            // 忽略发送错误，因为客户端可能已经断开
+            let _ = responder.send(RegisterResult::UsernameTaken);
+        } else {
+            let client = Client {
+                username: username.clone(),
+                addr: addr.clone(),
+                role,
+                sender,
+            };
+            self.clients.insert(username.clone(), client);
+            info!(
+                username = %username,
+                addr = %addr,
+                total_clients = self.clients.len(),
+                "[Hub] Client registered."
+            );
+            let _ = responder.send(RegisterResult::Success);
+
+            // 通知其他人有新用户加入
+            let joined = ServerMessage::UserJoined {
+                username: username.clone(),
+                online: self.clients.len(),
+            };
+            self.send_to_others(&username, joined);
+        }
+    }
+
+    fn deregister(&mut self, username: &str) {
+        if let Some(client) = self.clients.remove(username) {
+            info!(
+                username = %client.username,
+                addr = %client.addr,
+                total_clients = self.clients.len(),
+                "[Hub] Client deregistered."
+            );
+
+            let left = ServerMessage::UserLeft {
+                username: client.username,
+                online: self.clients.len(),
+            };
+            self.send_to_others(username, left);
+
+            // 退出所有房间，空房间直接删除
+            let joined: Vec<String> = self.rooms
+                .iter_mut()
+                .filter_map(|(room, members)| members.remove(username).then(|| room.clone()))
+                .collect();
+            self.rooms.retain(|_, members| !members.is_empty());
+            for room in joined {
+                let left = ServerMessage::RoomUserLeft {
+                    room: room.clone(),
+                    username: username.to_string(),
+                };
+                self.send_to_room(&room, username, left);
+            }
+        }
+    }
+
+    // 🔥 修复：移除 async，使用 try_send 防止阻塞
+    fn broadcast(&self, from: &str, content: String) {
+        let broadcast_msg = ServerMessage::NewMessage {
+            from: from.to_string(),
+            content,
+        };
+        // info!(from = %from, "[Hub] Broadcasting message."); // 可以根据需要开启 debug 日志
+        self.send_to_others(from, broadcast_msg);
+    }
+
+    /// 把一条消息发给除 `except` 以外的所有在线用户
+    fn send_to_others(&self, except: &str, msg: ServerMessage) {
+        // 只构造一次，所有接收者共享同一份消息和编码结果
+        let msg = SharedMessage::new(msg);
+        for (username, client) in &self.clients {
+            if username != except {
+                // 使用 try_send，绝不让 Hub 等待（await）。
+                // 队列满时按该客户端的 SlowConsumerPolicy 处理
+                client.sender.try_send(msg.clone());
+            }
+        }
+    }
+
+    fn whisper(&self, from: String, to: &str, content: String) {
+        let reply = match self.clients.get(to) {
+            Some(target) => {
+                let msg = ServerMessage::PrivateMessage { from: from.clone(), content: content.clone() };
+                target.sender.try_send(msg);
+                ServerMessage::PrivateMessageSent { to: to.to_string(), content }
+            }
+            // 目标不在线，把错误发回给发送者
+            None => ServerMessage::error(format!("User '{to}' not found.")),
+        };
+
+        if let Some(sender) = self.clients.get(&from) {
+            sender.sender.try_send(reply);
+        }
+    }
+
+    fn join_room(&mut self, username: String, room: String) {
+        let members = self.rooms.entry(room.clone()).or_default();
+        if !members.insert(username.clone()) {
+            self.send_to(&username, ServerMessage::error(format!("You are already in #{room}.")));
+            return;
+        }
+
+        let members = members.iter().cloned().collect();
+        info!(username = %username, room = %room, "[Hub] Client joined room.");
+        let joined = ServerMessage::RoomUserJoined { room: room.clone(), username: username.clone() };
+        self.send_to_room(&room, &username, joined);
+        self.send_to(&username, ServerMessage::JoinedRoom { room, members });
+    }
+
+    fn part_room(&mut self, username: &str, room: &str) {
+        let Some(members) = self.rooms.get_mut(room) else {
+            self.send_to(username, ServerMessage::error(format!("You are not in #{room}.")));
+            return;
+        };
+        if !members.remove(username) {
+            self.send_to(username, ServerMessage::error(format!("You are not in #{room}.")));
+            return;
+        }
+        if members.is_empty() {
+            self.rooms.remove(room);
+        }
+
+        info!(username = %username, room = %room, "[Hub] Client left room.");
+        let left = ServerMessage::RoomUserLeft {
+            room: room.to_string(),
+            username: username.to_string(),
+        };
+        self.send_to_room(room, username, left);
+        self.send_to(username, ServerMessage::LeftRoom { room: room.to_string() });
+    }
+
+    fn room_message(&self, from: String, room: String, content: String) {
+        if !self.rooms.get(&room).is_some_and(|members| members.contains(&from)) {
+            self.send_to(&from, ServerMessage::error(format!("You are not in #{room}.")));
+            return;
+        }
+        let msg = ServerMessage::RoomMessage { room: room.clone(), from: from.clone(), content };
+        self.send_to_room(&room, &from, msg);
+    }
+
+    /// 把一条消息发给房间内除 `except` 以外的所有成员
+    fn send_to_room(&self, room: &str, except: &str, msg: ServerMessage) {
+        let Some(members) = self.rooms.get(room) else {
+            return;
+        };
+        let msg = SharedMessage::new(msg);
+        for name in members.iter().filter(|name| *name != except) {
+            if let Some(client) = self.clients.get(name) {
+                client.sender.try_send(msg.clone());
+            }
+        }
+    }
+
+    /// 只发给一个用户，用户不在线时忽略
+    fn send_to(&self, username: &str, msg: ServerMessage) {
+        if let Some(client) = self.clients.get(username) {
+            client.sender.try_send(msg);
+        }
+    }
+
+    fn users(&self) -> Vec<String> {
+        let mut users: Vec<String> = self.clients.keys().cloned().collect();
+        users.sort();
+        users
+    }
+}
+
+fn user_info(client: &Client) -> UserInfo {
+    UserInfo { username: client.username.clone(), addr: client.addr.clone(), role: client.role }
+}
@@ -0,0 +1,158 @@
+// src/backend/actor/mod.rs
+
+// 基于 Actor 模型的后端：所有状态由一个 Hub 任务独占，
+// 连接任务通过 mpsc 通道向 Hub 发送 HubCommand。
+
+pub mod hub;
+pub mod models;
+
+pub use hub::Hub;
+pub use models::{ Client, HubCommand };
+
+use super::{ Backend, RegisterResult, UserInfo };
+use crate::auth::moderation::Role;
+use crate::connection::peer::PeerAddr;
+use crate::connection::queue::ClientSender;
+use crate::protocol::ServerMessage;
+use anyhow::bail;
+use std::net::IpAddr;
+use tokio::sync::{ mpsc, oneshot };
+
+#[derive(Clone)]
+pub struct ActorBackend {
+    hub_tx: mpsc::Sender<HubCommand>,
+}
+
+impl ActorBackend {
+    /// 创建 Hub 并在后台启动它的事件循环。
+    /// `capacity` 是 Hub 邮箱的容量，作为整个服务器的“写入缓冲”
+    pub fn spawn(capacity: usize) -> Self {
+        let (hub_tx, hub_rx) = mpsc::channel::<HubCommand>(capacity);
+        let mut hub = Hub::new(hub_rx);
+        tokio::spawn(async move {
+            hub.run().await;
+        });
+        ActorBackend { hub_tx }
+    }
+
+    /// 发送一条带 oneshot 回复通道的命令并等待 Hub 的回复
+    async fn request<T>(
+        &self,
+        make_cmd: impl FnOnce(oneshot::Sender<T>) -> HubCommand
+    ) -> anyhow::Result<T> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        if self.hub_tx.send(make_cmd(resp_tx)).await.is_err() {
+            bail!("Hub has been shutdown.");
+        }
+        match resp_rx.await {
+            Ok(result) => Ok(result),
+            Err(_) => bail!("Hub dropped the request (shutdown?)."),
+        }
+    }
+}
+
+impl Backend for ActorBackend {
+    fn name(&self) -> &'static str {
+        "actor"
+    }
+
+    async fn register(
+        &self,
+        username: String,
+        addr: PeerAddr,
+        role: Role,
+        sender: ClientSender
+    ) -> anyhow::Result<RegisterResult> {
+        self.request(|responder| HubCommand::Register {
+            username,
+            addr,
+            role,
+            sender,
+            responder,
+        }).await
+    }
+
+    async fn deregister(&self, username: &str) {
+        // 如果 Hub 已经关闭或发送失败，我们也不在乎了。
+        let _ = self.hub_tx.send(HubCommand::Deregister {
+            username: username.to_string(),
+        }).await;
+    }
+
+    async fn broadcast(&self, from: &str, content: String) {
+        let _ = self.hub_tx.send(HubCommand::Broadcast {
+            from: from.to_string(),
+            content,
+        }).await;
+    }
+
+    async fn whisper(&self, from: &str, to: &str, content: String) {
+        let _ = self.hub_tx.send(HubCommand::Whisper {
+            from: from.to_string(),
+            to: to.to_string(),
+            content,
+        }).await;
+    }
+
+    async fn users(&self) -> anyhow::Result<Vec<String>> {
+        self.request(|responder| HubCommand::ListUsers { responder }).await
+    }
+
+    async fn join_room(&self, username: &str, room: &str) {
+        let _ = self.hub_tx.send(HubCommand::JoinRoom {
+            username: username.to_string(),
+            room: room.to_string(),
+        }).await;
+    }
+
+    async fn part_room(&self, username: &str, room: &str) {
+        let _ = self.hub_tx.send(HubCommand::PartRoom {
+            username: username.to_string(),
+            room: room.to_string(),
+        }).await;
+    }
+
+    async fn room_message(&self, from: &str, room: &str, content: String) {
+        let _ = self.hub_tx.send(HubCommand::RoomMessage {
+            from: from.to_string(),
+            room: room.to_string(),
+            content,
+        }).await;
+    }
+
+    async fn room_members(&self, room: &str) -> anyhow::Result<Option<Vec<String>>> {
+        let room = room.to_string();
+        self.request(|responder| HubCommand::RoomMembers { room, responder }).await
+    }
+
+    async fn rooms(&self) -> anyhow::Result<Vec<String>> {
+        self.request(|responder| HubCommand::ListRooms { responder }).await
+    }
+
+    async fn user(&self, username: &str) -> anyhow::Result<Option<UserInfo>> {
+        let username = username.to_string();
+        self.request(|responder| HubCommand::UserInfo { username, responder }).await
+    }
+
+    async fn clients(&self) -> anyhow::Result<Vec<UserInfo>> {
+        self.request(|responder| HubCommand::ListClients { responder }).await
+    }
+
+    async fn users_at(&self, ip: IpAddr) -> anyhow::Result<Vec<UserInfo>> {
+        self.request(|responder| HubCommand::UsersAt { ip, responder }).await
+    }
+
+    async fn notify(&self, username: &str, msg: ServerMessage) {
+        let _ = self.hub_tx.send(HubCommand::Notify {
+            username: username.to_string(),
+            msg,
+        }).await;
+    }
+
+    async fn kick(&self, username: &str, reason: String) {
+        let _ = self.hub_tx.send(HubCommand::Kick {
+            username: username.to_string(),
+            reason,
+        }).await;
+    }
+}
@@ -0,0 +1,100 @@
+// src/backend/actor/models.rs
+
+use crate::auth::moderation::Role;
+use crate::backend::{ RegisterResult, UserInfo };
+use crate::connection::peer::PeerAddr;
+use crate::connection::queue::ClientSender;
+use crate::protocol::ServerMessage;
+use std::net::IpAddr;
+use tokio::sync::oneshot;
+
+/// 代表一个已连接的客户端的所有信息，由 Hub 持有
+#[derive(Debug)]
+pub struct Client {
+    pub username: String,
+    pub addr: PeerAddr,
+    pub role: Role,
+    /// 这个 Sender 用于将消息（如广播）发回给该客户端的写入任务
+    pub sender: ClientSender,
+}
+
+/// 定义客户端任务可以发送给 Hub 的所有命令
+#[derive(Debug)]
+pub enum HubCommand {
+    /// 新客户端注册，包含一个用于回复注册结果的 oneshot channel
+    Register {
+        username: String,
+        addr: PeerAddr,
+        role: Role,
+        sender: ClientSender, // 这里必须传真的 Sender
+        responder: oneshot::Sender<RegisterResult>,
+    },
+    /// 客户端断开连接
+    Deregister {
+        username: String,
+    },
+    /// 广播消息
+    Broadcast {
+        from: String,
+        content: String,
+    },
+    /// 私聊消息，Hub 直接给发送者回显或回复错误
+    Whisper {
+        from: String,
+        to: String,
+        content: String,
+    },
+    /// 查询在线用户列表
+    ListUsers {
+        responder: oneshot::Sender<Vec<String>>,
+    },
+    /// 加入房间，不存在时创建
+    JoinRoom {
+        username: String,
+        room: String,
+    },
+    /// 离开房间，空房间会被删除
+    PartRoom {
+        username: String,
+        room: String,
+    },
+    /// 房间内广播
+    RoomMessage {
+        from: String,
+        room: String,
+        content: String,
+    },
+    /// 查询房间成员，房间不存在时回复 None
+    RoomMembers {
+        room: String,
+        responder: oneshot::Sender<Option<Vec<String>>>,
+    },
+    /// 查询所有房间
+    ListRooms {
+        responder: oneshot::Sender<Vec<String>>,
+    },
+    /// 查询一个在线用户，不在线时回复 None
+    UserInfo {
+        username: String,
+        responder: oneshot::Sender<Option<UserInfo>>,
+    },
+    /// 查询所有在线用户
+    ListClients {
+        responder: oneshot::Sender<Vec<UserInfo>>,
+    },
+    /// 查询从某个 IP 连接的所有在线用户
+    UsersAt {
+        ip: IpAddr,
+        responder: oneshot::Sender<Vec<UserInfo>>,
+    },
+    /// 只发给一个用户
+    Notify {
+        username: String,
+        msg: ServerMessage,
+    },
+    /// 断开一个用户
+    Kick {
+        username: String,
+        reason: String,
+    },
+}
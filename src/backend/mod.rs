@@ -0,0 +1,299 @@
+// src/backend/mod.rs
+
+// 聊天室状态后端。连接处理逻辑只依赖 Backend trait，
+// 具体使用 Mutex 共享状态还是 Actor (Hub) 由 ChatServer 的使用者决定。
+
+pub mod actor;
+pub mod mutex;
+
+pub use actor::ActorBackend;
+pub use mutex::MutexBackend;
+
+use crate::auth::moderation::Role;
+use crate::connection::peer::PeerAddr;
+use crate::connection::queue::ClientSender;
+use crate::protocol::ServerMessage;
+use std::future::Future;
+use std::net::IpAddr;
+
+/// 注册操作的结果
+#[derive(Debug)]
+pub enum RegisterResult {
+    Success,
+    UsernameTaken,
+}
+
+/// 一个在线用户的信息，供管理命令使用
+#[derive(Debug, Clone)]
+pub struct UserInfo {
+    pub username: String,
+    pub addr: PeerAddr,
+    pub role: Role,
+}
+
+/// 聊天室状态的存取接口。实现需要可以廉价地 clone，每个连接任务持有一份。
+pub trait Backend: Clone + Send + Sync + 'static {
+    /// 后端名称，用于日志
+    fn name(&self) -> &'static str;
+
+    /// 以 `username` 和角色 `role` 注册一个客户端，`sender` 用于把消息发回给该客户端
+    fn register(
+        &self,
+        username: String,
+        addr: PeerAddr,
+        role: Role,
+        sender: ClientSender
+    ) -> impl Future<Output = anyhow::Result<RegisterResult>> + Send;
+
+    /// 客户端断开连接
+    fn deregister(&self, username: &str) -> impl Future<Output = ()> + Send;
+
+    /// 把消息发给除发送者以外的所有在线用户
+    fn broadcast(&self, from: &str, content: String) -> impl Future<Output = ()> + Send;
+
+    /// 私聊；成功时给发送者回显一条 `PrivateMessageSent`，目标不在线时给发送者回复一条错误
+    fn whisper(&self, from: &str, to: &str, content: String) -> impl Future<Output = ()> + Send;
+
+    /// 当前所有在线用户名，按字典序排列
+    fn users(&self) -> impl Future<Output = anyhow::Result<Vec<String>>> + Send;
+
+    /// 加入房间，房间不存在时自动创建。
+    /// 成功时给调用者发 `JoinedRoom`、给房间其他成员发 `RoomUserJoined`，已在房间中时回复错误
+    fn join_room(&self, username: &str, room: &str) -> impl Future<Output = ()> + Send;
+
+    /// 离开房间，最后一个成员离开后房间被删除。
+    /// 成功时给调用者发 `LeftRoom`、给房间其他成员发 `RoomUserLeft`，不在房间中时回复错误
+    fn part_room(&self, username: &str, room: &str) -> impl Future<Output = ()> + Send;
+
+    /// 发给房间内除发送者以外的所有成员；发送者不在房间中时回复错误
+    fn room_message(
+        &self,
+        from: &str,
+        room: &str,
+        content: String
+    ) -> impl Future<Output = ()> + Send;
+
+    /// 房间成员，按字典序排列；房间不存在时返回 `None`
+    fn room_members(
+        &self,
+        room: &str
+    ) -> impl Future<Output = anyhow::Result<Option<Vec<String>>>> + Send;
+
+    /// 当前所有房间名，按字典序排列
+    fn rooms(&self) -> impl Future<Output = anyhow::Result<Vec<String>>> + Send;
+
+    /// 查询一个在线用户，不在线时返回 `None`
+    fn user(&self, username: &str) -> impl Future<Output = anyhow::Result<Option<UserInfo>>> + Send;
+
+    /// 所有在线用户，按用户名的字典序排列
+    fn clients(&self) -> impl Future<Output = anyhow::Result<Vec<UserInfo>>> + Send;
+
+    /// 从 `ip` 连接的所有在线用户
+    fn users_at(&self, ip: IpAddr) -> impl Future<Output = anyhow::Result<Vec<UserInfo>>> + Send;
+
+    /// 只发给一个用户，用户不在线时忽略
+    fn notify(&self, username: &str, msg: ServerMessage) -> impl Future<Output = ()> + Send;
+
+    /// 断开一个用户：清空他的队列并发送一条以 `reason` 为原因的 `Disconnected`，
+    /// 连接随后按正常流程注销。用户不在线时忽略
+    fn kick(&self, username: &str, reason: String) -> impl Future<Output = ()> + Send;
+}
+
+/// 两个后端跑同一组测试，保证行为一致
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::queue::{ ClientReceiver, SlowConsumerPolicy, client_queue };
+    use serde_json::{ Value, json };
+    use std::sync::Arc;
+    use tokio::sync::Barrier;
+
+    fn client() -> (ClientSender, ClientReceiver) {
+        client_queue(100, usize::MAX, SlowConsumerPolicy::DropNewest, Arc::default())
+    }
+
+    fn addr() -> PeerAddr {
+        PeerAddr::Tcp("127.0.0.1:4000".parse().unwrap())
+    }
+
+    /// 注册一个用户，返回他的接收端
+    async fn login<B: Backend>(backend: &B, username: &str) -> ClientReceiver {
+        let (tx, rx) = client();
+        let result = backend.register(username.to_string(), addr(), Role::Member, tx).await;
+        assert!(matches!(result.unwrap(), RegisterResult::Success));
+        rx
+    }
+
+    /// 等后端处理完之前发出的命令（actor 后端按顺序处理邮箱），再取出队列里已有的消息
+    async fn received<B: Backend>(backend: &B, rx: &mut ClientReceiver) -> Vec<Value> {
+        backend.users().await.unwrap();
+        let mut messages = Vec::new();
+        while let Some(msg) = rx.try_recv() {
+            messages.push(serde_json::to_value(msg.message()).unwrap());
+        }
+        messages
+    }
+
+    fn error(msg: &str) -> Value {
+        json!({ "type": "Error", "msg": msg })
+    }
+
+    async fn join_and_part<B: Backend>(backend: B) {
+        let mut alice = login(&backend, "alice").await;
+        let mut bob = login(&backend, "bob").await;
+        received(&backend, &mut alice).await;
+
+        backend.join_room("alice", "rust").await;
+        let joined = json!({ "type": "JoinedRoom", "room": "rust", "members": ["alice"] });
+        assert_eq!(received(&backend, &mut alice).await, [joined]);
+
+        backend.join_room("bob", "rust").await;
+        let joined = json!({ "type": "JoinedRoom", "room": "rust", "members": ["alice", "bob"] });
+        assert_eq!(received(&backend, &mut bob).await, [joined]);
+        let bob_joined = json!({ "type": "RoomUserJoined", "room": "rust", "username": "bob" });
+        assert_eq!(received(&backend, &mut alice).await, [bob_joined]);
+        assert_eq!(backend.rooms().await.unwrap(), ["rust"]);
+        assert_eq!(backend.room_members("rust").await.unwrap().unwrap(), ["alice", "bob"]);
+
+        backend.join_room("alice", "rust").await;
+        assert_eq!(received(&backend, &mut alice).await, [error("You are already in #rust.")]);
+
+        backend.room_message("alice", "rust", "hi".to_string()).await;
+        let msg = json!({
+            "type": "RoomMessage",
+            "room": "rust",
+            "from": "alice",
+            "content": "hi",
+        });
+        assert_eq!(received(&backend, &mut bob).await, [msg]);
+        // 发送者自己收不到
+        assert!(received(&backend, &mut alice).await.is_empty());
+
+        backend.part_room("bob", "rust").await;
+        let left = || json!({ "type": "LeftRoom", "room": "rust" });
+        assert_eq!(received(&backend, &mut bob).await, [left()]);
+        let bob_left = json!({ "type": "RoomUserLeft", "room": "rust", "username": "bob" });
+        assert_eq!(received(&backend, &mut alice).await, [bob_left]);
+        assert_eq!(backend.room_members("rust").await.unwrap().unwrap(), ["alice"]);
+
+        // 最后一个成员离开后房间被删除
+        backend.part_room("alice", "rust").await;
+        assert_eq!(received(&backend, &mut alice).await, [left()]);
+        assert!(backend.rooms().await.unwrap().is_empty());
+        assert_eq!(backend.room_members("rust").await.unwrap(), None);
+    }
+
+    async fn not_in_room<B: Backend>(backend: B) {
+        let mut alice = login(&backend, "alice").await;
+        let _bob = login(&backend, "bob").await;
+        backend.join_room("bob", "rust").await;
+        received(&backend, &mut alice).await;
+
+        // 房间不存在
+        backend.part_room("alice", "nowhere").await;
+        backend.room_message("alice", "nowhere", "hi".to_string()).await;
+        // 房间存在但不是成员
+        backend.part_room("alice", "rust").await;
+        backend.room_message("alice", "rust", "hi".to_string()).await;
+        assert_eq!(received(&backend, &mut alice).await, [
+            error("You are not in #nowhere."),
+            error("You are not in #nowhere."),
+            error("You are not in #rust."),
+            error("You are not in #rust."),
+        ]);
+        // 出错不会创建或改变房间
+        assert_eq!(backend.rooms().await.unwrap(), ["rust"]);
+        assert_eq!(backend.room_members("rust").await.unwrap().unwrap(), ["bob"]);
+    }
+
+    async fn deregister_leaves_rooms<B: Backend>(backend: B) {
+        let _alice = login(&backend, "alice").await;
+        let mut bob = login(&backend, "bob").await;
+        backend.join_room("alice", "rust").await;
+        backend.join_room("bob", "rust").await;
+        backend.join_room("alice", "solo").await;
+        received(&backend, &mut bob).await;
+
+        backend.deregister("alice").await;
+        assert_eq!(received(&backend, &mut bob).await, [
+            json!({ "type": "UserLeft", "username": "alice", "online": 1 }),
+            json!({ "type": "RoomUserLeft", "room": "rust", "username": "alice" }),
+        ]);
+        // 只剩 alice 的房间被删除
+        assert_eq!(backend.rooms().await.unwrap(), ["rust"]);
+        assert_eq!(backend.room_members("rust").await.unwrap().unwrap(), ["bob"]);
+        assert_eq!(backend.room_members("solo").await.unwrap(), None);
+    }
+
+    /// 两个连接同时以同一个名字注册：只能有一个成功
+    async fn concurrent_register<B: Backend>(backend: B) {
+        for round in 0..50 {
+            let username = format!("user{round}");
+            let barrier = Arc::new(Barrier::new(2));
+            let attempts: Vec<_> = (0..2)
+                .map(|_| {
+                    let backend = backend.clone();
+                    let (username, barrier) = (username.clone(), barrier.clone());
+                    tokio::spawn(async move {
+                        let (tx, rx) = client();
+                        barrier.wait().await;
+                        let result = backend.register(username, addr(), Role::Member, tx).await;
+                        (result.unwrap(), rx)
+                    })
+                })
+                .collect();
+
+            let mut results = Vec::new();
+            for attempt in attempts {
+                results.push(attempt.await.unwrap().0);
+            }
+            let won = results.iter().filter(|r| matches!(r, RegisterResult::Success)).count();
+            let taken = results
+                .iter()
+                .filter(|r| matches!(r, RegisterResult::UsernameTaken))
+                .count();
+            assert_eq!((won, taken), (1, 1), "round {round}: {results:?}");
+        }
+        assert_eq!(backend.users().await.unwrap().len(), 50);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn mutex_concurrent_register() {
+        concurrent_register(MutexBackend::new()).await;
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn actor_concurrent_register() {
+        concurrent_register(ActorBackend::spawn(16)).await;
+    }
+
+    #[tokio::test]
+    async fn mutex_join_and_part() {
+        join_and_part(MutexBackend::new()).await;
+    }
+
+    #[tokio::test]
+    async fn actor_join_and_part() {
+        join_and_part(ActorBackend::spawn(16)).await;
+    }
+
+    #[tokio::test]
+    async fn mutex_not_in_room() {
+        not_in_room(MutexBackend::new()).await;
+    }
+
+    #[tokio::test]
+    async fn actor_not_in_room() {
+        not_in_room(ActorBackend::spawn(16)).await;
+    }
+
+    #[tokio::test]
+    async fn mutex_deregister_leaves_rooms() {
+        deregister_leaves_rooms(MutexBackend::new()).await;
+    }
+
+    #[tokio::test]
+    async fn actor_deregister_leaves_rooms() {
+        deregister_leaves_rooms(ActorBackend::spawn(16)).await;
+    }
+}
@@ -0,0 +1,244 @@
+// src/backend/mutex.rs
+
+// 基于 Arc<Mutex<HashMap>> 共享状态的后端，每个连接任务直接加锁读写。
+// 需要同时持有两把锁时，加锁顺序固定为 rooms -> contact。
+
+use super::{ Backend, RegisterResult, UserInfo };
+use crate::auth::moderation::Role;
+use crate::connection::peer::PeerAddr;
+use crate::connection::queue::ClientSender;
+use crate::connection::{ ClientInfo, SharedContacts, SharedRooms };
+use crate::message::broadcast::{ broadcast_to_others, send_private, send_to_others };
+use crate::message::room::send_to_room;
+use crate::protocol::ServerMessage;
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::net::IpAddr;
+use std::sync::{ Arc, Mutex };
+use tracing::info;
+
+#[derive(Clone, Default)]
+pub struct MutexBackend {
+    contact: SharedContacts,
+    rooms: SharedRooms,
+}
+
+impl MutexBackend {
+    pub fn new() -> Self {
+        MutexBackend {
+            contact: Arc::new(Mutex::new(HashMap::new())),
+            rooms: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// 底层的共享状态
+    pub fn contacts(&self) -> &SharedContacts {
+        &self.contact
+    }
+
+    /// 只发给一个用户，用户不在线时忽略
+    async fn send_to(&self, username: &str, msg: ServerMessage) {
+        let sender = self.contact
+            .lock()
+            .unwrap()
+            .get(username)
+            .map(|info| info.tx.clone());
+        if let Some(tx) = sender {
+            tx.send(msg).await;
+        }
+    }
+}
+
+impl Backend for MutexBackend {
+    fn name(&self) -> &'static str {
+        "mutex"
+    }
+
+    async fn register(
+        &self,
+        username: String,
+        addr: PeerAddr,
+        role: Role,
+        sender: ClientSender
+    ) -> anyhow::Result<RegisterResult> {
+        // 检查和插入必须在同一次加锁中完成，否则两个客户端可能同时通过检查，
+        // 后插入的会悄悄覆盖先插入的
+        let online = {
+            let mut guard = self.contact.lock().unwrap();
+            match guard.entry(username.clone()) {
+                Entry::Occupied(_) => {
+                    return Ok(RegisterResult::UsernameTaken);
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(ClientInfo {
+                        addr,
+                        username: username.clone(),
+                        role,
+                        tx: sender,
+                    });
+                }
+            }
+            guard.len()
+        };
+
+        // 通知其他人有新用户加入
+        let joined = ServerMessage::UserJoined { username: username.clone(), online };
+        send_to_others(&self.contact, &username, joined).await;
+        Ok(RegisterResult::Success)
+    }
+
+    async fn deregister(&self, username: &str) {
+        let online = {
+            let mut guard = self.contact.lock().unwrap();
+            let Some(info) = guard.remove(username) else {
+                return;
+            };
+            info!(
+                username = %info.username,
+                peer_addr = %info.addr,
+                "User disconnected. Active connections: {}",
+                guard.len()
+            );
+            guard.len()
+        };
+
+        // 该用户已不在表中，直接通知剩下的所有人
+        let left = ServerMessage::UserLeft { username: username.to_string(), online };
+        send_to_others(&self.contact, username, left).await;
+
+        // 退出所有房间，空房间直接删除
+        let joined: Vec<String> = {
+            let mut rooms = self.rooms.lock().unwrap();
+            let joined = rooms
+                .iter_mut()
+                .filter_map(|(room, members)| members.remove(username).then(|| room.clone()))
+                .collect();
+            rooms.retain(|_, members| !members.is_empty());
+            joined
+        };
+        for room in joined {
+            let left = ServerMessage::RoomUserLeft { room: room.clone(), username: username.to_string() };
+            send_to_room(&self.contact, &self.rooms, &room, username, left).await;
+        }
+    }
+
+    async fn broadcast(&self, from: &str, content: String) {
+        broadcast_to_others(&self.contact, from, content).await;
+    }
+
+    async fn whisper(&self, from: &str, to: &str, content: String) {
+        let reply = if send_private(&self.contact, from, to, content.clone()).await {
+            ServerMessage::PrivateMessageSent { to: to.to_string(), content }
+        } else {
+            ServerMessage::error(format!("User '{to}' not found."))
+        };
+
+        // 回显或错误都发回给发送者
+        self.send_to(from, reply).await;
+    }
+
+    async fn users(&self) -> anyhow::Result<Vec<String>> {
+        let mut users: Vec<String> = self.contact.lock().unwrap().keys().cloned().collect();
+        users.sort();
+        Ok(users)
+    }
+
+    async fn join_room(&self, username: &str, room: &str) {
+        let members = {
+            let mut rooms = self.rooms.lock().unwrap();
+            let members = rooms.entry(room.to_string()).or_default();
+            members.insert(username.to_string()).then(|| members.iter().cloned().collect())
+        };
+
+        let Some(members) = members else {
+            self.send_to(username, ServerMessage::error(format!("You are already in #{room}."))).await;
+            return;
+        };
+        let joined = ServerMessage::RoomUserJoined { room: room.to_string(), username: username.to_string() };
+        send_to_room(&self.contact, &self.rooms, room, username, joined).await;
+        self.send_to(username, ServerMessage::JoinedRoom { room: room.to_string(), members }).await;
+    }
+
+    async fn part_room(&self, username: &str, room: &str) {
+        let was_member = {
+            let mut rooms = self.rooms.lock().unwrap();
+            match rooms.get_mut(room) {
+                Some(members) => {
+                    let removed = members.remove(username);
+                    if members.is_empty() {
+                        rooms.remove(room);
+                    }
+                    removed
+                }
+                None => false,
+            }
+        };
+
+        if !was_member {
+            self.send_to(username, ServerMessage::error(format!("You are not in #{room}."))).await;
+            return;
+        }
+        let left = ServerMessage::RoomUserLeft { room: room.to_string(), username: username.to_string() };
+        send_to_room(&self.contact, &self.rooms, room, username, left).await;
+        self.send_to(username, ServerMessage::LeftRoom { room: room.to_string() }).await;
+    }
+
+    async fn room_message(&self, from: &str, room: &str, content: String) {
+        let is_member = self.rooms
+            .lock()
+            .unwrap()
+            .get(room)
+            .is_some_and(|members| members.contains(from));
+        if !is_member {
+            self.send_to(from, ServerMessage::error(format!("You are not in #{room}."))).await;
+            return;
+        }
+
+        let msg = ServerMessage::RoomMessage {
+            room: room.to_string(),
+            from: from.to_string(),
+            content,
+        };
+        send_to_room(&self.contact, &self.rooms, room, from, msg).await;
+    }
+
+    async fn room_members(&self, room: &str) -> anyhow::Result<Option<Vec<String>>> {
+        let rooms = self.rooms.lock().unwrap();
+        Ok(rooms.get(room).map(|members| members.iter().cloned().collect()))
+    }
+
+    async fn rooms(&self) -> anyhow::Result<Vec<String>> {
+        let mut rooms: Vec<String> = self.rooms.lock().unwrap().keys().cloned().collect();
+        rooms.sort();
+        Ok(rooms)
+    }
+
+    async fn user(&self, username: &str) -> anyhow::Result<Option<UserInfo>> {
+        Ok(self.contact.lock().unwrap().get(username).map(user_info))
+    }
+
+    async fn clients(&self) -> anyhow::Result<Vec<UserInfo>> {
+        let mut clients: Vec<UserInfo> = self.contact.lock().unwrap().values().map(user_info).collect();
+        clients.sort_by(|a, b| a.username.cmp(&b.username));
+        Ok(clients)
+    }
+
+    async fn users_at(&self, ip: IpAddr) -> anyhow::Result<Vec<UserInfo>> {
+        let contact = self.contact.lock().unwrap();
+        Ok(contact.values().filter(|info| info.addr.ip() == Some(ip)).map(user_info).collect())
+    }
+
+    async fn notify(&self, username: &str, msg: ServerMessage) {
+        self.send_to(username, msg).await;
+    }
+
+    async fn kick(&self, username: &str, reason: String) {
+        if let Some(info) = self.contact.lock().unwrap().get(username) {
+            info.tx.kick(reason);
+        }
+    }
+}
+
+fn user_info(info: &ClientInfo) -> UserInfo {
+    UserInfo { username: info.username.clone(), addr: info.addr.clone(), role: info.role }
+}
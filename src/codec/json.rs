@@ -0,0 +1,189 @@
+// src/codec/json.rs
+
+// 长度前缀分帧的 JSON 协议 (v0.4)。
+//
+// 帧格式：| 长度 N (u32, 大端) | N 字节 UTF-8 JSON |
+
+use super::{ Codec, Decoded, SharedMessage, frame_too_large };
+use crate::protocol::{ DEFAULT_MAX_FRAME_SIZE, ServerMessage };
+use anyhow::{ Result, bail };
+use bytes::{ Buf, BufMut, BytesMut };
+
+/// 长度前缀的字节数
+const LEN_PREFIX: usize = 4;
+
+#[derive(Debug)]
+pub struct JsonCodec {
+    /// 客户端发来的单帧负载的最大字节数
+    max_frame_size: usize,
+}
+
+impl JsonCodec {
+    pub fn new() -> Self {
+        JsonCodec::with_max_frame_size(DEFAULT_MAX_FRAME_SIZE)
+    }
+
+    pub fn with_max_frame_size(max_frame_size: usize) -> Self {
+        JsonCodec { max_frame_size }
+    }
+}
+
+impl Default for JsonCodec {
+    fn default() -> Self {
+        JsonCodec::new()
+    }
+}
+
+impl Codec for JsonCodec {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn decode(&mut self, src: &mut BytesMut, reply: &mut BytesMut) -> Result<Option<Decoded>> {
+        if src.len() < LEN_PREFIX {
+            return Ok(None);
+        }
+
+        let len = u32::from_be_bytes([src[0], src[1], src[2], src[3]]) as usize;
+        // 先检查长度前缀，超限的帧不会被读进缓冲区
+        if len > self.max_frame_size {
+            let limit = self.max_frame_size;
+            self.encode(&frame_too_large(limit), reply)?;
+            bail!("Frame of {len} bytes exceeds the {limit} byte limit");
+        }
+        if src.len() < LEN_PREFIX + len {
+            // 提前预留空间，避免大帧反复扩容
+            src.reserve(LEN_PREFIX + len - src.len());
+            return Ok(None);
+        }
+
+        src.advance(LEN_PREFIX);
+        let payload = src.split_to(len);
+        Ok(
+            Some(match serde_json::from_slice(&payload) {
+                Ok(msg) => Decoded::Message(msg),
+                Err(e) => Decoded::Invalid(e.to_string()),
+            })
+        )
+    }
+
+    fn encode(&mut self, msg: &ServerMessage, dst: &mut BytesMut) -> Result<()> {
+        let payload = serde_json::to_vec(msg)?;
+        encode_frame(&payload, dst)
+    }
+
+    fn encode_shared(&mut self, msg: &SharedMessage, dst: &mut BytesMut) -> Result<()> {
+        encode_frame(msg.json()?, dst)
+    }
+}
+
+/// 发给客户端的帧只受长度前缀本身的限制：
+/// 一条接近 max_frame_size 的广播加上 type / from 等字段后会比上限略大，不能因此发不出去
+fn encode_frame(payload: &[u8], dst: &mut BytesMut) -> Result<()> {
+    let Ok(len) = u32::try_from(payload.len()) else {
+        bail!("Frame of {} bytes does not fit in the length prefix", payload.len());
+    };
+    dst.reserve(LEN_PREFIX + payload.len());
+    dst.put_u32(len);
+    dst.extend_from_slice(payload);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::ClientMessage;
+
+    fn frame(payload: &[u8]) -> Vec<u8> {
+        let mut frame = (payload.len() as u32).to_be_bytes().to_vec();
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    #[test]
+    fn round_trips_server_messages() {
+        let mut codec = JsonCodec::new();
+        let msg = ServerMessage::Disconnected { reason: "bye".to_string() };
+        let mut dst = BytesMut::new();
+        codec.encode(&msg, &mut dst).unwrap();
+        codec.encode_shared(&SharedMessage::new(msg.clone()), &mut dst).unwrap();
+
+        // 直接编码和共享编码的结果相同
+        let expected = frame(&serde_json::to_vec(&msg).unwrap());
+        assert_eq!(&dst[..expected.len()], expected);
+        assert_eq!(&dst[expected.len()..], expected);
+    }
+
+    #[test]
+    fn decodes_consecutive_frames() {
+        let mut codec = JsonCodec::new();
+        let mut src = BytesMut::from(&frame(br#"{"type":"Ping"}"#)[..]);
+        src.extend_from_slice(&frame(br#"{"type":"Broadcast","content":"hi"}"#));
+        let mut reply = BytesMut::new();
+
+        let first = codec.decode(&mut src, &mut reply).unwrap();
+        assert!(matches!(first, Some(Decoded::Message(ClientMessage::Ping))));
+        match codec.decode(&mut src, &mut reply).unwrap() {
+            Some(Decoded::Message(ClientMessage::Broadcast { content })) => {
+                assert_eq!(content, "hi");
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+        assert!(codec.decode(&mut src, &mut reply).unwrap().is_none());
+        assert!(src.is_empty() && reply.is_empty());
+    }
+
+    #[test]
+    fn waits_for_partial_prefix_and_body() {
+        let mut codec = JsonCodec::new();
+        let bytes = frame(br#"{"type":"Ping"}"#);
+        let mut src = BytesMut::new();
+        let mut reply = BytesMut::new();
+        // 长度前缀不完整、负载不完整时都等待更多数据，不消耗缓冲区
+        for (i, &byte) in bytes[..bytes.len() - 1].iter().enumerate() {
+            src.put_u8(byte);
+            assert!(codec.decode(&mut src, &mut reply).unwrap().is_none());
+            assert_eq!(src.len(), i + 1);
+        }
+        src.put_u8(bytes[bytes.len() - 1]);
+        let decoded = codec.decode(&mut src, &mut reply).unwrap();
+        assert!(matches!(decoded, Some(Decoded::Message(ClientMessage::Ping))));
+        assert!(src.is_empty());
+    }
+
+    #[test]
+    fn rejects_oversized_length_prefix() {
+        let mut codec = JsonCodec::with_max_frame_size(16);
+        // 只有长度前缀就足以拒绝，不等负载到达
+        let mut src = BytesMut::from(&17u32.to_be_bytes()[..]);
+        let mut reply = BytesMut::new();
+        assert!(codec.decode(&mut src, &mut reply).is_err());
+
+        let len = u32::from_be_bytes([reply[0], reply[1], reply[2], reply[3]]) as usize;
+        let notice: ServerMessage = serde_json::from_slice(&reply[LEN_PREFIX..]).unwrap();
+        assert_eq!(reply.len(), LEN_PREFIX + len);
+        assert!(matches!(notice, ServerMessage::Disconnected { .. }));
+
+        // 刚好等于上限的帧可以通过
+        let mut src = BytesMut::from(&frame(br#"{"type":"Ping"} "#)[..]);
+        assert!(codec.decode(&mut src, &mut BytesMut::new()).unwrap().is_some());
+    }
+
+    #[test]
+    fn malformed_json_keeps_connection() {
+        let mut codec = JsonCodec::new();
+        let mut src = BytesMut::from(&frame(b"{not json")[..]);
+        src.extend_from_slice(&frame(br#"{"type":"Shout"}"#));
+        src.extend_from_slice(&frame(br#"{"type":"Ping"}"#));
+        let mut reply = BytesMut::new();
+
+        // 坏帧被整帧跳过，后面的帧照常解码
+        for _ in 0..2 {
+            let decoded = codec.decode(&mut src, &mut reply).unwrap();
+            assert!(matches!(decoded, Some(Decoded::Invalid(_))));
+        }
+        let decoded = codec.decode(&mut src, &mut reply).unwrap();
+        assert!(matches!(decoded, Some(Decoded::Message(ClientMessage::Ping))));
+        assert!(reply.is_empty());
+    }
+}
@@ -0,0 +1,368 @@
+// src/codec/line.rs
+
+// 以换行符分隔的纯文本协议，适合 nc / telnet 直接连接。
+// 登录前收到的每一行都是用户名（或 `/login`、`/register`、`/resume` 开头的登录命令），
+// 登录后收到的每一行都是一条广播（以 `/` 开头的是命令）。任何时候发送 `/quit` 都会退出。
+
+use super::{ Codec, Decoded, SharedMessage, frame_too_large };
+use crate::protocol::{ ClientMessage, DEFAULT_MAX_FRAME_SIZE, ServerMessage };
+use crate::utils::color::{ GREEN, RED, RESET };
+use anyhow::{ Result, bail };
+use bytes::{ BufMut, BytesMut };
+
+const LOGIN_PROMPT: &[u8] = b"Enter username: \n";
+
+#[derive(Debug)]
+pub struct LineCodec {
+    /// 是否已经登录成功（发出过 Welcome）
+    logged_in: bool,
+    /// 单行的最大字节数（不含换行符）
+    max_line_length: usize,
+    /// 缓冲区开头已经确认没有换行符的字节数，下次只需扫描新读到的部分
+    scanned: usize,
+}
+
+impl LineCodec {
+    pub fn new() -> Self {
+        LineCodec::with_max_frame_size(DEFAULT_MAX_FRAME_SIZE)
+    }
+
+    pub fn with_max_frame_size(max_line_length: usize) -> Self {
+        LineCodec { logged_in: false, max_line_length, scanned: 0 }
+    }
+}
+
+impl Default for LineCodec {
+    fn default() -> Self {
+        LineCodec::new()
+    }
+}
+
+impl Codec for LineCodec {
+    fn name(&self) -> &'static str {
+        "line"
+    }
+
+    fn greeting(&mut self, dst: &mut BytesMut) {
+        dst.extend_from_slice(LOGIN_PROMPT);
+    }
+
+    // nc / telnet 没法回复 Pong
+    fn supports_heartbeat(&self) -> bool {
+        false
+    }
+
+    fn decode(&mut self, src: &mut BytesMut, reply: &mut BytesMut) -> Result<Option<Decoded>> {
+        loop {
+            // 一直没有换行符时缓冲区最多增长到上限，不会无限占用内存。
+            // 上次扫描过的部分不再重扫，否则一行分多次到达时是平方级的开销
+            let start = self.scanned.min(src.len());
+            let newline = src[start..].iter().position(|b| *b == b'\n').map(|pos| start + pos);
+            let length = newline.unwrap_or(src.len());
+            if length > self.max_line_length {
+                let limit = self.max_line_length;
+                self.encode(&frame_too_large(limit), reply)?;
+                bail!("Line exceeds the {limit} byte limit");
+            }
+            let Some(pos) = newline else {
+                self.scanned = src.len();
+                return Ok(None);
+            };
+
+            let line = src.split_to(pos + 1);
+            self.scanned = 0;
+            let Ok(text) = std::str::from_utf8(&line) else {
+                return Ok(Some(Decoded::Invalid("line is not valid UTF-8".to_string())));
+            };
+            let text = text.trim();
+
+            if text == "/quit" {
+                return Ok(Some(Decoded::Message(ClientMessage::Quit)));
+            }
+            if !self.logged_in {
+                return Ok(Some(Decoded::Message(parse_login(text))));
+            }
+            // 登录后忽略空行
+            if text.is_empty() {
+                continue;
+            }
+            return Ok(
+                Some(Decoded::Message(ClientMessage::Broadcast { content: text.to_string() }))
+            );
+        }
+    }
+
+    fn encode(&mut self, msg: &ServerMessage, dst: &mut BytesMut) -> Result<()> {
+        match msg {
+            ServerMessage::Welcome { .. } => self.logged_in = true,
+            // 行协议不参与心跳，不输出任何内容
+            ServerMessage::Ping | ServerMessage::Pong => return Ok(()),
+            _ => {}
+        }
+        let text = render(msg);
+        dst.reserve(text.len() + 1);
+        dst.extend_from_slice(text.as_bytes());
+        dst.put_u8(b'\n');
+
+        // 登录失败后重新提示输入用户名
+        if !self.logged_in && matches!(msg, ServerMessage::Error { .. }) {
+            dst.extend_from_slice(LOGIN_PROMPT);
+        }
+        Ok(())
+    }
+
+    fn encode_shared(&mut self, msg: &SharedMessage, dst: &mut BytesMut) -> Result<()> {
+        // 登录前后的渲染结果不同（登录提示），只有登录后的消息走缓存
+        if !self.logged_in || matches!(msg.message(), ServerMessage::Welcome { .. }) {
+            return self.encode(msg.message(), dst);
+        }
+        let text = msg.text(|msg| {
+            let mut text = render(msg);
+            text.push('\n');
+            text
+        });
+        dst.extend_from_slice(text);
+        Ok(())
+    }
+}
+
+/// 登录前的一行：`/register <name> <password>`、`/login <name> <password>`、`/resume <token>`
+/// 或者直接是用户名（访客）。
+/// 密码是用户名之后的全部内容，可以包含空格
+fn parse_login(text: &str) -> ClientMessage {
+    let credentials = |args: &str| {
+        let (username, password) = args.trim().split_once(char::is_whitespace).unwrap_or((args.trim(), ""));
+        (username.to_string(), password.trim().to_string())
+    };
+    if let Some(args) = text.strip_prefix("/register ") {
+        let (username, password) = credentials(args);
+        return ClientMessage::Register { username, password };
+    }
+    if let Some(token) = text.strip_prefix("/resume ") {
+        return ClientMessage::Resume { token: token.trim().to_string() };
+    }
+    if let Some(args) = text.strip_prefix("/login ") {
+        let (username, password) = credentials(args);
+        return ClientMessage::Login { username, password: Some(password) };
+    }
+    ClientMessage::Login { username: text.to_string(), password: None }
+}
+
+/// 把一条服务器消息渲染成一行文本（不含换行符）
+fn render(msg: &ServerMessage) -> String {
+    match msg {
+        ServerMessage::Welcome { username } => format!("{GREEN}Welcome, {username}!{RESET}"),
+        ServerMessage::SessionToken { token, grace_secs } => {
+            format!(
+                "[Server] If you get disconnected, reconnect within {grace_secs}s and send `/resume {token}` to continue."
+            )
+        }
+        ServerMessage::UserJoined { username, online } => {
+            format!("[Server] {username} has joined. ({online} online)")
+        }
+        ServerMessage::UserLeft { username, online } => {
+            format!("[Server] {username} has left. ({online} online)")
+        }
+        ServerMessage::NewMessage { from, content } => format!("[{from}]: {content}"),
+        ServerMessage::PrivateMessage { from, content } => {
+            format!("[Private from {from}] {content}")
+        }
+        ServerMessage::PrivateMessageSent { to, content } => {
+            format!("[Private to {to}] {content}")
+        }
+        ServerMessage::UserList { users, guests } => {
+            let users: Vec<String> = users
+                .iter()
+                .map(|user| {
+                    if guests.contains(user) { format!("{user} (guest)") } else { user.clone() }
+                })
+                .collect();
+            format!("[Server] Users online: {}", users.join(", "))
+        }
+        ServerMessage::JoinedRoom { room, members } => {
+            format!("[Server] You joined #{room}. Members: {}", members.join(", "))
+        }
+        ServerMessage::LeftRoom { room } => format!("[Server] You left #{room}."),
+        ServerMessage::RoomUserJoined { room, username } => {
+            format!("[#{room}] {username} has joined.")
+        }
+        ServerMessage::RoomUserLeft { room, username } => {
+            format!("[#{room}] {username} has left.")
+        }
+        ServerMessage::RoomMessage { room, from, content } => {
+            format!("[#{room}] [{from}]: {content}")
+        }
+        ServerMessage::RoomMembers { room, members } => {
+            format!("[Server] Members of #{room}: {}", members.join(", "))
+        }
+        ServerMessage::RoomList { rooms } if rooms.is_empty() => {
+            "[Server] No rooms yet. Use /join <room> to create one.".to_string()
+        }
+        ServerMessage::RoomList { rooms } => {
+            let rooms: Vec<String> = rooms.iter().map(|room| format!("#{room}")).collect();
+            format!("[Server] Rooms: {}", rooms.join(", "))
+        }
+        ServerMessage::Info { msg } => format!("[Server] {msg}"),
+        ServerMessage::Error { msg } => format!("{RED}{msg}{RESET}"),
+        ServerMessage::MessagesDropped { count } => {
+            format!("{RED}[Server] {count} message(s) were dropped because you are reading too slowly.{RESET}")
+        }
+        ServerMessage::Disconnected { reason } => format!("{RED}{reason}{RESET}"),
+        ServerMessage::Ping => "PING".to_string(),
+        ServerMessage::Pong => "PONG".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{ Value, json };
+
+    /// 解码下一条消息，转成 JSON 方便比较；无效的行写作 `{"invalid": 原因}`
+    fn next(codec: &mut LineCodec, src: &mut BytesMut) -> Option<Value> {
+        let mut reply = BytesMut::new();
+        let decoded = codec.decode(src, &mut reply).unwrap();
+        assert!(reply.is_empty());
+        match decoded? {
+            Decoded::Message(msg) => Some(serde_json::to_value(msg).unwrap()),
+            Decoded::Invalid(reason) => Some(json!({ "invalid": reason })),
+            Decoded::Closed => panic!("line codec never reports Closed"),
+        }
+    }
+
+    fn welcome(codec: &mut LineCodec) {
+        let welcome = ServerMessage::Welcome { username: "alice".to_string() };
+        codec.encode(&welcome, &mut BytesMut::new()).unwrap();
+    }
+
+    fn logged_in() -> LineCodec {
+        let mut codec = LineCodec::new();
+        welcome(&mut codec);
+        codec
+    }
+
+    fn broadcast(content: &str) -> Option<Value> {
+        Some(json!({ "type": "Broadcast", "content": content }))
+    }
+
+    fn login_as(username: &str) -> Option<Value> {
+        Some(json!({ "type": "Login", "username": username }))
+    }
+
+    fn login(text: &str) -> Value {
+        serde_json::to_value(parse_login(text)).unwrap()
+    }
+
+    #[test]
+    fn parses_login_lines() {
+        assert_eq!(login("alice"), json!({ "type": "Login", "username": "alice" }));
+        assert_eq!(
+            login("/login alice  correct horse "),
+            json!({ "type": "Login", "username": "alice", "password": "correct horse" })
+        );
+        assert_eq!(
+            login("/register  bob secret123"),
+            json!({ "type": "Register", "username": "bob", "password": "secret123" })
+        );
+        assert_eq!(login("/resume  abc.def "), json!({ "type": "Resume", "token": "abc.def" }));
+        // 没有参数的命令按用户名处理，由用户名规则拒绝
+        assert_eq!(login("/login"), json!({ "type": "Login", "username": "/login" }));
+    }
+
+    #[test]
+    fn first_line_is_login() {
+        let mut codec = LineCodec::new();
+        let mut src = BytesMut::from("alice\r\n/w bob hi\n");
+        assert_eq!(next(&mut codec, &mut src), login_as("alice"));
+        // 还没收到 Welcome，下一行仍然按登录处理
+        assert_eq!(next(&mut codec, &mut src), login_as("/w bob hi"));
+        assert_eq!(next(&mut codec, &mut src), None);
+    }
+
+    #[test]
+    fn lines_after_login_are_broadcasts() {
+        let mut codec = logged_in();
+        let mut src = BytesMut::from("\n  \r\n  hello world \r\n\n/list\n");
+        // 空行被跳过，首尾空白被去掉
+        assert_eq!(next(&mut codec, &mut src), broadcast("hello world"));
+        assert_eq!(next(&mut codec, &mut src), broadcast("/list"));
+        assert_eq!(next(&mut codec, &mut src), None);
+        assert!(src.is_empty());
+    }
+
+    #[test]
+    fn quit_works_before_and_after_login() {
+        let quit = Some(json!({ "type": "Quit" }));
+        assert_eq!(next(&mut LineCodec::new(), &mut BytesMut::from("/quit\r\n")), quit);
+        assert_eq!(next(&mut logged_in(), &mut BytesMut::from(" /quit \n")), quit);
+    }
+
+    #[test]
+    fn invalid_utf8_skips_the_line() {
+        let mut codec = logged_in();
+        let mut src = BytesMut::from(&b"caf\xe9\nok\n"[..]);
+        let invalid = json!({ "invalid": "line is not valid UTF-8" });
+        assert_eq!(next(&mut codec, &mut src), Some(invalid));
+        assert_eq!(next(&mut codec, &mut src), broadcast("ok"));
+    }
+
+    #[test]
+    fn line_split_across_reads() {
+        let mut codec = logged_in();
+        let mut src = BytesMut::new();
+        for chunk in ["hel", "lo", " wor", "ld\nsec", "ond\n"] {
+            src.extend_from_slice(chunk.as_bytes());
+            if let Some(msg) = next(&mut codec, &mut src) {
+                assert_eq!(Some(msg), broadcast("hello world"));
+                break;
+            }
+        }
+        // 第一行之后剩下的部分要从头重新扫描
+        assert_eq!(&src[..], b"sec");
+        src.extend_from_slice(b"ond\n");
+        assert_eq!(next(&mut codec, &mut src), broadcast("second"));
+    }
+
+    #[test]
+    fn too_long_line_is_refused() {
+        let mut codec = LineCodec::with_max_frame_size(16);
+        welcome(&mut codec);
+        // 正好 16 字节可以
+        let mut src = BytesMut::from("0123456789abcdef\n");
+        assert!(next(&mut codec, &mut src).is_some());
+
+        // 没有换行符也会在超过上限时断开，而不是一直缓冲
+        let mut src = BytesMut::from("0123456789");
+        assert_eq!(next(&mut codec, &mut src), None);
+        src.extend_from_slice(b"abcdefg");
+        let mut reply = BytesMut::new();
+        let err = codec.decode(&mut src, &mut reply).unwrap_err();
+        assert_eq!(err.to_string(), "Line exceeds the 16 byte limit");
+        let expected = format!("{RED}Message exceeds the 16 byte limit.{RESET}\n");
+        assert_eq!(&reply[..], expected.as_bytes());
+    }
+
+    #[test]
+    fn encodes_lines() {
+        let mut codec = LineCodec::new();
+        let mut dst = BytesMut::new();
+        codec.greeting(&mut dst);
+        // 登录失败后重新提示
+        codec.encode(&ServerMessage::error("taken"), &mut dst).unwrap();
+        let expected = format!("Enter username: \n{RED}taken{RESET}\nEnter username: \n");
+        assert_eq!(&dst[..], expected.as_bytes());
+
+        let mut dst = BytesMut::new();
+        let welcome = ServerMessage::Welcome { username: "alice".to_string() };
+        codec.encode(&welcome, &mut dst).unwrap();
+        codec.encode(&ServerMessage::Ping, &mut dst).unwrap();
+        codec.encode(&ServerMessage::error("oops"), &mut dst).unwrap();
+        let shared = SharedMessage::new(ServerMessage::NewMessage {
+            from: "bob".to_string(),
+            content: "hi".to_string(),
+        });
+        codec.encode_shared(&shared, &mut dst).unwrap();
+        let expected = format!("{GREEN}Welcome, alice!{RESET}\n{RED}oops{RESET}\n[bob]: hi\n");
+        assert_eq!(&dst[..], expected.as_bytes());
+    }
+}
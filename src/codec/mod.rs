@@ -0,0 +1,257 @@
+// src/codec/mod.rs
+
+// 线路协议抽象：Codec 负责 “字节 <-> ClientMessage / ServerMessage” 的转换，
+// Transport 负责 socket 的读写与缓冲。认证、广播等业务逻辑只和 ClientMessage / ServerMessage 打交道，
+// 因此新增一种分帧方式只需要实现 Codec，不需要改动业务代码；
+// 不同协议的客户端共享同一个聊天室，可以互相聊天。
+
+pub mod json;
+pub mod line;
+pub mod shared;
+pub mod websocket;
+
+pub use json::JsonCodec;
+pub use line::LineCodec;
+pub use shared::SharedMessage;
+pub use websocket::WebSocketCodec;
+
+use crate::protocol::{ ClientMessage, ServerMessage };
+use anyhow::Result;
+use bytes::{ Buf, BytesMut };
+use std::io;
+use std::time::{ Duration, Instant };
+use tokio::io::{ AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt };
+
+/// `Codec::decode` 的解码结果
+#[derive(Debug)]
+pub enum Decoded {
+    /// 一条完整的客户端消息
+    Message(ClientMessage),
+    /// 帧边界完好但内容无法解析，回复错误后连接可以继续
+    Invalid(String),
+    /// 对端按协议主动关闭了连接（如 WebSocket Close 帧）
+    Closed,
+}
+
+/// 一种线路协议的编解码器，每个连接持有一个实例（可以保存握手、登录等状态）
+pub trait Codec: Send + 'static {
+    /// 协议名称，用于日志
+    fn name(&self) -> &'static str;
+
+    /// 连接建立后立即发送给客户端的字节（如登录提示）
+    fn greeting(&mut self, _dst: &mut BytesMut) {}
+
+    /// 从 `src` 中解码下一条消息，数据不够时返回 `Ok(None)`。
+    ///
+    /// 协议层面的自动回复（握手响应、Pong、Close 帧等）写入 `reply`，由 Transport 负责发出。
+    /// 返回 `Err` 表示连接无法继续，`reply` 中的内容仍会尽量发出。
+    fn decode(&mut self, src: &mut BytesMut, reply: &mut BytesMut) -> Result<Option<Decoded>>;
+
+    /// 把一条服务器消息编码后追加到 `dst`
+    fn encode(&mut self, msg: &ServerMessage, dst: &mut BytesMut) -> Result<()>;
+
+    /// 与 `encode` 相同，但可以复用 `SharedMessage` 中缓存的编码结果，避免每个接收者都序列化一次
+    fn encode_shared(&mut self, msg: &SharedMessage, dst: &mut BytesMut) -> Result<()> {
+        self.encode(msg.message(), dst)
+    }
+
+    /// 客户端能否回应服务器的心跳 `Ping`。不能回应的协议（如给人直接用的行协议）不发心跳，
+    /// 只受空闲超时约束
+    fn supports_heartbeat(&self) -> bool {
+        true
+    }
+
+    /// 连接在握手之前就被拒绝（如服务器已满）时，写给客户端的说明
+    fn refuse(&mut self, reason: &str, dst: &mut BytesMut) {
+        let _ = self.encode(&ServerMessage::Disconnected { reason: reason.to_string() }, dst);
+    }
+
+    /// 连接结束前写入的收尾字节（如 WebSocket Close 帧）
+    fn close(&mut self, _dst: &mut BytesMut) {}
+}
+
+/// 客户端发来的帧超过上限时，断开前回复给客户端的通知
+fn frame_too_large(limit: usize) -> ServerMessage {
+    ServerMessage::Disconnected { reason: format!("Message exceeds the {limit} byte limit.") }
+}
+
+/// 一条连接：socket + 读写缓冲区 + 编解码器
+pub struct Transport<S, C> {
+    stream: S,
+    codec: C,
+    read_buf: BytesMut,
+    write_buf: BytesMut,
+    /// 最近一次从 socket 读到数据的时间（包括心跳回应和协议控制帧）
+    last_received: Instant,
+    /// 一次 flush 的最长时间，对端长时间不读（半开连接）时写操作不会永远挂住
+    write_timeout: Option<Duration>,
+}
+
+impl<S, C> Transport<S, C> where S: AsyncRead + AsyncWrite + Unpin, C: Codec {
+    pub fn new(stream: S, mut codec: C) -> Self {
+        let mut write_buf = BytesMut::new();
+        codec.greeting(&mut write_buf);
+        Transport {
+            stream,
+            codec,
+            read_buf: BytesMut::with_capacity(4096),
+            write_buf,
+            last_received: Instant::now(),
+            write_timeout: None,
+        }
+    }
+
+    pub fn set_write_timeout(&mut self, timeout: Option<Duration>) {
+        self.write_timeout = timeout;
+    }
+
+    pub fn protocol(&self) -> &'static str {
+        self.codec.name()
+    }
+
+    pub fn supports_heartbeat(&self) -> bool {
+        self.codec.supports_heartbeat()
+    }
+
+    /// 最近一次收到对端数据的时间，用来判断对端是否还活着
+    pub fn last_received(&self) -> Instant {
+        self.last_received
+    }
+
+    /// 读取下一条客户端消息。
+    ///
+    /// 返回 `Ok(None)` 表示连接已关闭；内层 `Err` 是可恢复的解析错误（回复错误后可以继续）。
+    /// 未解码的字节保存在内部缓冲区里，所以该方法可以安全地用在 `tokio::select!` 中。
+    pub async fn recv(&mut self) -> Result<Option<Result<ClientMessage, String>>> {
+        loop {
+            let decoded = match self.codec.decode(&mut self.read_buf, &mut self.write_buf) {
+                Ok(decoded) => decoded,
+                Err(e) => {
+                    let _ = self.flush().await;
+                    return Err(e);
+                }
+            };
+
+            match decoded {
+                Some(Decoded::Message(msg)) => {
+                    return Ok(Some(Ok(msg)));
+                }
+                Some(Decoded::Invalid(reason)) => {
+                    return Ok(Some(Err(reason)));
+                }
+                Some(Decoded::Closed) => {
+                    self.flush().await?;
+                    return Ok(None);
+                }
+                None => {
+                    // 解码过程中可能产生了协议回复（握手响应、Pong 等），读之前先发出去
+                    self.flush().await?;
+                    if self.stream.read_buf(&mut self.read_buf).await? == 0 {
+                        return Ok(None);
+                    }
+                    self.last_received = Instant::now();
+                }
+            }
+        }
+    }
+
+    /// 编码并发送一条服务器消息
+    pub async fn send(&mut self, msg: &ServerMessage) -> Result<()> {
+        self.codec.encode(msg, &mut self.write_buf)?;
+        self.flush().await
+    }
+
+    /// 发送一条共享消息（来自客户端队列），尽量复用已经缓存的编码结果
+    pub async fn send_shared(&mut self, msg: &SharedMessage) -> Result<()> {
+        self.feed_shared(msg)?;
+        self.flush().await
+    }
+
+    /// 只把共享消息编码进写缓冲区，不写 socket。
+    /// 连续 feed 多条后调用一次 `flush`，整批消息通常只需要一次 write 系统调用
+    pub fn feed_shared(&mut self, msg: &SharedMessage) -> Result<()> {
+        self.codec.encode_shared(msg, &mut self.write_buf)
+    }
+
+    /// 发送协议层面的收尾字节并关闭写端
+    pub async fn shutdown(&mut self) -> Result<()> {
+        self.codec.close(&mut self.write_buf);
+        self.flush().await?;
+        self.stream.shutdown().await?;
+        Ok(())
+    }
+
+    /// 把写缓冲区的内容全部写入 socket，超过 write_timeout 返回 `TimedOut` 错误。
+    /// `write_buf` 只会在写成功后前进，所以中途被取消也不会写出半个帧。
+    pub async fn flush(&mut self) -> Result<()> {
+        if !self.write_buf.has_remaining() {
+            return Ok(());
+        }
+        let timeout = self.write_timeout;
+        let write = async {
+            while self.write_buf.has_remaining() {
+                // 和 write_all 一样，写入 0 字节说明对端已经不再接收，继续循环只会空转
+                if self.stream.write_buf(&mut self.write_buf).await? == 0 {
+                    return Err(io::Error::from(io::ErrorKind::WriteZero));
+                }
+            }
+            self.stream.flush().await
+        };
+        match timeout {
+            Some(timeout) => {
+                tokio::time::timeout(timeout, write).await.map_err(|_| {
+                    io::Error::new(io::ErrorKind::TimedOut, "peer stopped reading")
+                })??
+            }
+            None => write.await?,
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::pin::Pin;
+    use std::task::{ Context, Poll };
+    use tokio::io::ReadBuf;
+
+    /// 永远只接受 0 字节的 socket
+    struct ZeroWriter;
+
+    impl AsyncRead for ZeroWriter {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            _buf: &mut ReadBuf<'_>
+        ) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    impl AsyncWrite for ZeroWriter {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            _buf: &[u8]
+        ) -> Poll<io::Result<usize>> {
+            Poll::Ready(Ok(0))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[tokio::test]
+    async fn flush_fails_on_zero_write() {
+        let mut transport = Transport::new(ZeroWriter, JsonCodec::new());
+        let err = transport.send(&ServerMessage::Ping).await.unwrap_err();
+        let err = err.downcast::<io::Error>().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::WriteZero);
+    }
+}
@@ -0,0 +1,61 @@
+// src/codec/shared.rs
+
+// 广播时在所有接收者之间共享的消息。
+//
+// 一条广播只构造一次 ServerMessage，放进 Arc 后分发给每个接收者的队列；
+// 编码结果按线路格式缓存（JSON 负载由 JSON 和 WebSocket 协议共用，行文本由行协议使用），
+// 所以无论有多少接收者，每种格式最多只序列化一次，后续接收者只是复制缓存好的字节。
+
+use crate::protocol::ServerMessage;
+use anyhow::Result;
+use bytes::Bytes;
+use std::sync::{ Arc, OnceLock };
+
+#[derive(Debug)]
+pub struct SharedMessage {
+    msg: ServerMessage,
+    /// 序列化后的 JSON
+    json: OnceLock<Bytes>,
+    /// 行协议渲染出的文本（含换行符）
+    text: OnceLock<Bytes>,
+}
+
+impl SharedMessage {
+    pub fn new(msg: ServerMessage) -> Arc<Self> {
+        Arc::new(SharedMessage {
+            msg,
+            json: OnceLock::new(),
+            text: OnceLock::new(),
+        })
+    }
+
+    pub fn message(&self) -> &ServerMessage {
+        &self.msg
+    }
+
+    /// 序列化后的 JSON，第一次调用时生成
+    pub fn json(&self) -> Result<&Bytes> {
+        if let Some(json) = self.json.get() {
+            return Ok(json);
+        }
+        let json = Bytes::from(serde_json::to_vec(&self.msg)?);
+        // 并发时可能有别的线程先写入，两者内容相同，用哪一份都可以
+        Ok(self.json.get_or_init(|| json))
+    }
+
+    /// 消息的大致字节数（JSON 编码后的长度），客户端队列用它按字节限制积压的数据量
+    pub fn size(&self) -> usize {
+        self.json().map_or(0, |json| json.len())
+    }
+
+    /// 用 `render` 渲染出的文本，第一次调用时生成
+    pub fn text(&self, render: impl FnOnce(&ServerMessage) -> String) -> &Bytes {
+        self.text.get_or_init(|| Bytes::from(render(&self.msg)))
+    }
+}
+
+impl From<ServerMessage> for Arc<SharedMessage> {
+    fn from(msg: ServerMessage) -> Self {
+        SharedMessage::new(msg)
+    }
+}
@@ -0,0 +1,787 @@
+// src/codec/websocket.rs
+
+// RFC 6455 WebSocket 服务端实现：HTTP/1.1 Upgrade 握手 + 数据帧编解码。
+// 每条 WebSocket 消息（文本帧或二进制帧）承载一条 JSON 协议消息（见 protocol.rs），
+// 浏览器可以直接 `JSON.stringify` / `JSON.parse`，不需要长度前缀。
+
+use super::{ Codec, Decoded, SharedMessage, frame_too_large };
+use crate::protocol::{ DEFAULT_MAX_FRAME_SIZE, ServerMessage };
+use anyhow::{ Result, bail };
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use bytes::{ Buf, BufMut, BytesMut };
+use sha1::{ Digest, Sha1 };
+use tracing::debug;
+
+/// 握手请求（请求行 + 所有头部）的最大字节数
+pub const MAX_HANDSHAKE_SIZE: usize = 8 * 1024;
+
+/// RFC 6455 规定的固定 GUID，用于计算 Sec-WebSocket-Accept
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xa;
+
+/// 关闭帧中的状态码 (RFC 6455 §7.4.1)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CloseCode(pub u16);
+
+impl CloseCode {
+    pub const NORMAL: CloseCode = CloseCode(1000);
+    pub const PROTOCOL_ERROR: CloseCode = CloseCode(1002);
+    pub const INVALID_PAYLOAD: CloseCode = CloseCode(1007);
+    pub const MESSAGE_TOO_BIG: CloseCode = CloseCode(1009);
+
+    /// 能否出现在 Close 帧里。1004 / 1005 / 1006 / 1015 是保留值，不能发送；
+    /// 3000-4999 留给库和应用自定义，其余未分配的值一律视为无效
+    pub fn is_valid(self) -> bool {
+        matches!(self.0, 1000..=1003 | 1007..=1014 | 3000..=4999)
+    }
+}
+
+/// 解析出来的一个原始帧
+struct Frame {
+    fin: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub struct WebSocketCodec {
+    /// 是否已经完成 HTTP Upgrade 握手
+    handshake_done: bool,
+    /// 正在重组的分片消息：(首帧 opcode, 已收到的负载)
+    fragments: Option<(u8, Vec<u8>)>,
+    close_sent: bool,
+    /// 客户端发来的单条消息（分片重组后）的最大字节数
+    max_message_size: usize,
+}
+
+impl WebSocketCodec {
+    pub fn new() -> Self {
+        WebSocketCodec::with_max_frame_size(DEFAULT_MAX_FRAME_SIZE)
+    }
+
+    pub fn with_max_frame_size(max_message_size: usize) -> Self {
+        WebSocketCodec {
+            handshake_done: false,
+            fragments: None,
+            close_sent: false,
+            max_message_size,
+        }
+    }
+
+    /// 处理 HTTP Upgrade 请求。请求不完整时返回 `Ok(false)`
+    fn handshake(&mut self, src: &mut BytesMut, reply: &mut BytesMut) -> Result<bool> {
+        let Some(end) = src.windows(4).position(|w| w == b"\r\n\r\n") else {
+            if src.len() > MAX_HANDSHAKE_SIZE {
+                reject(reply, "431 Request Header Fields Too Large");
+                bail!("WebSocket handshake request too large");
+            }
+            return Ok(false);
+        };
+
+        let request = src.split_to(end + 4);
+        let request = String::from_utf8_lossy(&request);
+
+        let key = match parse_upgrade_request(&request) {
+            Ok(key) => key,
+            Err(HandshakeError::UnsupportedVersion) => {
+                reply.extend_from_slice(
+                    b"HTTP/1.1 426 Upgrade Required\r\nSec-WebSocket-Version: 13\r\nContent-Length: 0\r\n\r\n"
+                );
+                bail!("Unsupported WebSocket version");
+            }
+            Err(HandshakeError::Invalid(reason)) => {
+                reject(reply, "400 Bad Request");
+                bail!("Invalid WebSocket handshake: {reason}");
+            }
+        };
+
+        let response = format!(
+            "HTTP/1.1 101 Switching Protocols\r\n\
+             Upgrade: websocket\r\n\
+             Connection: Upgrade\r\n\
+             Sec-WebSocket-Accept: {}\r\n\r\n",
+            accept_key(&key)
+        );
+        reply.extend_from_slice(response.as_bytes());
+        self.handshake_done = true;
+        Ok(true)
+    }
+
+    /// 协议错误：写入带状态码的 Close 帧，然后返回错误
+    fn fail<T>(&mut self, reply: &mut BytesMut, code: CloseCode, reason: &str) -> Result<T> {
+        self.write_close(reply, code, reason);
+        bail!("WebSocket protocol error ({}): {}", code.0, reason)
+    }
+
+    /// 消息超过上限：先用一条 Disconnected 告诉客户端原因，再以 1009 关闭
+    fn too_big<T>(&mut self, reply: &mut BytesMut) -> Result<T> {
+        if let Ok(payload) = serde_json::to_vec(&frame_too_large(self.max_message_size)) {
+            encode_frame(OP_TEXT, &payload, reply);
+        }
+        self.fail(reply, CloseCode::MESSAGE_TOO_BIG, "message too big")
+    }
+
+    fn write_close(&mut self, dst: &mut BytesMut, code: CloseCode, reason: &str) {
+        if self.close_sent {
+            return;
+        }
+        let mut payload = Vec::with_capacity(2 + reason.len());
+        payload.extend_from_slice(&code.0.to_be_bytes());
+        // 控制帧负载最多 125 字节
+        payload.extend_from_slice(&reason.as_bytes()[..reason.len().min(123)]);
+        encode_frame(OP_CLOSE, &payload, dst);
+        self.close_sent = true;
+    }
+
+    fn finish_message(&mut self, payload: Vec<u8>, reply: &mut BytesMut) -> Result<Decoded> {
+        // 文本帧和二进制帧都按 JSON 解析；文本帧必须是合法 UTF-8
+        if std::str::from_utf8(&payload).is_err() {
+            return self.fail(reply, CloseCode::INVALID_PAYLOAD, "message is not valid UTF-8");
+        }
+        Ok(match serde_json::from_slice(&payload) {
+            Ok(msg) => Decoded::Message(msg),
+            Err(e) => Decoded::Invalid(e.to_string()),
+        })
+    }
+}
+
+impl Default for WebSocketCodec {
+    fn default() -> Self {
+        WebSocketCodec::new()
+    }
+}
+
+impl Codec for WebSocketCodec {
+    fn name(&self) -> &'static str {
+        "websocket"
+    }
+
+    fn decode(&mut self, src: &mut BytesMut, reply: &mut BytesMut) -> Result<Option<Decoded>> {
+        if !self.handshake_done && !self.handshake(src, reply)? {
+            return Ok(None);
+        }
+
+        loop {
+            let frame = match parse_frame(src, self.max_message_size) {
+                Ok(Some(frame)) => frame,
+                Ok(None) => {
+                    return Ok(None);
+                }
+                Err((CloseCode::MESSAGE_TOO_BIG, _)) => {
+                    return self.too_big(reply);
+                }
+                Err((code, reason)) => {
+                    return self.fail(reply, code, reason);
+                }
+            };
+
+            match frame.opcode {
+                OP_PING => {
+                    encode_frame(OP_PONG, &frame.payload, reply);
+                }
+                OP_PONG => {}
+                OP_CLOSE => {
+                    // 负载要么为空，要么以两字节的状态码开头
+                    let code = match frame.payload[..] {
+                        [] => None,
+                        [_] => {
+                            let reason = "truncated close code";
+                            return self.fail(reply, CloseCode::PROTOCOL_ERROR, reason);
+                        }
+                        [high, low, ..] => Some(CloseCode(u16::from_be_bytes([high, low]))),
+                    };
+                    if code.is_some_and(|code| !code.is_valid()) {
+                        return self.fail(reply, CloseCode::PROTOCOL_ERROR, "invalid close code");
+                    }
+                    debug!(code = ?code, "WebSocket close frame received");
+                    // 按协议回一个 Close 帧，完成关闭握手；只回状态码，不带原因
+                    if !self.close_sent {
+                        let echo = code.map_or(Vec::new(), |code| code.0.to_be_bytes().to_vec());
+                        encode_frame(OP_CLOSE, &echo, reply);
+                        self.close_sent = true;
+                    }
+                    return Ok(Some(Decoded::Closed));
+                }
+                OP_TEXT | OP_BINARY => {
+                    if self.fragments.is_some() {
+                        return self.fail(
+                            reply,
+                            CloseCode::PROTOCOL_ERROR,
+                            "new data frame inside a fragmented message"
+                        );
+                    }
+                    if frame.fin {
+                        return self.finish_message(frame.payload, reply).map(Some);
+                    }
+                    self.fragments = Some((frame.opcode, frame.payload));
+                }
+                OP_CONTINUATION => {
+                    let Some((opcode, mut payload)) = self.fragments.take() else {
+                        return self.fail(
+                            reply,
+                            CloseCode::PROTOCOL_ERROR,
+                            "continuation frame without a start frame"
+                        );
+                    };
+                    if payload.len() + frame.payload.len() > self.max_message_size {
+                        return self.too_big(reply);
+                    }
+                    payload.extend_from_slice(&frame.payload);
+                    if frame.fin {
+                        return self.finish_message(payload, reply).map(Some);
+                    }
+                    self.fragments = Some((opcode, payload));
+                }
+                _ => {
+                    return self.fail(reply, CloseCode::PROTOCOL_ERROR, "unknown opcode");
+                }
+            }
+        }
+    }
+
+    fn encode(&mut self, msg: &ServerMessage, dst: &mut BytesMut) -> Result<()> {
+        // 心跳用 WebSocket 自己的 Ping 帧，浏览器会自动回复 Pong 帧
+        if let ServerMessage::Ping = msg {
+            encode_frame(OP_PING, &[], dst);
+            return Ok(());
+        }
+        let payload = serde_json::to_vec(msg)?;
+        encode_frame(OP_TEXT, &payload, dst);
+        Ok(())
+    }
+
+    fn encode_shared(&mut self, msg: &SharedMessage, dst: &mut BytesMut) -> Result<()> {
+        encode_frame(OP_TEXT, msg.json()?, dst);
+        Ok(())
+    }
+
+    /// 还没有握手，只能用 HTTP 状态码拒绝
+    fn refuse(&mut self, reason: &str, dst: &mut BytesMut) {
+        let response = format!(
+            "HTTP/1.1 503 Service Unavailable\r\n\
+             Content-Type: text/plain; charset=utf-8\r\n\
+             Content-Length: {}\r\n\
+             Connection: close\r\n\r\n{reason}",
+            reason.len()
+        );
+        dst.extend_from_slice(response.as_bytes());
+    }
+
+    fn close(&mut self, dst: &mut BytesMut) {
+        if self.handshake_done {
+            self.write_close(dst, CloseCode::NORMAL, "");
+        }
+    }
+}
+
+/// 尝试从缓冲区中解析出一个完整的帧；数据不够时返回 `Ok(None)`
+fn parse_frame(
+    src: &mut BytesMut,
+    max_payload: usize
+) -> Result<Option<Frame>, (CloseCode, &'static str)> {
+    if src.len() < 2 {
+        return Ok(None);
+    }
+
+    let fin = src[0] & 0x80 != 0;
+    let rsv = src[0] & 0x70;
+    let opcode = src[0] & 0x0f;
+    let masked = src[1] & 0x80 != 0;
+    let len_byte = (src[1] & 0x7f) as usize;
+
+    if rsv != 0 {
+        return Err((CloseCode::PROTOCOL_ERROR, "reserved bits must be zero"));
+    }
+    // 客户端发往服务端的帧必须带掩码
+    if !masked {
+        return Err((CloseCode::PROTOCOL_ERROR, "client frames must be masked"));
+    }
+    let is_control = opcode & 0x08 != 0;
+    if is_control && (!fin || len_byte > 125) {
+        return Err((CloseCode::PROTOCOL_ERROR, "invalid control frame"));
+    }
+
+    let (payload_len, mut offset) = match len_byte {
+        126 => {
+            if src.len() < 4 {
+                return Ok(None);
+            }
+            (u16::from_be_bytes([src[2], src[3]]) as u64, 4)
+        }
+        127 => {
+            if src.len() < 10 {
+                return Ok(None);
+            }
+            let mut len = [0u8; 8];
+            len.copy_from_slice(&src[2..10]);
+            (u64::from_be_bytes(len), 10)
+        }
+        n => (n as u64, 2),
+    };
+
+    if payload_len > (max_payload as u64) {
+        return Err((CloseCode::MESSAGE_TOO_BIG, "message too big"));
+    }
+    let payload_len = payload_len as usize;
+
+    if src.len() < offset + 4 + payload_len {
+        // 提前预留空间，避免大帧反复扩容
+        src.reserve(offset + 4 + payload_len - src.len());
+        return Ok(None);
+    }
+
+    let mask = [src[offset], src[offset + 1], src[offset + 2], src[offset + 3]];
+    offset += 4;
+
+    src.advance(offset);
+    let mut payload = src.split_to(payload_len).to_vec();
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+
+    Ok(Some(Frame { fin, opcode, payload }))
+}
+
+enum HandshakeError {
+    UnsupportedVersion,
+    Invalid(&'static str),
+}
+
+/// 校验 Upgrade 请求，成功时返回 Sec-WebSocket-Key
+fn parse_upgrade_request(request: &str) -> Result<String, HandshakeError> {
+    let mut lines = request.split("\r\n");
+
+    let request_line = lines.next().unwrap_or_default();
+    let mut parts = request_line.split_whitespace();
+    if parts.next() != Some("GET") {
+        return Err(HandshakeError::Invalid("method must be GET"));
+    }
+    let _path = parts.next().ok_or(HandshakeError::Invalid("missing request target"))?;
+    if parts.next() != Some("HTTP/1.1") {
+        return Err(HandshakeError::Invalid("HTTP/1.1 required"));
+    }
+
+    let mut upgrade = false;
+    let mut connection = false;
+    let mut version = None;
+    let mut key = None;
+
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        match name.trim().to_ascii_lowercase().as_str() {
+            "upgrade" => {
+                upgrade = value.eq_ignore_ascii_case("websocket");
+            }
+            "connection" => {
+                connection = value
+                    .split(',')
+                    .any(|token| token.trim().eq_ignore_ascii_case("upgrade"));
+            }
+            "sec-websocket-version" => {
+                version = Some(value.to_string());
+            }
+            "sec-websocket-key" => {
+                key = Some(value.to_string());
+            }
+            _ => {}
+        }
+    }
+
+    if !upgrade || !connection {
+        return Err(HandshakeError::Invalid("missing Upgrade: websocket"));
+    }
+    if version.as_deref() != Some("13") {
+        return Err(HandshakeError::UnsupportedVersion);
+    }
+    let key = key.ok_or(HandshakeError::Invalid("missing Sec-WebSocket-Key"))?;
+    // key 是 16 字节随机数的 base64 编码
+    match BASE64.decode(&key) {
+        Ok(raw) if raw.len() == 16 => Ok(key),
+        _ => Err(HandshakeError::Invalid("malformed Sec-WebSocket-Key")),
+    }
+}
+
+/// Sec-WebSocket-Accept = base64(sha1(key + GUID))
+fn accept_key(key: &str) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(key.as_bytes());
+    hasher.update(WEBSOCKET_GUID.as_bytes());
+    BASE64.encode(hasher.finalize())
+}
+
+fn reject(reply: &mut BytesMut, status: &str) {
+    let response = format!("HTTP/1.1 {status}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+    reply.extend_from_slice(response.as_bytes());
+}
+
+/// 编码一个服务端帧（服务端发出的帧不加掩码）
+fn encode_frame(opcode: u8, payload: &[u8], dst: &mut BytesMut) {
+    dst.reserve(10 + payload.len());
+    dst.put_u8(0x80 | opcode);
+    match payload.len() {
+        len if len < 126 => dst.put_u8(len as u8),
+        len if len <= (u16::MAX as usize) => {
+            dst.put_u8(126);
+            dst.put_u16(len as u16);
+        }
+        len => {
+            dst.put_u8(127);
+            dst.put_u64(len as u64);
+        }
+    }
+    dst.extend_from_slice(payload);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::ClientMessage;
+
+    const HANDSHAKE: &[u8] = b"GET /chat HTTP/1.1\r\n\
+        Host: localhost\r\n\
+        Upgrade: websocket\r\n\
+        Connection: keep-alive, Upgrade\r\n\
+        Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+        Sec-WebSocket-Version: 13\r\n\r\n";
+
+    const MASK: [u8; 4] = [0x37, 0xfa, 0x21, 0x3d];
+
+    /// 编码一个客户端帧（带掩码）
+    fn client_frame(fin: bool, opcode: u8, payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![(if fin { 0x80 } else { 0 }) | opcode];
+        match payload.len() {
+            len if len < 126 => frame.push(0x80 | (len as u8)),
+            len if len <= (u16::MAX as usize) => {
+                frame.push(0x80 | 126);
+                frame.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+            len => {
+                frame.push(0x80 | 127);
+                frame.extend_from_slice(&(len as u64).to_be_bytes());
+            }
+        }
+        frame.extend_from_slice(&MASK);
+        frame.extend(payload.iter().enumerate().map(|(i, byte)| byte ^ MASK[i % 4]));
+        frame
+    }
+
+    /// 已经完成握手的编解码器
+    fn connected(max_message_size: usize) -> WebSocketCodec {
+        let mut codec = WebSocketCodec::with_max_frame_size(max_message_size);
+        let mut src = BytesMut::from(HANDSHAKE);
+        let mut reply = BytesMut::new();
+        assert!(codec.decode(&mut src, &mut reply).unwrap().is_none());
+        codec
+    }
+
+    fn decode(codec: &mut WebSocketCodec, bytes: &[u8]) -> (Result<Option<Decoded>>, BytesMut) {
+        let mut src = BytesMut::from(bytes);
+        let mut reply = BytesMut::new();
+        (codec.decode(&mut src, &mut reply), reply)
+    }
+
+    /// 从 `reply` 中取出第一个服务端帧：(opcode, 负载)
+    fn server_frame(reply: &mut BytesMut) -> (u8, Vec<u8>) {
+        assert_eq!(reply[0] & 0x80, 0x80, "server frames are never fragmented");
+        assert_eq!(reply[1] & 0x80, 0, "server frames must not be masked");
+        let opcode = reply[0] & 0x0f;
+        let (len, offset) = match reply[1] & 0x7f {
+            126 => (u16::from_be_bytes([reply[2], reply[3]]) as usize, 4),
+            127 => (u64::from_be_bytes(reply[2..10].try_into().unwrap()) as usize, 10),
+            n => (n as usize, 2),
+        };
+        reply.advance(offset);
+        (opcode, reply.split_to(len).to_vec())
+    }
+
+    fn close_code(payload: &[u8]) -> u16 {
+        u16::from_be_bytes([payload[0], payload[1]])
+    }
+
+    #[test]
+    fn accept_key_matches_rfc_example() {
+        // RFC 6455 §1.3 中的例子
+        assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+
+    #[test]
+    fn completes_handshake() {
+        let mut codec = WebSocketCodec::new();
+        let mut src = BytesMut::from(HANDSHAKE);
+        let mut reply = BytesMut::new();
+        // 消息帧紧跟在握手请求后面
+        src.extend_from_slice(&client_frame(true, OP_TEXT, br#"{"type":"Ping"}"#));
+
+        let decoded = codec.decode(&mut src, &mut reply).unwrap();
+        assert!(matches!(decoded, Some(Decoded::Message(ClientMessage::Ping))));
+        let reply = String::from_utf8(reply.to_vec()).unwrap();
+        assert!(reply.starts_with("HTTP/1.1 101 Switching Protocols\r\n"));
+        assert!(reply.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+    }
+
+    #[test]
+    fn waits_for_complete_handshake() {
+        let mut codec = WebSocketCodec::new();
+        let (decoded, reply) = decode(&mut codec, &HANDSHAKE[..HANDSHAKE.len() - 2]);
+        assert!(decoded.unwrap().is_none());
+        assert!(reply.is_empty());
+    }
+
+    #[test]
+    fn rejects_bad_handshakes() {
+        let cases: [(&[u8], &str); 3] = [
+            (b"POST / HTTP/1.1\r\n\r\n", "400"),
+            (b"GET / HTTP/1.1\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\r\n", "426"),
+            (
+                b"GET / HTTP/1.1\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+                  Sec-WebSocket-Version: 13\r\nSec-WebSocket-Key: short\r\n\r\n",
+                "400",
+            ),
+        ];
+        for (request, status) in cases {
+            let (decoded, reply) = decode(&mut WebSocketCodec::new(), request);
+            assert!(decoded.is_err());
+            assert!(reply.starts_with(format!("HTTP/1.1 {status}").as_bytes()));
+        }
+
+        let oversized = vec![b'a'; MAX_HANDSHAKE_SIZE + 1];
+        let (decoded, reply) = decode(&mut WebSocketCodec::new(), &oversized);
+        assert!(decoded.is_err());
+        assert!(reply.starts_with(b"HTTP/1.1 431"));
+    }
+
+    #[test]
+    fn decodes_masked_text_frame() {
+        let mut codec = connected(DEFAULT_MAX_FRAME_SIZE);
+        let json = r#"{"type":"Broadcast","content":"你好"}"#;
+        let frame = client_frame(true, OP_TEXT, json.as_bytes());
+        // 掩码之后的负载和原文不同
+        assert!(!frame.windows(2).any(|w| w == b"{\""));
+
+        let (decoded, reply) = decode(&mut codec, &frame);
+        match decoded.unwrap() {
+            Some(Decoded::Message(ClientMessage::Broadcast { content })) => {
+                assert_eq!(content, "你好");
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+        assert!(reply.is_empty());
+    }
+
+    #[test]
+    fn waits_for_complete_frame() {
+        let mut codec = connected(DEFAULT_MAX_FRAME_SIZE);
+        let frame = client_frame(true, OP_TEXT, br#"{"type":"Ping"}"#);
+        let mut src = BytesMut::new();
+        let mut reply = BytesMut::new();
+        for &byte in &frame[..frame.len() - 1] {
+            src.put_u8(byte);
+            assert!(codec.decode(&mut src, &mut reply).unwrap().is_none());
+        }
+        src.put_u8(frame[frame.len() - 1]);
+        let decoded = codec.decode(&mut src, &mut reply).unwrap();
+        assert!(matches!(decoded, Some(Decoded::Message(ClientMessage::Ping))));
+        assert!(src.is_empty());
+    }
+
+    #[test]
+    fn decodes_extended_lengths() {
+        for len in [200, 70_000] {
+            let content = "x".repeat(len);
+            let json = format!(r#"{{"type":"Broadcast","content":"{content}"}}"#);
+            let frame = client_frame(true, OP_TEXT, json.as_bytes());
+            // 126 是 16 位长度，127 是 64 位长度
+            assert_eq!(frame[1] & 0x7f, if len < 65_536 { 126 } else { 127 });
+
+            let mut codec = connected(128 * 1024);
+            match decode(&mut codec, &frame).0.unwrap() {
+                Some(Decoded::Message(ClientMessage::Broadcast { content: got })) => {
+                    assert_eq!(got, content);
+                }
+                other => panic!("unexpected {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn reassembles_fragmented_message() {
+        let mut codec = connected(DEFAULT_MAX_FRAME_SIZE);
+        let json = br#"{"type":"Broadcast","content":"split"}"#;
+        let mut bytes = client_frame(false, OP_TEXT, &json[..10]);
+        // 分片之间可以插入控制帧
+        bytes.extend(client_frame(true, OP_PING, b"hb"));
+        bytes.extend(client_frame(false, OP_CONTINUATION, &json[10..20]));
+        bytes.extend(client_frame(true, OP_CONTINUATION, &json[20..]));
+
+        let (decoded, mut reply) = decode(&mut codec, &bytes);
+        match decoded.unwrap() {
+            Some(Decoded::Message(ClientMessage::Broadcast { content })) => {
+                assert_eq!(content, "split");
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+        assert_eq!(server_frame(&mut reply), (OP_PONG, b"hb".to_vec()));
+    }
+
+    #[test]
+    fn rejects_invalid_fragmentation() {
+        let mut codec = connected(DEFAULT_MAX_FRAME_SIZE);
+        let (decoded, mut reply) = decode(&mut codec, &client_frame(true, OP_CONTINUATION, b"{}"));
+        assert!(decoded.is_err());
+        let (opcode, payload) = server_frame(&mut reply);
+        assert_eq!((opcode, close_code(&payload)), (OP_CLOSE, 1002));
+
+        let mut codec = connected(DEFAULT_MAX_FRAME_SIZE);
+        let mut bytes = client_frame(false, OP_TEXT, b"{");
+        bytes.extend(client_frame(true, OP_TEXT, b"{}"));
+        let (decoded, mut reply) = decode(&mut codec, &bytes);
+        assert!(decoded.is_err());
+        assert_eq!(close_code(&server_frame(&mut reply).1), 1002);
+    }
+
+    #[test]
+    fn rejects_unmasked_client_frame() {
+        let mut codec = connected(DEFAULT_MAX_FRAME_SIZE);
+        let payload = br#"{"type":"Ping"}"#;
+        let mut frame = vec![0x80 | OP_TEXT, payload.len() as u8];
+        frame.extend_from_slice(payload);
+
+        let (decoded, mut reply) = decode(&mut codec, &frame);
+        assert!(decoded.is_err());
+        let (opcode, payload) = server_frame(&mut reply);
+        assert_eq!((opcode, close_code(&payload)), (OP_CLOSE, 1002));
+    }
+
+    #[test]
+    fn rejects_invalid_control_frames() {
+        // 控制帧不能分片，负载不能超过 125 字节
+        for frame in [client_frame(false, OP_PING, b""), client_frame(true, OP_PING, &[0; 126])] {
+            let mut codec = connected(DEFAULT_MAX_FRAME_SIZE);
+            let (decoded, mut reply) = decode(&mut codec, &frame);
+            assert!(decoded.is_err());
+            assert_eq!(close_code(&server_frame(&mut reply).1), 1002);
+        }
+    }
+
+    #[test]
+    fn rejects_oversized_frame() {
+        let mut codec = connected(64);
+        let (decoded, mut reply) = decode(&mut codec, &client_frame(true, OP_TEXT, &[b' '; 65]));
+        assert!(decoded.is_err());
+        // 先说明原因，再以 1009 关闭
+        let (opcode, payload) = server_frame(&mut reply);
+        assert_eq!(opcode, OP_TEXT);
+        let notice: ServerMessage = serde_json::from_slice(&payload).unwrap();
+        assert!(matches!(notice, ServerMessage::Disconnected { .. }));
+        let (opcode, payload) = server_frame(&mut reply);
+        assert_eq!((opcode, close_code(&payload)), (OP_CLOSE, 1009));
+    }
+
+    #[test]
+    fn rejects_oversized_fragmented_message() {
+        let mut codec = connected(64);
+        let mut bytes = client_frame(false, OP_TEXT, &[b' '; 40]);
+        bytes.extend(client_frame(true, OP_CONTINUATION, &[b' '; 40]));
+        let (decoded, mut reply) = decode(&mut codec, &bytes);
+        assert!(decoded.is_err());
+        server_frame(&mut reply);
+        assert_eq!(close_code(&server_frame(&mut reply).1), 1009);
+    }
+
+    #[test]
+    fn invalid_json_keeps_connection() {
+        let mut codec = connected(DEFAULT_MAX_FRAME_SIZE);
+        let (decoded, _) = decode(&mut codec, &client_frame(true, OP_TEXT, b"not json"));
+        assert!(matches!(decoded.unwrap(), Some(Decoded::Invalid(_))));
+
+        let frame = client_frame(true, OP_BINARY, &[0xff, 0xfe]);
+        let (decoded, mut reply) = decode(&mut codec, &frame);
+        assert!(decoded.is_err());
+        assert_eq!(close_code(&server_frame(&mut reply).1), 1007);
+    }
+
+    #[test]
+    fn answers_close_handshake() {
+        let mut codec = connected(DEFAULT_MAX_FRAME_SIZE);
+        let frame = client_frame(true, OP_CLOSE, &1001u16.to_be_bytes());
+        let (decoded, mut reply) = decode(&mut codec, &frame);
+        assert!(matches!(decoded.unwrap(), Some(Decoded::Closed)));
+        let (opcode, payload) = server_frame(&mut reply);
+        assert_eq!((opcode, close_code(&payload)), (OP_CLOSE, 1001));
+
+        // 已经回过 Close 帧，收尾时不再发第二个
+        let mut dst = BytesMut::new();
+        codec.close(&mut dst);
+        assert!(dst.is_empty());
+    }
+
+    #[test]
+    fn close_without_code() {
+        let mut codec = connected(DEFAULT_MAX_FRAME_SIZE);
+        let (decoded, mut reply) = decode(&mut codec, &client_frame(true, OP_CLOSE, &[]));
+        assert!(matches!(decoded.unwrap(), Some(Decoded::Closed)));
+        assert_eq!(server_frame(&mut reply), (OP_CLOSE, Vec::new()));
+
+        // 原因不会被回传
+        let mut codec = connected(DEFAULT_MAX_FRAME_SIZE);
+        let frame = client_frame(true, OP_CLOSE, b"\x0b\xb8bye");
+        let (decoded, mut reply) = decode(&mut codec, &frame);
+        assert!(matches!(decoded.unwrap(), Some(Decoded::Closed)));
+        assert_eq!(server_frame(&mut reply), (OP_CLOSE, 3000u16.to_be_bytes().to_vec()));
+    }
+
+    #[test]
+    fn rejects_invalid_close_frames() {
+        let mut codec = connected(DEFAULT_MAX_FRAME_SIZE);
+        let (decoded, mut reply) = decode(&mut codec, &client_frame(true, OP_CLOSE, &[0x03]));
+        assert!(decoded.is_err());
+        let (opcode, payload) = server_frame(&mut reply);
+        assert_eq!((opcode, close_code(&payload)), (OP_CLOSE, 1002));
+
+        // 保留的和未分配的状态码不能原样回传
+        for code in [0u16, 999, 1004, 1005, 1006, 1015, 1016, 2999, 5000] {
+            let mut codec = connected(DEFAULT_MAX_FRAME_SIZE);
+            let frame = client_frame(true, OP_CLOSE, &code.to_be_bytes());
+            let (decoded, mut reply) = decode(&mut codec, &frame);
+            assert!(decoded.is_err(), "{code}");
+            assert_eq!(close_code(&server_frame(&mut reply).1), 1002, "{code}");
+        }
+        for code in [1000u16, 1003, 1007, 1014, 3000, 4999] {
+            let mut codec = connected(DEFAULT_MAX_FRAME_SIZE);
+            let frame = client_frame(true, OP_CLOSE, &code.to_be_bytes());
+            let (decoded, mut reply) = decode(&mut codec, &frame);
+            assert!(matches!(decoded.unwrap(), Some(Decoded::Closed)), "{code}");
+            assert_eq!(close_code(&server_frame(&mut reply).1), code);
+        }
+    }
+
+    #[test]
+    fn encodes_unmasked_server_frames() {
+        let mut codec = connected(DEFAULT_MAX_FRAME_SIZE);
+        let mut dst = BytesMut::new();
+        codec.encode(&ServerMessage::Ping, &mut dst).unwrap();
+        assert_eq!(server_frame(&mut dst), (OP_PING, Vec::new()));
+
+        let msg = ServerMessage::Disconnected { reason: "x".repeat(70_000) };
+        codec.encode(&msg, &mut dst).unwrap();
+        assert_eq!(dst[1], 127);
+        let (opcode, payload) = server_frame(&mut dst);
+        assert_eq!(opcode, OP_TEXT);
+        assert_eq!(payload, serde_json::to_vec(&msg).unwrap());
+        assert!(dst.is_empty());
+
+        codec.close(&mut dst);
+        let (opcode, payload) = server_frame(&mut dst);
+        assert_eq!((opcode, close_code(&payload)), (OP_CLOSE, 1000));
+    }
+}
@@ -0,0 +1,257 @@
+// src/command/builtin.rs
+
+// 内置命令：/help、/list、/w 以及房间相关的 /join、/part、/r、/rooms
+
+use super::{ Command, CommandContext, CommandFuture };
+use crate::auth::moderation::Role;
+use crate::backend::Backend;
+use crate::protocol::ServerMessage;
+
+/// `/help`：列出所有命令
+pub struct HelpCommand;
+
+impl<B: Backend> Command<B> for HelpCommand {
+    fn name(&self) -> &'static str {
+        "help"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/help"
+    }
+
+    fn description(&self) -> &'static str {
+        "Show this list of commands."
+    }
+
+    fn execute<'a>(&'a self, ctx: CommandContext<'a, B>) -> CommandFuture<'a> {
+        Box::pin(async move {
+            let mut msg = String::from("Available commands:");
+            // 只列出调用者有权限使用的命令
+            let available = ctx.commands
+                .iter()
+                .filter(|command| ctx.role >= command.required_role());
+            for command in available {
+                msg.push_str(&format!("\n  {:<24} {}", command.usage(), command.description()));
+            }
+            Ok(Some(ServerMessage::Info { msg }))
+        })
+    }
+}
+
+/// 房间名的最大字符数（不含 `#`）
+pub const MAX_ROOM_NAME_LENGTH: usize = 32;
+
+/// 解析房间名：去掉可选的 `#` 前缀并转为小写，只允许字母、数字、`-` 和 `_`
+pub fn parse_room_name(arg: &str) -> Result<String, ServerMessage> {
+    let name = arg.strip_prefix('#').unwrap_or(arg);
+    if name.is_empty() {
+        return Err(ServerMessage::error("Room name cannot be empty."));
+    }
+    if name.chars().count() > MAX_ROOM_NAME_LENGTH {
+        return Err(
+            ServerMessage::error(
+                format!("Room name must be at most {MAX_ROOM_NAME_LENGTH} characters.")
+            )
+        );
+    }
+    if !name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+        return Err(
+            ServerMessage::error("Room name may only contain letters, digits, '-' and '_'.")
+        );
+    }
+    Ok(name.to_lowercase())
+}
+
+/// `/list [room]`：只向调用者回复当前在线用户列表，或指定房间的成员
+pub struct ListCommand;
+
+impl<B: Backend> Command<B> for ListCommand {
+    fn name(&self) -> &'static str {
+        "list"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/list [room]"
+    }
+
+    fn description(&self) -> &'static str {
+        "List the users online, or the members of a room."
+    }
+
+    fn execute<'a>(&'a self, ctx: CommandContext<'a, B>) -> CommandFuture<'a> {
+        Box::pin(async move {
+            if ctx.args.is_empty() {
+                let clients = ctx.backend.clients().await?;
+                let guests = clients
+                    .iter()
+                    .filter(|client| client.role == Role::Guest)
+                    .map(|client| client.username.clone())
+                    .collect();
+                let users = clients.into_iter().map(|client| client.username).collect();
+                return Ok(Some(ServerMessage::UserList { users, guests }));
+            }
+
+            let room = match parse_room_name(ctx.args) {
+                Ok(room) => room,
+                Err(reply) => return Ok(Some(reply)),
+            };
+            Ok(
+                Some(match ctx.backend.room_members(&room).await? {
+                    Some(members) => ServerMessage::RoomMembers { room, members },
+                    None => ServerMessage::error(format!("Room #{room} does not exist.")),
+                })
+            )
+        })
+    }
+}
+
+/// `/w <user> <message>`：私聊，等价于 `ClientMessage::Whisper`
+pub struct WhisperCommand;
+
+impl<B: Backend> Command<B> for WhisperCommand {
+    fn name(&self) -> &'static str {
+        "w"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/w <user> <message>"
+    }
+
+    fn description(&self) -> &'static str {
+        "Send a private message."
+    }
+
+    fn sends_message(&self) -> bool {
+        true
+    }
+
+    fn execute<'a>(&'a self, ctx: CommandContext<'a, B>) -> CommandFuture<'a> {
+        Box::pin(async move {
+            let Some((to, content)) = ctx.args.split_once(char::is_whitespace) else {
+                return Ok(Some(ServerMessage::error("Usage: /w <user> <message>")));
+            };
+            let to = ctx.usernames.resolve(to);
+            ctx.backend.whisper(ctx.username, &to, content.trim_start().to_string()).await;
+            Ok(None)
+        })
+    }
+}
+
+/// `/join <room>`：加入房间，不存在时创建
+pub struct JoinCommand;
+
+impl<B: Backend> Command<B> for JoinCommand {
+    fn name(&self) -> &'static str {
+        "join"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/join <room>"
+    }
+
+    fn description(&self) -> &'static str {
+        "Join a room, creating it if needed."
+    }
+
+    fn execute<'a>(&'a self, ctx: CommandContext<'a, B>) -> CommandFuture<'a> {
+        Box::pin(async move {
+            match parse_room_name(ctx.args) {
+                Ok(room) => {
+                    ctx.backend.join_room(ctx.username, &room).await;
+                    Ok(None)
+                }
+                Err(reply) => Ok(Some(reply)),
+            }
+        })
+    }
+}
+
+/// `/part <room>`：离开房间
+pub struct PartCommand;
+
+impl<B: Backend> Command<B> for PartCommand {
+    fn name(&self) -> &'static str {
+        "part"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/part <room>"
+    }
+
+    fn description(&self) -> &'static str {
+        "Leave a room."
+    }
+
+    fn execute<'a>(&'a self, ctx: CommandContext<'a, B>) -> CommandFuture<'a> {
+        Box::pin(async move {
+            match parse_room_name(ctx.args) {
+                Ok(room) => {
+                    ctx.backend.part_room(ctx.username, &room).await;
+                    Ok(None)
+                }
+                Err(reply) => Ok(Some(reply)),
+            }
+        })
+    }
+}
+
+/// `/r <room> <message>`：发给一个自己所在的房间
+pub struct RoomSayCommand;
+
+impl<B: Backend> Command<B> for RoomSayCommand {
+    fn name(&self) -> &'static str {
+        "r"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/r <room> <message>"
+    }
+
+    fn description(&self) -> &'static str {
+        "Send a message to a room you have joined."
+    }
+
+    fn sends_message(&self) -> bool {
+        true
+    }
+
+    fn execute<'a>(&'a self, ctx: CommandContext<'a, B>) -> CommandFuture<'a> {
+        Box::pin(async move {
+            let Some((room, content)) = ctx.args.split_once(char::is_whitespace) else {
+                return Ok(Some(ServerMessage::error("Usage: /r <room> <message>")));
+            };
+            match parse_room_name(room) {
+                Ok(room) => {
+                    let content = content.trim_start().to_string();
+                    ctx.backend.room_message(ctx.username, &room, content).await;
+                    Ok(None)
+                }
+                Err(reply) => Ok(Some(reply)),
+            }
+        })
+    }
+}
+
+/// `/rooms`：列出所有房间
+pub struct RoomsCommand;
+
+impl<B: Backend> Command<B> for RoomsCommand {
+    fn name(&self) -> &'static str {
+        "rooms"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/rooms"
+    }
+
+    fn description(&self) -> &'static str {
+        "List all rooms."
+    }
+
+    fn execute<'a>(&'a self, ctx: CommandContext<'a, B>) -> CommandFuture<'a> {
+        Box::pin(async move {
+            let rooms = ctx.backend.rooms().await?;
+            Ok(Some(ServerMessage::RoomList { rooms }))
+        })
+    }
+}
@@ -0,0 +1,177 @@
+// src/command/mod.rs
+
+// 斜杠命令：以 `/` 开头的聊天内容不会被广播，而是交给 CommandRegistry 分发。
+// 分发时按命令要求的最低角色检查权限，被禁言的用户不能使用会发出消息的命令。
+// 内置命令见 builtin.rs，管理命令见 moderation.rs；
+// 嵌入方可以实现 Command 并通过 ChatServerBuilder::command 注册自己的命令，
+// 不需要改动连接处理的主循环。
+
+pub mod builtin;
+pub mod moderation;
+
+pub use builtin::{
+    HelpCommand,
+    JoinCommand,
+    ListCommand,
+    PartCommand,
+    RoomSayCommand,
+    RoomsCommand,
+    WhisperCommand,
+};
+pub use moderation::{
+    BanCommand,
+    BanIpCommand,
+    BansCommand,
+    KickCommand,
+    MuteCommand,
+    UnbanCommand,
+    UnmuteCommand,
+};
+
+use crate::auth::moderation::{ Moderation, Role, muted_notice };
+use crate::auth::rules::UsernameRegistry;
+use crate::backend::Backend;
+use crate::connection::ConnectionSettings;
+use crate::protocol::ServerMessage;
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+
+/// 命令前缀
+pub const COMMAND_PREFIX: char = '/';
+
+/// `Command::execute` 返回的 future。
+/// 成功时可以返回一条只发给调用者的回复；`Err` 表示连接无法继续（例如后端已关闭）。
+pub type CommandFuture<'a> = Pin<Box<dyn Future<Output = Result<Option<ServerMessage>>> + Send + 'a>>;
+
+/// 执行一条命令时可以访问的上下文
+pub struct CommandContext<'a, B> {
+    pub backend: &'a B,
+    /// 角色名单、封禁和禁言
+    pub moderation: &'a Moderation,
+    /// 在线用户名的登记表，用来按规范化键找到目标用户
+    pub usernames: &'a UsernameRegistry,
+    /// 调用者的用户名
+    pub username: &'a str,
+    /// 调用者的角色
+    pub role: Role,
+    /// 命令名之后的全部内容，已去掉首尾空白
+    pub args: &'a str,
+    /// 全部已注册的命令（/help 使用）
+    pub commands: &'a CommandRegistry<B>,
+}
+
+/// 一条斜杠命令
+pub trait Command<B: Backend>: Send + Sync + 'static {
+    /// 命令名，不含前缀，例如 `"list"`
+    fn name(&self) -> &'static str;
+
+    /// 用法，例如 `"/w <user> <message>"`
+    fn usage(&self) -> &'static str;
+
+    /// 一句话说明，显示在 /help 中
+    fn description(&self) -> &'static str;
+
+    /// 使用这条命令需要的最低角色，默认所有人都可以用
+    fn required_role(&self) -> Role {
+        Role::Guest
+    }
+
+    /// 是否会把消息发给别人；被禁言的用户不能使用
+    fn sends_message(&self) -> bool {
+        false
+    }
+
+    fn execute<'a>(&'a self, ctx: CommandContext<'a, B>) -> CommandFuture<'a>;
+}
+
+/// 命令名 -> 命令
+pub struct CommandRegistry<B> {
+    commands: BTreeMap<&'static str, Box<dyn Command<B>>>,
+}
+
+impl<B: Backend> CommandRegistry<B> {
+    /// 空的注册表
+    pub fn new() -> Self {
+        CommandRegistry {
+            commands: BTreeMap::new(),
+        }
+    }
+
+    /// 包含全部内置命令的注册表
+    pub fn with_builtins() -> Self {
+        let mut registry = CommandRegistry::new();
+        registry.register(HelpCommand);
+        registry.register(ListCommand);
+        registry.register(WhisperCommand);
+        registry.register(JoinCommand);
+        registry.register(PartCommand);
+        registry.register(RoomSayCommand);
+        registry.register(RoomsCommand);
+        registry.register(KickCommand);
+        registry.register(MuteCommand);
+        registry.register(UnmuteCommand);
+        registry.register(BanCommand);
+        registry.register(BanIpCommand);
+        registry.register(UnbanCommand);
+        registry.register(BansCommand);
+        registry
+    }
+
+    /// 注册一条命令，同名的旧命令（包括内置命令）会被替换
+    pub fn register(&mut self, command: impl Command<B>) {
+        self.commands.insert(command.name(), Box::new(command));
+    }
+
+    /// 按命令名排序遍历
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Command<B>> {
+        self.commands.values().map(|command| command.as_ref())
+    }
+
+    /// 如果 `content` 是一条命令就执行它并返回 `Some(回复)`，否则返回 `None`（应当作普通消息广播）
+    pub async fn dispatch(
+        &self,
+        backend: &B,
+        settings: &ConnectionSettings,
+        username: &str,
+        role: Role,
+        content: &str
+    ) -> Option<Result<Option<ServerMessage>>> {
+        let line = content.strip_prefix(COMMAND_PREFIX)?;
+        let (name, args) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+
+        let Some(command) = self.commands.get(name) else {
+            let reply = ServerMessage::error(
+                format!("Unknown command '/{name}'. Type /help for a list of commands.")
+            );
+            return Some(Ok(Some(reply)));
+        };
+        if role < command.required_role() {
+            let reply = ServerMessage::error(format!("You do not have permission to use /{name}."));
+            return Some(Ok(Some(reply)));
+        }
+        if command.sends_message()
+            && let Some(remaining) = settings.moderation.muted_for(username)
+        {
+            return Some(Ok(Some(muted_notice(remaining))));
+        }
+
+        let ctx = CommandContext {
+            backend,
+            moderation: &settings.moderation,
+            usernames: &settings.usernames,
+            username,
+            role,
+            args: args.trim(),
+            commands: self,
+        };
+        Some(command.execute(ctx).await)
+    }
+}
+
+impl<B: Backend> Default for CommandRegistry<B> {
+    fn default() -> Self {
+        CommandRegistry::with_builtins()
+    }
+}
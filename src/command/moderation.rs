@@ -0,0 +1,430 @@
+// src/command/moderation.rs
+
+// 管理命令：版主可以 /kick、/mute、/unmute，管理员还可以 /ban、/banip、/unban、/bans。
+// 只能管理角色比自己低的用户。结果只回复给调用者，被处理的用户会收到一条说明。
+
+use super::{ Command, CommandContext, CommandFuture };
+use crate::auth::moderation::{ BanTarget, Role, format_duration, parse_duration };
+use crate::backend::{ Backend, UserInfo };
+use crate::protocol::ServerMessage;
+use anyhow::Result;
+use std::net::IpAddr;
+use tracing::{ error, info };
+
+/// 把参数拆成第一个词和其余部分（可选）
+fn split_target(args: &str) -> Option<(&str, Option<&str>)> {
+    if args.is_empty() {
+        return None;
+    }
+    Some(match args.split_once(char::is_whitespace) {
+        Some((target, rest)) => (target, Some(rest.trim_start())),
+        None => (args, None),
+    })
+}
+
+/// 发给被处理用户的说明，例如 `You have been kicked by alice: spam`
+fn notice(action: &str, by: &str, reason: Option<&str>) -> String {
+    match reason {
+        Some(reason) => format!("You have been {action} by {by}: {reason}"),
+        None => format!("You have been {action} by {by}."),
+    }
+}
+
+fn cannot_moderate(name: &str) -> ServerMessage {
+    ServerMessage::error(format!("You cannot moderate '{name}'."))
+}
+
+/// 按规范化键查找在线用户，`/kick ALICE` 找到的是在线的 `alice`
+async fn find_user<B: Backend>(
+    ctx: &CommandContext<'_, B>,
+    name: &str
+) -> Result<Option<UserInfo>> {
+    match ctx.usernames.holder(name) {
+        Some(holder) => ctx.backend.user(&holder).await,
+        None => Ok(None),
+    }
+}
+
+/// 目标用户在线时按他当前的角色，不在线时按名单里的角色
+async fn target_role<B: Backend>(
+    ctx: &CommandContext<'_, B>,
+    name: &str
+) -> Result<(Option<UserInfo>, Role)> {
+    let online = find_user(ctx, name).await?;
+    let role = match &online {
+        Some(target) => target.role,
+        None => ctx.moderation.role_of(name),
+    };
+    Ok((online, role))
+}
+
+/// 查找在线的目标用户，并检查调用者的角色比他高。内层 `Err` 是回复给调用者的错误
+async fn online_target<B: Backend>(
+    ctx: &CommandContext<'_, B>,
+    name: &str
+) -> Result<Result<UserInfo, ServerMessage>> {
+    let Some(target) = find_user(ctx, name).await? else {
+        return Ok(Err(ServerMessage::error(format!("User '{name}' not found."))));
+    };
+    if !ctx.role.outranks(target.role) {
+        return Ok(Err(cannot_moderate(name)));
+    }
+    Ok(Ok(target))
+}
+
+/// 封禁名单写入失败时的回复
+fn save_failed(e: anyhow::Error) -> ServerMessage {
+    error!(error = ?e, "Failed to save ban list");
+    ServerMessage::error("Could not save the ban list, please try again later.")
+}
+
+/// `/kick <user> [reason]`：断开一个用户，他可以重新登录
+pub struct KickCommand;
+
+impl<B: Backend> Command<B> for KickCommand {
+    fn name(&self) -> &'static str {
+        "kick"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/kick <user> [reason]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Disconnect a user."
+    }
+
+    fn required_role(&self) -> Role {
+        Role::Moderator
+    }
+
+    fn execute<'a>(&'a self, ctx: CommandContext<'a, B>) -> CommandFuture<'a> {
+        Box::pin(async move {
+            let Some((name, reason)) = split_target(ctx.args) else {
+                return Ok(Some(ServerMessage::error("Usage: /kick <user> [reason]")));
+            };
+            let target = match online_target(&ctx, name).await? {
+                Ok(target) => target,
+                Err(reply) => return Ok(Some(reply)),
+            };
+
+            ctx.backend.kick(&target.username, notice("kicked", ctx.username, reason)).await;
+            info!(
+                moderator = %ctx.username,
+                username = %target.username,
+                reason = ?reason,
+                "User kicked."
+            );
+            Ok(Some(ServerMessage::Info { msg: format!("Kicked {}.", target.username) }))
+        })
+    }
+}
+
+/// `/mute <user> <duration> [reason]`：一段时间内不能广播、私聊或在房间里发言
+pub struct MuteCommand;
+
+impl<B: Backend> Command<B> for MuteCommand {
+    fn name(&self) -> &'static str {
+        "mute"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/mute <user> <duration> [reason]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Mute a user for a while (e.g. 30s, 10m, 2h, 1d)."
+    }
+
+    fn required_role(&self) -> Role {
+        Role::Moderator
+    }
+
+    fn execute<'a>(&'a self, ctx: CommandContext<'a, B>) -> CommandFuture<'a> {
+        Box::pin(async move {
+            let usage = || Ok(Some(ServerMessage::error("Usage: /mute <user> <duration> [reason]")));
+            let Some((name, Some(rest))) = split_target(ctx.args) else {
+                return usage();
+            };
+            let Some((duration, reason)) = split_target(rest) else {
+                return usage();
+            };
+            let Some(duration) = parse_duration(duration) else {
+                let err_msg = format!("Invalid duration '{duration}', use e.g. 30s, 10m, 2h or 1d.");
+                return Ok(Some(ServerMessage::error(err_msg)));
+            };
+            let target = match online_target(&ctx, name).await? {
+                Ok(target) => target,
+                Err(reply) => return Ok(Some(reply)),
+            };
+
+            if !ctx.moderation.mute(&target.username, duration) {
+                let err_msg = format!("Invalid duration '{}'.", format_duration(duration));
+                return Ok(Some(ServerMessage::error(err_msg)));
+            }
+            let span = format_duration(duration);
+            let msg = notice(&format!("muted for {span}"), ctx.username, reason);
+            ctx.backend.notify(&target.username, ServerMessage::Info { msg }).await;
+            info!(
+                moderator = %ctx.username,
+                username = %target.username,
+                secs = duration.as_secs(),
+                reason = ?reason,
+                "User muted."
+            );
+            Ok(Some(ServerMessage::Info { msg: format!("Muted {} for {span}.", target.username) }))
+        })
+    }
+}
+
+/// `/unmute <user>`：提前解除禁言
+pub struct UnmuteCommand;
+
+impl<B: Backend> Command<B> for UnmuteCommand {
+    fn name(&self) -> &'static str {
+        "unmute"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/unmute <user>"
+    }
+
+    fn description(&self) -> &'static str {
+        "Lift a mute."
+    }
+
+    fn required_role(&self) -> Role {
+        Role::Moderator
+    }
+
+    fn execute<'a>(&'a self, ctx: CommandContext<'a, B>) -> CommandFuture<'a> {
+        Box::pin(async move {
+            let name = ctx.args;
+            if name.is_empty() {
+                return Ok(Some(ServerMessage::error("Usage: /unmute <user>")));
+            }
+            // 与 /mute 一样只能管理角色比自己低的用户，版主不能给自己或其他版主解除禁言
+            let (online, role) = target_role(&ctx, name).await?;
+            if !ctx.role.outranks(role) {
+                return Ok(Some(cannot_moderate(name)));
+            }
+            let name = online.as_ref().map_or(name, |target| target.username.as_str());
+            if !ctx.moderation.unmute(name) {
+                return Ok(Some(ServerMessage::error(format!("'{name}' is not muted."))));
+            }
+
+            let msg = notice("unmuted", ctx.username, None);
+            ctx.backend.notify(name, ServerMessage::Info { msg }).await;
+            info!(moderator = %ctx.username, username = %name, "User unmuted.");
+            Ok(Some(ServerMessage::Info { msg: format!("Unmuted {name}.") }))
+        })
+    }
+}
+
+/// `/ban <user> [reason]`：封禁用户名，在线时立即断开。不在线的用户名也可以封禁
+pub struct BanCommand;
+
+impl<B: Backend> Command<B> for BanCommand {
+    fn name(&self) -> &'static str {
+        "ban"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/ban <user> [reason]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Ban a username."
+    }
+
+    fn required_role(&self) -> Role {
+        Role::Admin
+    }
+
+    fn execute<'a>(&'a self, ctx: CommandContext<'a, B>) -> CommandFuture<'a> {
+        Box::pin(async move {
+            let Some((name, reason)) = split_target(ctx.args) else {
+                return Ok(Some(ServerMessage::error("Usage: /ban <user> [reason]")));
+            };
+            // 不在线时按名单里的角色判断，管理员和版主的名字不能被更低的角色封禁
+            let (online, role) = target_role(&ctx, name).await?;
+            if !ctx.role.outranks(role) {
+                return Ok(Some(cannot_moderate(name)));
+            }
+            let name = online.as_ref().map_or(name, |target| target.username.as_str());
+
+            let target = BanTarget::User(name.to_string());
+            match ctx.moderation.ban(&target, ctx.username, reason).await {
+                Ok(true) => {}
+                Ok(false) => {
+                    return Ok(Some(ServerMessage::error(format!("'{name}' is already banned."))));
+                }
+                Err(e) => return Ok(Some(save_failed(e))),
+            }
+            if online.is_some() {
+                ctx.backend.kick(name, notice("banned", ctx.username, reason)).await;
+            }
+            info!(admin = %ctx.username, username = %name, reason = ?reason, "Username banned.");
+            Ok(Some(ServerMessage::Info { msg: format!("Banned {name}.") }))
+        })
+    }
+}
+
+/// `/banip <user|ip> [reason]`：封禁一个 IP（或在线用户当前的 IP），断开从它连接的所有用户
+pub struct BanIpCommand;
+
+impl<B: Backend> Command<B> for BanIpCommand {
+    fn name(&self) -> &'static str {
+        "banip"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/banip <user|ip> [reason]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Ban an IP address, or the address of an online user."
+    }
+
+    fn required_role(&self) -> Role {
+        Role::Admin
+    }
+
+    fn execute<'a>(&'a self, ctx: CommandContext<'a, B>) -> CommandFuture<'a> {
+        Box::pin(async move {
+            let Some((arg, reason)) = split_target(ctx.args) else {
+                return Ok(Some(ServerMessage::error("Usage: /banip <user|ip> [reason]")));
+            };
+            let ip: IpAddr = match arg.parse::<IpAddr>() {
+                Ok(ip) => ip.to_canonical(),
+                Err(_) => match find_user(&ctx, arg).await? {
+                    Some(target) => match target.addr.ip() {
+                        Some(ip) => ip,
+                        None => {
+                            let err_msg =
+                                format!("User '{arg}' is connected over a Unix socket, use /ban instead.");
+                            return Ok(Some(ServerMessage::error(err_msg)));
+                        }
+                    },
+                    None => {
+                        return Ok(Some(ServerMessage::error(format!("User '{arg}' not found."))));
+                    }
+                },
+            };
+
+            // 同一地址上有角色不低于自己的用户（包括自己）时不能封禁
+            let users = ctx.backend.users_at(ip).await?;
+            if let Some(user) = users.iter().find(|user| !ctx.role.outranks(user.role)) {
+                let err_msg = format!("You cannot ban {ip}, it is used by '{}'.", user.username);
+                return Ok(Some(ServerMessage::error(err_msg)));
+            }
+
+            match ctx.moderation.ban(&BanTarget::Ip(ip), ctx.username, reason).await {
+                Ok(true) => {}
+                Ok(false) => {
+                    return Ok(Some(ServerMessage::error(format!("{ip} is already banned."))));
+                }
+                Err(e) => return Ok(Some(save_failed(e))),
+            }
+            for user in &users {
+                ctx.backend.kick(&user.username, notice("banned", ctx.username, reason)).await;
+            }
+            info!(
+                admin = %ctx.username,
+                ip = %ip,
+                kicked = users.len(),
+                reason = ?reason,
+                "Address banned."
+            );
+            let msg = match users.len() {
+                0 => format!("Banned {ip}."),
+                n => format!("Banned {ip} and disconnected {n} user(s)."),
+            };
+            Ok(Some(ServerMessage::Info { msg }))
+        })
+    }
+}
+
+/// `/unban <user|ip>`：解除封禁
+pub struct UnbanCommand;
+
+impl<B: Backend> Command<B> for UnbanCommand {
+    fn name(&self) -> &'static str {
+        "unban"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/unban <user|ip>"
+    }
+
+    fn description(&self) -> &'static str {
+        "Lift a ban on a username or IP address."
+    }
+
+    fn required_role(&self) -> Role {
+        Role::Admin
+    }
+
+    fn execute<'a>(&'a self, ctx: CommandContext<'a, B>) -> CommandFuture<'a> {
+        Box::pin(async move {
+            if ctx.args.is_empty() {
+                return Ok(Some(ServerMessage::error("Usage: /unban <user|ip>")));
+            }
+            let mut target = BanTarget::parse(ctx.args);
+            // `10.0.0.1` 也是合法的用户名，`/ban 10.0.0.1` 记下的是用户名封禁：
+            // 没有这个 IP 的封禁时按用户名解除
+            if let BanTarget::Ip(ip) = target
+                && ctx.moderation.ip_ban(ip).is_none()
+                && ctx.moderation.user_ban(ctx.args).is_some()
+            {
+                target = BanTarget::User(ctx.args.to_string());
+            }
+            match ctx.moderation.unban(&target).await {
+                Ok(true) => {
+                    info!(admin = %ctx.username, target = %target, "Ban lifted.");
+                    Ok(Some(ServerMessage::Info { msg: format!("Unbanned {target}.") }))
+                }
+                Ok(false) => Ok(Some(ServerMessage::error(format!("'{target}' is not banned.")))),
+                Err(e) => Ok(Some(save_failed(e))),
+            }
+        })
+    }
+}
+
+/// `/bans`：列出所有封禁
+pub struct BansCommand;
+
+impl<B: Backend> Command<B> for BansCommand {
+    fn name(&self) -> &'static str {
+        "bans"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/bans"
+    }
+
+    fn description(&self) -> &'static str {
+        "List banned usernames and IP addresses."
+    }
+
+    fn required_role(&self) -> Role {
+        Role::Admin
+    }
+
+    fn execute<'a>(&'a self, ctx: CommandContext<'a, B>) -> CommandFuture<'a> {
+        Box::pin(async move {
+            let bans = ctx.moderation.bans();
+            if bans.is_empty() {
+                return Ok(Some(ServerMessage::Info { msg: "No bans.".to_string() }));
+            }
+            let mut msg = String::from("Bans:");
+            for ban in bans {
+                msg.push_str(&format!("\n  {:<24} by {}", ban.target, ban.by));
+                if let Some(reason) = ban.reason {
+                    msg.push_str(&format!(": {reason}"));
+                }
+            }
+            Ok(Some(ServerMessage::Info { msg }))
+        })
+    }
+}
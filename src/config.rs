@@ -0,0 +1,306 @@
+// src/config.rs
+
+// 服务器配置：从 TOML 文件加载（写法与 press_test/src/config.rs 一致），
+// 命令行参数可以覆盖其中的任意一项。
+
+use crate::auth::accounts::AccountConfig;
+use crate::auth::authenticator::AuthConfig;
+use crate::auth::moderation::ModerationConfig;
+use crate::auth::rules::UsernameRules;
+use crate::connection::limits::ConnectionLimits;
+use crate::connection::queue::SlowConsumerPolicy;
+use crate::connection::rate_limit::RateLimitConfig;
+use crate::connection::session::SessionConfig;
+use crate::connection::tls::TlsConfig;
+use crate::protocol::DEFAULT_MAX_FRAME_SIZE;
+use crate::server::Protocol;
+use crate::utils::sanitize::SanitizePolicy;
+use anyhow::{ Context, Result, anyhow };
+use serde::Deserialize;
+use std::fs;
+use std::str::FromStr;
+
+/// 聊天室状态后端
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    /// Arc<Mutex<HashMap>> 共享状态
+    Mutex,
+    /// Hub Actor
+    Actor,
+}
+
+impl FromStr for BackendKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "mutex" => Ok(BackendKind::Mutex),
+            "actor" => Ok(BackendKind::Actor),
+            _ => Err(anyhow!("unknown backend '{s}' (expected mutex or actor)")),
+        }
+    }
+}
+
+/// 日志输出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// 默认的多字段单行格式
+    Full,
+    /// 更紧凑的单行格式
+    Compact,
+    /// 每行一个 JSON 对象，便于日志系统采集
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "full" => Ok(LogFormat::Full),
+            "compact" => Ok(LogFormat::Compact),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(anyhow!("unknown log format '{s}' (expected full, compact or json)")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LogConfig {
+    /// trace / debug / info / warn / error
+    pub level: String,
+    pub format: LogFormat,
+    /// 是否输出 ANSI 颜色
+    pub ansi: bool,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            level: "info".to_string(),
+            format: LogFormat::Full,
+            ansi: true,
+        }
+    }
+}
+
+/// 一个监听端口
+#[derive(Debug, Clone, Deserialize)]
+pub struct ListenerConfig {
+    /// `127.0.0.1:8080`、`[::1]:8080`，或 `unix:` 开头的 Unix 套接字路径
+    pub addr: String,
+    pub protocol: Protocol,
+    /// 覆盖全局的 slow_consumer
+    #[serde(default)]
+    pub slow_consumer: Option<SlowConsumerPolicy>,
+    /// 是否使用 TLS，证书见 [tls]
+    #[serde(default)]
+    pub tls: bool,
+}
+
+/// 命令行写法：`PROTOCOL=ADDR`，例如 `websocket=127.0.0.1:8081`、`line=unix:/tmp/chat.sock`；
+/// TLS 端口写作 `PROTOCOL+tls=ADDR`，例如 `json+tls=127.0.0.1:8443`
+impl FromStr for ListenerConfig {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (protocol, addr) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("expected PROTOCOL=ADDR, got '{s}'"))?;
+        let (protocol, tls) = match protocol.strip_suffix("+tls") {
+            Some(protocol) => (protocol, true),
+            None => (protocol, false),
+        };
+        Ok(ListenerConfig {
+            addr: addr.to_string(),
+            protocol: protocol.parse()?,
+            slow_consumer: None,
+            tls,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    pub backend: BackendKind,
+    /// 每个客户端消息队列的容量（条数）
+    pub client_queue_size: usize,
+    /// 每个客户端消息队列最多积压的字节数
+    pub client_queue_bytes: usize,
+    /// 客户端发来的单条消息（一帧 / 一行）的最大字节数
+    pub max_frame_size: usize,
+    /// 客户端队列已满时的处理策略
+    pub slow_consumer: SlowConsumerPolicy,
+    /// Hub 邮箱的容量，仅 actor 后端使用
+    pub hub_mailbox_size: usize,
+    /// 连接建立后必须在多少秒内登录成功
+    pub login_timeout_secs: u64,
+    /// 登录后多少秒没有发过消息就断开，0 表示不限制
+    pub idle_timeout_secs: u64,
+    /// 心跳间隔（秒），0 表示不发心跳
+    pub heartbeat_interval_secs: u64,
+    /// 对端沉默超过多少个心跳间隔就断开
+    pub heartbeat_misses: u32,
+    /// TCP keepalive 的空闲时间和探测间隔（秒），0 表示不开启
+    pub tcp_keepalive_secs: u64,
+    /// 关闭时等待客户端发完队列中消息的最长秒数
+    pub drain_timeout_secs: u64,
+    /// 每个连接一次最多合并写出的消息条数
+    pub write_batch_size: usize,
+    /// 凑批时最多等待的毫秒数，0 表示不等待
+    pub write_batch_delay_ms: u64,
+    /// 用户名和消息中终端控制字符的处理方式
+    pub sanitize: SanitizePolicy,
+    /// 用户名规则
+    pub usernames: UsernameRules,
+    /// 身份认证方式
+    pub auth: AuthConfig,
+    /// 注册账号，认证方式为 accounts 时使用
+    pub accounts: AccountConfig,
+    /// 管理员、版主和封禁名单
+    pub moderation: ModerationConfig,
+    /// 断线重连
+    pub sessions: SessionConfig,
+    /// 客户端发消息的限速
+    pub rate_limit: RateLimitConfig,
+    /// 连接数和建立连接速率的上限
+    pub connection_limits: ConnectionLimits,
+    /// TLS 端口的证书和双向 TLS
+    pub tls: TlsConfig,
+    pub log: LogConfig,
+    pub listeners: Vec<ListenerConfig>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            backend: BackendKind::Mutex,
+            client_queue_size: 100,
+            client_queue_bytes: 1024 * 1024,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            slow_consumer: SlowConsumerPolicy::default(),
+            hub_mailbox_size: 1000,
+            login_timeout_secs: 60,
+            idle_timeout_secs: 0,
+            heartbeat_interval_secs: 30,
+            heartbeat_misses: 3,
+            tcp_keepalive_secs: 60,
+            drain_timeout_secs: 10,
+            write_batch_size: 64,
+            write_batch_delay_ms: 0,
+            sanitize: SanitizePolicy::default(),
+            usernames: UsernameRules::default(),
+            auth: AuthConfig::default(),
+            accounts: AccountConfig::default(),
+            moderation: ModerationConfig::default(),
+            sessions: SessionConfig::default(),
+            rate_limit: RateLimitConfig::default(),
+            connection_limits: ConnectionLimits::default(),
+            tls: TlsConfig::default(),
+            log: LogConfig::default(),
+            listeners: vec![
+                ListenerConfig {
+                    addr: "127.0.0.1:8080".to_string(),
+                    protocol: Protocol::Json,
+                    slow_consumer: None,
+                    tls: false,
+                },
+                ListenerConfig {
+                    addr: "127.0.0.1:8081".to_string(),
+                    protocol: Protocol::WebSocket,
+                    slow_consumer: None,
+                    tls: false,
+                },
+                ListenerConfig {
+                    addr: "127.0.0.1:8082".to_string(),
+                    protocol: Protocol::Line,
+                    slow_consumer: None,
+                    tls: false,
+                }
+            ],
+        }
+    }
+}
+
+pub fn load_config(path: &str) -> Result<ServerConfig> {
+    let content = fs::read_to_string(path).with_context(|| format!("failed to read {path}"))?;
+    let config: ServerConfig =
+        toml::from_str(&content).with_context(|| format!("failed to parse {path}"))?;
+    config.rate_limit
+        .validate()
+        .with_context(|| format!("invalid [rate_limit] in {path}"))?;
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::listener::UNIX_PREFIX;
+    use std::net::SocketAddr;
+
+    #[test]
+    fn parse_listener() {
+        let listener: ListenerConfig = "websocket=127.0.0.1:8081".parse().unwrap();
+        assert_eq!(listener.protocol, Protocol::WebSocket);
+        assert_eq!(listener.addr, "127.0.0.1:8081");
+        assert!(!listener.tls);
+        assert!(listener.slow_consumer.is_none());
+    }
+
+    #[test]
+    fn parse_ipv6_listener() {
+        let listener: ListenerConfig = "json=[::1]:8080".parse().unwrap();
+        assert_eq!(listener.protocol, Protocol::Json);
+        assert_eq!(listener.addr, "[::1]:8080");
+        // 方括号里的冒号不会被当成分隔符
+        assert_eq!(listener.addr.parse::<SocketAddr>().unwrap(), "[::1]:8080".parse().unwrap());
+    }
+
+    #[test]
+    fn parse_unix_listener() {
+        let listener: ListenerConfig = "line=unix:/tmp/chat.sock".parse().unwrap();
+        assert_eq!(listener.protocol, Protocol::Line);
+        assert_eq!(listener.addr, "unix:/tmp/chat.sock");
+        assert_eq!(listener.addr.strip_prefix(UNIX_PREFIX), Some("/tmp/chat.sock"));
+    }
+
+    #[test]
+    fn parse_tls_listener() {
+        let listener: ListenerConfig = "json+tls=127.0.0.1:8443".parse().unwrap();
+        assert_eq!(listener.protocol, Protocol::Json);
+        assert_eq!(listener.addr, "127.0.0.1:8443");
+        assert!(listener.tls);
+    }
+
+    #[test]
+    fn rejects_bad_listener() {
+        let bad = ["127.0.0.1:8080", "ftp=127.0.0.1:21", "json+ssl=127.0.0.1:8443", "=127.0.0.1:1"];
+        for arg in bad {
+            assert!(arg.parse::<ListenerConfig>().is_err(), "{arg}");
+        }
+    }
+
+    #[test]
+    fn listeners_from_toml() {
+        let config: ServerConfig = toml::from_str(r#"
+            [[listeners]]
+            protocol = "json"
+            addr = "[::1]:8080"
+
+            [[listeners]]
+            protocol = "line"
+            addr = "unix:/tmp/chat.sock"
+            slow_consumer = { policy = "drop-oldest" }
+        "#).unwrap();
+        assert_eq!(config.listeners.len(), 2);
+        assert_eq!(config.listeners[0].addr, "[::1]:8080");
+        assert_eq!(config.listeners[1].addr, "unix:/tmp/chat.sock");
+        assert_eq!(config.listeners[1].slow_consumer, Some(SlowConsumerPolicy::DropOldest));
+        // 没写的项用默认值
+        assert_eq!(config.backend, BackendKind::Mutex);
+    }
+}
@@ -1,75 +1,433 @@
 // src/connection/client.rs
 
-use super::{ ClientInfo, SharedContacts }; // `super` 指向父模块 connection
-use crate::auth::username::validate_and_register_username;
-use crate::message::broadcast::broadcast_to_others;
-use crate::utils::color::{ GREEN, RESET };
-use tokio::io::{ AsyncBufReadExt, AsyncWriteExt, BufReader };
-use tokio::net::TcpStream;
-use tokio::sync::mpsc; // <--- 添加 mpsc 的 use 语句
-
-use tracing::info;
+use crate::auth::moderation::{ Role, muted_notice };
+use crate::auth::username::{ LoggedIn, validate_and_register_username };
+use crate::backend::Backend;
+use crate::codec::{ Codec, SharedMessage, Transport };
+use super::peer::PeerAddr;
+use super::queue::{ ClientReceiver, client_queue };
+use super::rate_limit::{ ConnectionLimiter, Verdict };
+use super::session::{ ParkedSession, Session };
+use super::{ ConnectionContext, ConnectionSettings, shutdown_requested };
+use crate::protocol::{ ClientMessage, ServerMessage };
+use crate::utils::sanitize::{ SanitizePolicy, sanitize_owned };
 use anyhow::Result;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{ AsyncRead, AsyncWrite };
+use tokio::time::MissedTickBehavior;
 
-pub async fn handle_connection(
-    socket: TcpStream,
-    addr: std::net::SocketAddr,
-    contact: SharedContacts
-) -> anyhow::Result<()> {
-    let (reader_stream, mut writer) = socket.into_split();
-    let mut reader: BufReader<tokio::net::tcp::OwnedReadHalf> = BufReader::new(reader_stream);
-
-    // 1. 进行用户名验证和获取
-    let username = validate_and_register_username(&mut writer, &mut reader, &contact).await?;
-
-    // 2. 为此客户端创建消息通道
-    let (tx, mut rx) = mpsc::channel(100);
-
-    // 3. 验证成功后，将客户端信息（包括其消息通道）注册到全局状态中
-    {
-        let mut guard = contact.lock().unwrap();
-        let client_info = ClientInfo {
-            addr,
-            username: username.clone(),
-            tx, // 这个 tx 是上面新创建的
-        };
-        guard.insert(username.clone(), client_info);
+use tracing::{ info, warn };
+
+/// 服务器关闭时发给每个客户端的通知
+const SHUTDOWN_NOTICE: &str = "Server is shutting down.";
+/// 空闲超时断开时的通知
+const IDLE_NOTICE: &str = "Disconnected: idle for too long.";
+/// 心跳无响应断开时的通知（对端多半已经收不到了）
+const HEARTBEAT_NOTICE: &str = "Disconnected: no response to heartbeat.";
+/// 会话被新连接接管时发给旧连接的通知
+const TAKEOVER_NOTICE: &str = "Disconnected: session resumed from another connection.";
+/// 给旧连接发接管通知最多等多久
+const TAKEOVER_NOTICE_TIMEOUT: Duration = Duration::from_secs(1);
+
+// 线路协议由 Transport 内部的 Codec 决定，聊天室状态由 Backend 决定，
+// 这里只处理 ClientMessage / ServerMessage。`certificate` 是双向 TLS 下客户端证书的 CN
+pub async fn handle_connection<S, C, B>(
+    mut transport: Transport<S, C>,
+    addr: PeerAddr,
+    certificate: Option<String>,
+    mut ctx: ConnectionContext<B>
+) -> Result<()>
+    where S: AsyncRead + AsyncWrite + Unpin, C: Codec, B: Backend
+{
+    let settings = &ctx.settings;
+    transport.set_write_timeout(settings.peer_timeout());
+
+    // 1. 为此客户端创建消息通道，tx 交给后端，rx 留给自己
+    let (tx, rx) = client_queue(
+        settings.client_queue_size,
+        settings.client_queue_bytes,
+        settings.slow_consumer,
+        settings.queue_metrics.clone()
+    );
+
+    // 2. 被封禁的 IP 直接断开；其余的进行用户名验证和注册，超时未登录的连接直接断开，不能一直占着 socket
+    if let Some(ban) = addr.ip().and_then(|ip| settings.moderation.ip_ban(ip)) {
+        info!(peer_addr = %addr, "Banned address refused.");
+        return disconnect(&mut transport, &ban.notice()).await;
+    }
+    let login = tokio::time::timeout(
+        settings.login_timeout,
+        validate_and_register_username(
+            &mut transport,
+            &ctx.backend,
+            &addr,
+            certificate.as_deref(),
+            &tx,
+            settings
+        )
+    );
+    let login = tokio::select! {
+        result = login => result,
+        _ = shutdown_requested(&mut ctx.shutdown) => {
+            // 还没注册成功，不需要注销
+            return disconnect(&mut transport, SHUTDOWN_NOTICE).await;
+        }
+    };
+    let login = match login {
+        Ok(result) => result?,
+        Err(_) => {
+            warn!(peer_addr = %addr, "Login timed out.");
+            return disconnect(&mut transport, "Login timed out.").await;
+        }
+    };
+    drop(tx);
+
+    let (username, role, reservation, mut rx) = match login {
+        LoggedIn::New { username, role, reservation } => {
+            info!(
+                username = %username,
+                role = %role,
+                peer_addr = %addr,
+                protocol = transport.protocol(),
+                backend = ctx.backend.name(),
+                "User registered successfully."
+            );
+            (username, role, reservation, rx)
+        }
+        LoggedIn::Resumed(session) => {
+            info!(
+                username = %session.username,
+                peer_addr = %addr,
+                protocol = transport.protocol(),
+                "Session resumed."
+            );
+            (session.username, session.role, session.reservation, session.rx)
+        }
+    };
+
+    // 3. 欢迎消息和会话令牌；恢复的会话随后会收到断线期间积压在队列里的消息
+    transport.send(&(ServerMessage::Welcome { username: username.clone() })).await?;
+    let sessions = ctx.settings.sessions.clone();
+    let session = if sessions.enabled() {
+        let (session, token) = sessions.issue(&username);
+        let grace_secs = sessions.grace().as_secs();
+        transport.send(&ServerMessage::SessionToken { token, grace_secs }).await?;
+        Some(session)
+    } else {
+        None
+    };
+
+    // 4. 进入主事件循环；无论以何种方式退出，都要从后端注销（或者暂存会话，宽限期后再注销）
+    let user_key = ctx.settings.usernames.rules().key(&username);
+    let mut limiter = ctx.settings.rate_limiter.connect(user_key, addr.ip());
+    let result = chat_loop(
+        &mut transport,
+        &mut ctx,
+        &username,
+        role,
+        &mut rx,
+        &mut limiter,
+        session.as_ref()
+    ).await;
+
+    // 5. 客户端断开连接后的清理工作
+    let _ = transport.shutdown().await;
+    let dropped = match &result {
+        Ok(exit) => *exit == Exit::Dropped,
+        Err(e) => e.downcast_ref::<io::Error>().is_some(),
+    };
+    if let Some(session) = session.filter(|_| dropped) {
+        info!(
+            username = %username,
+            grace_secs = sessions.grace().as_secs(),
+            "Connection lost, keeping session for resumption."
+        );
+        park_session(&ctx, session, ParkedSession { username, role, reservation, rx });
+        return result.map(|_| ());
     }
-    info!(username = %username, peer_addr = %addr, "User registered successfully.");
-    //info!("{GREEN}User '{}' (from {}) registered successfully.{RESET}", username, addr);
-    writer.write_all(format!("{GREEN}Welcome, {}!{RESET}\n", username).as_bytes()).await?;
 
-    // 4. 进入主事件循环
-    let mut line = String::new();
+    ctx.backend.deregister(&username).await;
+    drop(reservation);
+    info!(username = %username, dropped = rx.dropped(), "User session finished.");
+
+    result.map(|_| ())
+}
+
+/// 暂存断线的会话：用户名继续占用，消息继续进队列；宽限期内没有被恢复（或服务器开始关闭）就注销
+fn park_session<B: Backend>(ctx: &ConnectionContext<B>, session: Session, parked: ParkedSession) {
+    let id = session.park(parked);
+    let sessions = ctx.settings.sessions.clone();
+    let backend = ctx.backend.clone();
+    let mut shutdown = ctx.shutdown.clone();
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = tokio::time::sleep(sessions.grace()) => {}
+            _ = shutdown_requested(&mut shutdown) => {}
+        }
+        if let Some(parked) = sessions.expire(&id) {
+            backend.deregister(&parked.username).await;
+            info!(username = %parked.username, "Session expired.");
+        }
+    });
+}
+
+/// `chat_loop` 的结束方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Exit {
+    /// 连接断了（对端关闭、心跳超时、被新连接接管），会话可以在宽限期内恢复
+    Dropped,
+    /// 客户端主动退出，或者服务器主动结束了会话（空闲、刷屏、读得太慢、被踢出、服务器关闭）
+    Closed,
+}
+
+async fn chat_loop<S, C, B>(
+    transport: &mut Transport<S, C>,
+    ctx: &mut ConnectionContext<B>,
+    username: &str,
+    role: Role,
+    rx: &mut ClientReceiver,
+    limiter: &mut ConnectionLimiter,
+    session: Option<&Session>
+) -> Result<Exit>
+    where S: AsyncRead + AsyncWrite + Unpin, C: Codec, B: Backend
+{
+    let ConnectionContext { backend, commands, settings, shutdown } = ctx;
+
+    // 空闲超时：只有聊天消息和命令会重置，心跳不算
+    let idle_enabled = !settings.idle_timeout.is_zero();
+    let idle = tokio::time::sleep(settings.idle_timeout);
+    tokio::pin!(idle);
+
+    // 心跳：对端沉默超过一个间隔就发 Ping，沉默超过 heartbeat_misses 个间隔就断开
+    let peer_timeout = settings.peer_timeout().filter(|_| transport.supports_heartbeat());
+    let heartbeat_enabled = peer_timeout.is_some();
+    let period = settings.heartbeat_interval.max(Duration::from_millis(1));
+    let mut heartbeat = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+    heartbeat.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
     loop {
         tokio::select! {
-            // 从客户端读取一行输入
-            result = reader.read_line(&mut line) => {
-                let bytes_read = result?;
-                if bytes_read == 0 {
+            // 从客户端读取一条消息
+            result = transport.recv() => {
+                let Some(msg) = result? else {
                     // 客户端主动断开连接 (EOF)
-                    break;
+                    return Ok(Exit::Dropped);
+                };
+
+                // 心跳不计入限速，也不算活跃
+                match msg {
+                    Ok(ClientMessage::Ping) => {
+                        transport.send(&ServerMessage::Pong).await?;
+                        continue;
+                    }
+                    Ok(ClientMessage::Pong) => continue,
+                    // 主动退出的会话不保留
+                    Ok(ClientMessage::Quit) => return Ok(Exit::Closed),
+                    _ => idle.as_mut().reset(tokio::time::Instant::now() + settings.idle_timeout),
                 }
 
-                // 将消息广播给其他人
-                broadcast_to_others(&contact, &username, line.trim().to_string()).await;
-                line.clear();
+                // 防刷屏：超限的消息按配置提醒、丢弃或断开
+                match limiter.check() {
+                    Verdict::Allow => {}
+                    Verdict::Warn(notice) => transport.send(&notice).await?,
+                    Verdict::Drop(notice) => {
+                        if let Some(notice) = notice {
+                            transport.send(&notice).await?;
+                        }
+                        continue;
+                    }
+                    Verdict::Disconnect(notice) => {
+                        warn!(username = %username, "Disconnecting client for flooding.");
+                        transport.send(&notice).await?;
+                        return Ok(Exit::Closed);
+                    }
+                }
+
+                // 广播、私聊和命令参数都会显示在别人的终端里，先清理其中的控制字符
+                let msg = match msg.map(|msg| sanitize_message(msg, settings.sanitize)) {
+                    Ok(Some(msg)) => Ok(msg),
+                    Ok(None) => {
+                        let error = ServerMessage::error("Message cannot contain control characters.");
+                        transport.send(&error).await?;
+                        continue;
+                    }
+                    Err(reason) => Err(reason),
+                };
+
+                match msg {
+                    // 以 `/` 开头的是命令，其余的广播给其他人；被禁言时不能发言，命令照常可用
+                    Ok(ClientMessage::Broadcast { content }) => {
+                        match commands.dispatch(backend, settings, username, role, &content).await {
+                            Some(reply) => {
+                                if let Some(reply) = reply? {
+                                    transport.send(&reply).await?;
+                                }
+                            }
+                            None => match settings.moderation.muted_for(username) {
+                                Some(remaining) => transport.send(&muted_notice(remaining)).await?,
+                                None => backend.broadcast(username, content).await,
+                            },
+                        }
+                    }
+                    Ok(ClientMessage::Whisper { to, content }) => {
+                        match settings.moderation.muted_for(username) {
+                            Some(remaining) => transport.send(&muted_notice(remaining)).await?,
+                            None => {
+                                let to = settings.usernames.resolve(&to);
+                                backend.whisper(username, &to, content).await
+                            }
+                        }
+                    }
+                    Ok(
+                        | ClientMessage::Login { .. }
+                        | ClientMessage::Register { .. }
+                        | ClientMessage::Resume { .. },
+                    ) => {
+                        transport.send(&ServerMessage::error("Already logged in.")).await?;
+                    }
+                    // 已经在上面处理
+                    Ok(ClientMessage::Ping | ClientMessage::Pong | ClientMessage::Quit) => {}
+                    Err(reason) => {
+                        let err_msg = ServerMessage::error(format!("Malformed message: {reason}"));
+                        transport.send(&err_msg).await?;
+                    }
+                }
             }
             // 从其他人的广播中接收消息
-            Some(msg_to_receive) = rx.recv() => {
-                writer.write_all(msg_to_receive.as_bytes()).await?;
-                writer.write_all(b"\n").await?;
+            Some(first) = rx.recv() => {
+                // 读得太慢被队列策略踢出，或者被管理员踢出
+                if let Some(reason) = write_batch(transport, rx, first, settings).await? {
+                    warn!(username = %username, reason = %reason, "Disconnecting client.");
+                    return Ok(Exit::Closed);
+                }
+            }
+            _ = &mut idle, if idle_enabled => {
+                warn!(
+                    username = %username,
+                    idle_secs = settings.idle_timeout.as_secs(),
+                    "Disconnecting idle client."
+                );
+                transport.send(&ServerMessage::Disconnected { reason: IDLE_NOTICE.to_string() }).await?;
+                return Ok(Exit::Closed);
+            }
+            _ = heartbeat.tick(), if heartbeat_enabled => {
+                let silence = transport.last_received().elapsed();
+                if peer_timeout.is_some_and(|timeout| silence >= timeout) {
+                    warn!(
+                        username = %username,
+                        silent_secs = silence.as_secs(),
+                        "Peer missed heartbeats, disconnecting."
+                    );
+                    let notice = ServerMessage::Disconnected { reason: HEARTBEAT_NOTICE.to_string() };
+                    transport.send(&notice).await?;
+                    return Ok(Exit::Dropped);
+                }
+                if silence >= settings.heartbeat_interval {
+                    transport.send(&ServerMessage::Ping).await?;
+                }
+            }
+            // 服务器正在关闭：先把队列里已有的消息发完，再通知客户端
+            _ = shutdown_requested(shutdown) => {
+                while let Some(msg) = rx.try_recv() {
+                    transport.feed_shared(&msg)?;
+                }
+                let notice = ServerMessage::Disconnected { reason: SHUTDOWN_NOTICE.to_string() };
+                transport.send(&notice).await?;
+                return Ok(Exit::Closed);
+            }
+            // 有人用令牌恢复了这个会话：旧连接多半已经失联，通知一下就让出会话，不等写超时
+            _ = taken_over(session) => {
+                info!(username = %username, "Session taken over by a new connection.");
+                let notice = ServerMessage::Disconnected { reason: TAKEOVER_NOTICE.to_string() };
+                let _ = tokio::time::timeout(TAKEOVER_NOTICE_TIMEOUT, transport.send(&notice)).await;
+                return Ok(Exit::Dropped);
             }
         }
     }
+}
 
-    // 5. 客户端断开连接后的清理工作
-    contact.lock().unwrap().remove(&username);
-    info!(
-        "User '{}' disconnected. Active connections: {}",
-        username,
-        contact.lock().unwrap().len()
-    );
+/// 会话被新连接接管时完成；没有会话时永远不会完成
+async fn taken_over(session: Option<&Session>) {
+    match session {
+        Some(session) => session.taken_over().await,
+        None => std::future::pending().await,
+    }
+}
 
+/// 发送 `Disconnected` 并关闭连接
+async fn disconnect<S, C>(transport: &mut Transport<S, C>, reason: &str) -> Result<()>
+    where S: AsyncRead + AsyncWrite + Unpin, C: Codec
+{
+    let _ = transport.send(&(ServerMessage::Disconnected { reason: reason.to_string() })).await;
+    let _ = transport.shutdown().await;
     Ok(())
 }
+
+/// 按 `policy` 清理消息中用户提供的文本，Reject 策略下含有控制字符时返回 `None`
+fn sanitize_message(msg: ClientMessage, policy: SanitizePolicy) -> Option<ClientMessage> {
+    Some(match msg {
+        msg @ (
+            | ClientMessage::Login { .. }
+            | ClientMessage::Register { .. }
+            | ClientMessage::Resume { .. }
+            | ClientMessage::Ping
+            | ClientMessage::Pong
+            | ClientMessage::Quit
+        ) => msg,
+        ClientMessage::Broadcast { content } => {
+            ClientMessage::Broadcast { content: sanitize_owned(content, policy)? }
+        }
+        ClientMessage::Whisper { to, content } => {
+            ClientMessage::Whisper {
+                to: sanitize_owned(to, policy)?,
+                content: sanitize_owned(content, policy)?,
+            }
+        }
+    })
+}
+
+/// 把 `first` 连同队列中积压的消息（最多 `write_batch_size` 条）编码进同一个写缓冲区，
+/// 然后一次性写出，而不是每条消息写一次 socket。
+/// `write_batch_delay` 不为 0 时，队列空了还会再等一会儿，凑更多消息一起发。
+///
+/// 批次中出现 `Disconnected` 时立即写出并返回其原因，调用者应当关闭连接。
+async fn write_batch<S, C>(
+    transport: &mut Transport<S, C>,
+    rx: &mut ClientReceiver,
+    first: Arc<SharedMessage>,
+    settings: &ConnectionSettings
+) -> Result<Option<String>>
+    where S: AsyncRead + AsyncWrite + Unpin, C: Codec
+{
+    let deadline = tokio::time::Instant::now() + settings.write_batch_delay;
+    let mut msg = first;
+    let mut batched = 0;
+
+    loop {
+        transport.feed_shared(&msg)?;
+        batched += 1;
+        if let ServerMessage::Disconnected { reason } = msg.message() {
+            transport.flush().await?;
+            return Ok(Some(reason.clone()));
+        }
+        if batched >= settings.write_batch_size {
+            break;
+        }
+
+        // try_recv 只取队列中的普通消息，丢弃 / 断开通知留给下一次 recv
+        msg = match rx.try_recv() {
+            Some(next) => next,
+            None if settings.write_batch_delay.is_zero() => break,
+            // recv 可以安全地被超时取消
+            None => match tokio::time::timeout_at(deadline, rx.recv()).await {
+                Ok(Some(next)) => next,
+                _ => break,
+            },
+        };
+    }
+
+    transport.flush().await?;
+    Ok(None)
+}
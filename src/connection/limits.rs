@@ -0,0 +1,325 @@
+// src/connection/limits.rs
+
+// 连接数限制：在 accept 之后、为连接创建任务之前检查。
+//
+// 三条规则：整个服务器的连接总数上限、单个 IP 同时打开的连接数上限、单个 IP 建立新连接的速率
+// （令牌桶）。被拒绝的连接会收到一条说明原因的通知后立即关闭，不会占用连接任务。
+
+use super::rate_limit::{ RateLimit, TokenBucket };
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::atomic::{ AtomicU64, Ordering };
+use std::sync::{ Arc, Mutex };
+use std::time::Instant;
+
+/// 清理 IP 表的最低阈值，条目数不到这个数时不清理
+const PRUNE_THRESHOLD: usize = 1024;
+
+/// 连接数限制配置，各项为 0 / 空表示不限制
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ConnectionLimits {
+    /// 所有端口加起来最多同时打开的连接数
+    pub max_connections: usize,
+    /// 单个 IP 最多同时打开的连接数
+    pub per_ip: usize,
+    /// 单个 IP 建立新连接的速率
+    pub accept_rate: Option<RateLimit>,
+}
+
+/// 拒绝连接的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rejection {
+    /// 连接总数已达上限
+    ServerFull,
+    /// 该 IP 的连接数已达上限
+    TooManyFromIp,
+    /// 该 IP 建立连接太快
+    AcceptRate,
+}
+
+impl Rejection {
+    /// 关闭前发给客户端的说明
+    pub fn notice(self) -> &'static str {
+        match self {
+            Rejection::ServerFull => "Server is full, please try again later.",
+            Rejection::TooManyFromIp => "Too many connections from your address.",
+            Rejection::AcceptRate => "Connecting too fast, please try again later.",
+        }
+    }
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Rejection::ServerFull => "server full",
+            Rejection::TooManyFromIp => "too many connections from IP",
+            Rejection::AcceptRate => "accept rate exceeded",
+        })
+    }
+}
+
+/// 被拒绝的连接数，按原因分别计数
+#[derive(Debug, Default)]
+pub struct ConnectionMetrics {
+    rejected_full: AtomicU64,
+    rejected_per_ip: AtomicU64,
+    rejected_rate: AtomicU64,
+}
+
+impl ConnectionMetrics {
+    /// 因连接总数达到上限被拒绝的连接数
+    pub fn rejected_full(&self) -> u64 {
+        self.rejected_full.load(Ordering::Relaxed)
+    }
+
+    /// 因单个 IP 连接数达到上限被拒绝的连接数
+    pub fn rejected_per_ip(&self) -> u64 {
+        self.rejected_per_ip.load(Ordering::Relaxed)
+    }
+
+    /// 因建立连接太快被拒绝的连接数
+    pub fn rejected_rate(&self) -> u64 {
+        self.rejected_rate.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug)]
+struct IpEntry {
+    /// 该 IP 当前打开的连接数
+    connections: usize,
+    accepts: Option<TokenBucket>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// 所有端口当前打开的连接数
+    total: usize,
+    ips: HashMap<IpAddr, IpEntry>,
+    /// IP 表超过这个大小时清理一次
+    prune_at: usize,
+}
+
+/// 服务器范围的连接计数，所有端口共享
+#[derive(Debug, Default)]
+pub struct ConnectionGate {
+    limits: ConnectionLimits,
+    state: Mutex<State>,
+    metrics: ConnectionMetrics,
+}
+
+impl ConnectionGate {
+    pub fn new(limits: ConnectionLimits) -> Self {
+        ConnectionGate { limits, ..Default::default() }
+    }
+
+    pub fn limits(&self) -> &ConnectionLimits {
+        &self.limits
+    }
+
+    pub fn metrics(&self) -> &ConnectionMetrics {
+        &self.metrics
+    }
+
+    /// 当前打开的连接数
+    pub fn connections(&self) -> usize {
+        self.state.lock().unwrap().total
+    }
+
+    /// 新连接到来时调用。通过时返回的 ConnectionPermit 在连接结束时 drop。
+    /// `ip` 为 `None`（Unix 套接字）时只检查总连接数
+    pub fn admit(self: &Arc<Self>, ip: Option<IpAddr>) -> Result<ConnectionPermit, Rejection> {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        if state.ips.len() >= state.prune_at.max(PRUNE_THRESHOLD) {
+            state.ips.retain(|_, entry| {
+                entry.connections > 0 || entry.accepts.as_mut().is_some_and(|b| !b.is_full(now))
+            });
+            state.prune_at = state.ips.len() * 2;
+        }
+
+        let Some(rejection) = self.check(&mut state, ip, now) else {
+            state.total += 1;
+            return Ok(ConnectionPermit { gate: self.clone(), ip });
+        };
+        let counter = match rejection {
+            Rejection::ServerFull => &self.metrics.rejected_full,
+            Rejection::TooManyFromIp => &self.metrics.rejected_per_ip,
+            Rejection::AcceptRate => &self.metrics.rejected_rate,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        Err(rejection)
+    }
+
+    fn check(&self, state: &mut State, ip: Option<IpAddr>, now: Instant) -> Option<Rejection> {
+        let limits = &self.limits;
+        let full = limits.max_connections > 0 && state.total >= limits.max_connections;
+        let Some(ip) = ip else {
+            return full.then_some(Rejection::ServerFull);
+        };
+        let entry = state.ips.entry(ip).or_insert_with(|| IpEntry {
+            connections: 0,
+            accepts: limits.accept_rate.map(TokenBucket::new),
+        });
+
+        // 先扣速率令牌：服务器满了也算一次连接尝试，不断重连的客户端同样会被限速
+        if let Some(bucket) = &mut entry.accepts
+            && !bucket.take(now)
+        {
+            return Some(Rejection::AcceptRate);
+        }
+        if full {
+            return Some(Rejection::ServerFull);
+        }
+        if limits.per_ip > 0 && entry.connections >= limits.per_ip {
+            return Some(Rejection::TooManyFromIp);
+        }
+        entry.connections += 1;
+        None
+    }
+}
+
+/// 一个已被接受的连接占用的名额
+#[derive(Debug)]
+pub struct ConnectionPermit {
+    gate: Arc<ConnectionGate>,
+    ip: Option<IpAddr>,
+}
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        let mut state = self.gate.state.lock().unwrap();
+        state.total -= 1;
+        let Some(ip) = &self.ip else {
+            return;
+        };
+        if let Some(entry) = state.ips.get_mut(ip) {
+            entry.connections -= 1;
+            // 没有速率限制时条目里只剩计数，直接删掉；否则留着桶，由 admit 统一清理
+            if entry.connections == 0 && entry.accepts.is_none() {
+                state.ips.remove(ip);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn gate(
+        max_connections: usize,
+        per_ip: usize,
+        accept_rate: Option<RateLimit>
+    ) -> Arc<ConnectionGate> {
+        Arc::new(ConnectionGate::new(ConnectionLimits { max_connections, per_ip, accept_rate }))
+    }
+
+    fn ip(last: u8) -> Option<IpAddr> {
+        Some(IpAddr::from([10, 0, 0, last]))
+    }
+
+    #[test]
+    fn unlimited_by_default() {
+        let gate = Arc::new(ConnectionGate::default());
+        let permits: Vec<_> = (0..100).map(|_| gate.admit(ip(1)).unwrap()).collect();
+        assert_eq!(gate.connections(), 100);
+        drop(permits);
+        assert_eq!(gate.connections(), 0);
+    }
+
+    #[test]
+    fn global_cap() {
+        let gate = gate(2, 0, None);
+        let first = gate.admit(ip(1)).unwrap();
+        // Unix 套接字没有 IP，同样计入总数
+        let _second = gate.admit(None).unwrap();
+        assert_eq!(gate.admit(ip(2)).unwrap_err(), Rejection::ServerFull);
+        assert_eq!(gate.admit(None).unwrap_err(), Rejection::ServerFull);
+        assert_eq!(gate.metrics().rejected_full(), 2);
+
+        // 释放名额后可以再连
+        drop(first);
+        assert_eq!(gate.connections(), 1);
+        assert!(gate.admit(ip(2)).is_ok());
+    }
+
+    #[test]
+    fn per_ip_cap() {
+        let gate = gate(0, 2, None);
+        let first = gate.admit(ip(1)).unwrap();
+        let _second = gate.admit(ip(1)).unwrap();
+        assert_eq!(gate.admit(ip(1)).unwrap_err(), Rejection::TooManyFromIp);
+        // 其他 IP 和 Unix 套接字不受影响
+        let _other = gate.admit(ip(2)).unwrap();
+        let _unix = gate.admit(None).unwrap();
+        assert_eq!(gate.metrics().rejected_per_ip(), 1);
+
+        drop(first);
+        assert!(gate.admit(ip(1)).is_ok());
+        assert_eq!(gate.metrics().rejected_per_ip(), 1);
+    }
+
+    #[test]
+    fn accept_rate() {
+        let gate = gate(0, 0, Some(RateLimit::new(1.0, 2).unwrap()));
+        // 速率按建立连接的次数计算，连接关闭后也不会返还令牌
+        drop(gate.admit(ip(1)).unwrap());
+        drop(gate.admit(ip(1)).unwrap());
+        assert_eq!(gate.admit(ip(1)).unwrap_err(), Rejection::AcceptRate);
+        assert!(gate.admit(ip(2)).is_ok());
+        // Unix 套接字不限速
+        assert!(gate.admit(None).is_ok());
+        assert_eq!(gate.metrics().rejected_rate(), 1);
+
+        // 一秒后补充一个令牌
+        let later = Instant::now() + Duration::from_secs(1);
+        let mut state = gate.state.lock().unwrap();
+        assert_eq!(gate.check(&mut state, ip(1), later), None);
+        assert_eq!(gate.check(&mut state, ip(1), later), Some(Rejection::AcceptRate));
+    }
+
+    #[test]
+    fn rate_is_charged_even_when_full() {
+        let gate = gate(1, 0, Some(RateLimit::new(1.0, 2).unwrap()));
+        let _permit = gate.admit(ip(9)).unwrap();
+        assert_eq!(gate.admit(ip(1)).unwrap_err(), Rejection::ServerFull);
+        assert_eq!(gate.admit(ip(1)).unwrap_err(), Rejection::ServerFull);
+        // 不断重连的客户端用完了令牌，之后按速率拒绝
+        assert_eq!(gate.admit(ip(1)).unwrap_err(), Rejection::AcceptRate);
+        assert_eq!(gate.metrics().rejected_full(), 2);
+        assert_eq!(gate.metrics().rejected_rate(), 1);
+    }
+
+    #[test]
+    fn drop_releases_ip_entry() {
+        let gate = gate(0, 1, None);
+        let permit = gate.admit(ip(1)).unwrap();
+        assert_eq!(gate.state.lock().unwrap().ips.len(), 1);
+        drop(permit);
+        // 没有速率限制时连接全部关闭就删除条目
+        assert!(gate.state.lock().unwrap().ips.is_empty());
+        assert_eq!(gate.connections(), 0);
+    }
+
+    #[test]
+    fn prunes_idle_ips() {
+        let gate = gate(0, 0, Some(RateLimit::new(1000.0, 1).unwrap()));
+        let _held = gate.admit(ip(1)).unwrap();
+        // 加上 ip(1) 正好到清理阈值
+        for n in 1..PRUNE_THRESHOLD as u32 {
+            drop(gate.admit(Some(IpAddr::from((n + 1000).to_be_bytes()))).unwrap());
+        }
+        assert_eq!(gate.state.lock().unwrap().ips.len(), PRUNE_THRESHOLD);
+
+        // 等桶补满，下一次 admit 时清掉没有连接的条目，仍有连接的保留
+        std::thread::sleep(Duration::from_millis(10));
+        let _next = gate.admit(ip(2)).unwrap();
+        let ips: Vec<IpAddr> = gate.state.lock().unwrap().ips.keys().copied().collect();
+        assert_eq!(ips.len(), 2, "{ips:?}");
+        assert!(ips.contains(&ip(1).unwrap()));
+    }
+}
@@ -0,0 +1,229 @@
+// src/connection/listener.rs
+
+// 监听端口的两种 socket：TCP（IPv4 / IPv6）和 Unix 域套接字。
+// ChatServer 的接收循环对二者一视同仁，接收到的连接都交给同一个后端。
+// 监听地址以 `unix:` 开头时是 Unix 套接字路径，例如 `unix:/tmp/chat.sock`，供本机的管理工具使用。
+
+use super::peer::PeerAddr;
+use socket2::{ SockRef, TcpKeepalive };
+use std::future::Future;
+use std::io::{ self, Write };
+use std::time::Duration;
+use tokio::io::{ AsyncRead, AsyncWrite };
+use tokio::net::{ TcpListener, TcpStream };
+
+/// 监听地址中表示 Unix 套接字的前缀
+pub const UNIX_PREFIX: &str = "unix:";
+
+/// keepalive 探测连续多少次没有回应就断开
+const KEEPALIVE_RETRIES: u32 = 3;
+
+/// 可以接收连接的监听 socket
+pub(crate) trait Listener: Send + 'static {
+    type Stream: AsyncRead + AsyncWrite + Unpin + Send + 'static;
+
+    fn accept(&self) -> impl Future<Output = io::Result<(Self::Stream, PeerAddr)>> + Send;
+
+    /// 日志中显示的监听地址
+    fn local_addr(&self) -> io::Result<String>;
+
+    /// 在底层的非阻塞 socket 上直接写一次，不等待可写。
+    /// tokio 的 try_write 在第一次轮询就绪状态之前总是返回 WouldBlock，所以要转成标准库的 socket
+    fn write_now(stream: Self::Stream, bytes: &[u8]);
+
+    /// 开启 keepalive：连接空闲 `time` 后每隔 `time` 探测一次。只有 TCP 需要，
+    /// 对端崩溃或断网时本地的 Unix 套接字会立即收到 EOF
+    fn set_keepalive(_stream: &Self::Stream, _time: Duration) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Listener for TcpListener {
+    type Stream = TcpStream;
+
+    async fn accept(&self) -> io::Result<(TcpStream, PeerAddr)> {
+        let (stream, addr) = TcpListener::accept(self).await?;
+        Ok((stream, PeerAddr::Tcp(addr)))
+    }
+
+    fn local_addr(&self) -> io::Result<String> {
+        Ok(TcpListener::local_addr(self)?.to_string())
+    }
+
+    fn write_now(stream: TcpStream, bytes: &[u8]) {
+        if let Ok(mut stream) = stream.into_std() {
+            let _ = stream.write(bytes);
+        }
+    }
+
+    fn set_keepalive(stream: &TcpStream, time: Duration) -> io::Result<()> {
+        let keepalive = TcpKeepalive::new()
+            .with_time(time)
+            .with_interval(time)
+            .with_retries(KEEPALIVE_RETRIES);
+        SockRef::from(stream).set_tcp_keepalive(&keepalive)
+    }
+}
+
+#[cfg(unix)]
+pub(crate) use unix::UnixSocketListener;
+
+#[cfg(unix)]
+mod unix {
+    use super::{ Listener, PeerAddr };
+    use std::io::{ self, Write };
+    use std::os::unix::fs::FileTypeExt;
+    use std::path::Path;
+    use std::sync::Arc;
+    use tokio::net::{ UnixListener, UnixStream };
+
+    /// Unix 域套接字监听器，drop 时删除套接字文件
+    pub(crate) struct UnixSocketListener {
+        listener: UnixListener,
+        path: Arc<Path>,
+    }
+
+    impl UnixSocketListener {
+        /// 绑定 `path`。上次没有正常退出留下的套接字文件会被删除，但仍有服务器在监听时返回错误
+        pub(crate) fn bind(path: &Path) -> io::Result<Self> {
+            if let Ok(meta) = std::fs::symlink_metadata(path) {
+                if !meta.file_type().is_socket() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AlreadyExists,
+                        format!("{} exists and is not a socket", path.display())
+                    ));
+                }
+                if std::os::unix::net::UnixStream::connect(path).is_ok() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AddrInUse,
+                        format!("another server is listening on {}", path.display())
+                    ));
+                }
+                std::fs::remove_file(path)?;
+            }
+            Ok(UnixSocketListener {
+                listener: UnixListener::bind(path)?,
+                path: Arc::from(path),
+            })
+        }
+    }
+
+    impl Listener for UnixSocketListener {
+        type Stream = UnixStream;
+
+        async fn accept(&self) -> io::Result<(UnixStream, PeerAddr)> {
+            let (stream, _) = self.listener.accept().await?;
+            // 对端一般是未命名的 socket，用内核提供的进程凭据标识它
+            let cred = stream.peer_cred().ok();
+            let peer = PeerAddr::Unix {
+                path: self.path.clone(),
+                pid: cred.and_then(|cred| cred.pid()),
+                uid: cred.map(|cred| cred.uid()),
+            };
+            Ok((stream, peer))
+        }
+
+        fn local_addr(&self) -> io::Result<String> {
+            Ok(format!("{}{}", super::UNIX_PREFIX, self.path.display()))
+        }
+
+        fn write_now(stream: UnixStream, bytes: &[u8]) {
+            if let Ok(mut stream) = stream.into_std() {
+                let _ = stream.write(bytes);
+            }
+        }
+    }
+
+    impl Drop for UnixSocketListener {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 本机上一对已经连接的 TCP socket：（服务器端，客户端）
+    async fn tcp_pair() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (server, _) = Listener::accept(&listener).await.unwrap();
+        (server, client)
+    }
+
+    #[tokio::test]
+    async fn enables_tcp_keepalive() {
+        let (server, _client) = tcp_pair().await;
+        let socket = SockRef::from(&server);
+        assert!(!socket.keepalive().unwrap());
+
+        TcpListener::set_keepalive(&server, Duration::from_secs(45)).unwrap();
+        assert!(socket.keepalive().unwrap());
+        assert_eq!(socket.tcp_keepalive_time().unwrap(), Duration::from_secs(45));
+        assert_eq!(socket.tcp_keepalive_interval().unwrap(), Duration::from_secs(45));
+        assert_eq!(socket.tcp_keepalive_retries().unwrap(), KEEPALIVE_RETRIES);
+    }
+
+    #[cfg(unix)]
+    mod unix {
+        use super::super::UnixSocketListener;
+        use super::*;
+        use std::path::PathBuf;
+
+        fn socket_path(name: &str) -> PathBuf {
+            std::env::temp_dir().join(format!("listener-test-{}-{name}.sock", std::process::id()))
+        }
+
+        #[tokio::test]
+        async fn replaces_stale_socket_file() {
+            let path = socket_path("stale");
+            // 上次没有正常退出：套接字文件还在，但没有人在监听
+            drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+            assert!(path.exists());
+
+            let listener = UnixSocketListener::bind(&path).unwrap();
+            assert_eq!(listener.local_addr().unwrap(), format!("unix:{}", path.display()));
+            // drop 时删除套接字文件
+            drop(listener);
+            assert!(!path.exists());
+        }
+
+        #[tokio::test]
+        async fn refuses_live_socket() {
+            let path = socket_path("live");
+            let _running = UnixSocketListener::bind(&path).unwrap();
+            let err = UnixSocketListener::bind(&path).err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+            assert!(path.exists());
+        }
+
+        #[tokio::test]
+        async fn refuses_regular_file() {
+            let path = socket_path("file");
+            std::fs::write(&path, "not a socket").unwrap();
+            let err = UnixSocketListener::bind(&path).err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+            // 不是套接字的文件不能删
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), "not a socket");
+            std::fs::remove_file(&path).unwrap();
+        }
+
+        #[tokio::test]
+        async fn unix_peer_has_credentials() {
+            let path = socket_path("peer");
+            let listener = UnixSocketListener::bind(&path).unwrap();
+            let _client = tokio::net::UnixStream::connect(&path).await.unwrap();
+            let (_stream, peer) = listener.accept().await.unwrap();
+
+            let PeerAddr::Unix { path: peer_path, pid, uid } = &peer else {
+                panic!("unexpected {peer:?}");
+            };
+            assert_eq!(&**peer_path, path.as_path());
+            assert_eq!(*pid, Some(std::process::id() as i32));
+            assert!(uid.is_some());
+            assert_eq!(peer.ip(), None);
+        }
+    }
+}
@@ -3,17 +3,131 @@
 // 声明 client.rs 是 connection 模块的一部分
 // `pub` 关键字使其对外部模块（如 main.rs）可见
 pub mod client;
+pub mod limits;
+pub mod listener;
+pub mod peer;
+pub mod queue;
+pub mod rate_limit;
+pub mod session;
+pub mod tls;
 
-use std::collections::HashMap;
-use std::net::SocketAddr;
+use std::collections::{ BTreeSet, HashMap };
 use std::sync::{Arc, Mutex};
-use tokio::sync::mpsc;
+use std::time::Duration;
+
+use crate::auth::authenticator::{ Authenticator, OpenAuthenticator };
+use crate::auth::moderation::{ Moderation, Role };
+use crate::auth::rules::UsernameRegistry;
+use crate::command::CommandRegistry;
+use crate::protocol::DEFAULT_MAX_FRAME_SIZE;
+use peer::PeerAddr;
+use crate::utils::sanitize::SanitizePolicy;
+use queue::{ ClientSender, QueueMetrics, SlowConsumerPolicy };
+use rate_limit::RateLimiter;
+use session::SessionStore;
+use tokio::sync::watch;
 
 // 将 ClientInfo 公开，以便 client.rs 和其他模块可以使用
 pub struct ClientInfo {
-    pub addr: SocketAddr,
+    pub addr: PeerAddr,
     pub username: String,
-    pub tx: mpsc::Sender<String>,
+    pub role: Role,
+    pub tx: ClientSender,
+}
+
+pub type SharedContacts = Arc<Mutex<HashMap<String, ClientInfo>>>;
+
+/// 房间名 -> 成员用户名
+pub type SharedRooms = Arc<Mutex<HashMap<String, BTreeSet<String>>>>;
+
+/// 每个连接共用的运行参数，由 ChatServer 传给 handle_connection
+#[derive(Debug, Clone)]
+pub struct ConnectionSettings {
+    /// 每个客户端消息队列的容量（条数）
+    pub client_queue_size: usize,
+    /// 每个客户端消息队列最多积压的字节数
+    pub client_queue_bytes: usize,
+    /// 客户端发来的单条消息（一帧 / 一行）的最大字节数
+    pub max_frame_size: usize,
+    /// 客户端队列已满时的处理策略
+    pub slow_consumer: SlowConsumerPolicy,
+    /// 所有客户端队列共享的计数器
+    pub queue_metrics: Arc<QueueMetrics>,
+    /// 连接建立后必须在这段时间内登录成功，否则断开
+    pub login_timeout: Duration,
+    /// 登录后这么久没有发过消息就断开，为 0 表示不限制
+    pub idle_timeout: Duration,
+    /// 对端沉默超过这个间隔就发送心跳 Ping，为 0 表示不发心跳
+    pub heartbeat_interval: Duration,
+    /// 对端沉默超过多少个心跳间隔就断开；写 socket 超过同样的时间也视为对端已失联
+    pub heartbeat_misses: u32,
+    /// TCP keepalive 的空闲时间和探测间隔，为 0 表示不开启
+    pub tcp_keepalive: Duration,
+    /// 一次写出的最大消息条数
+    pub write_batch_size: usize,
+    /// 凑批时最多等待多久；为 0 时只合并已经在队列里的消息，不额外等待
+    pub write_batch_delay: Duration,
+    /// 用户名和消息中终端控制字符的处理方式
+    pub sanitize: SanitizePolicy,
+    /// 用户名规则以及在线用户名的登记表
+    pub usernames: Arc<UsernameRegistry>,
+    /// 所有连接共享的限速器
+    pub rate_limiter: Arc<RateLimiter>,
+    /// 身份认证方式
+    pub authenticator: Arc<dyn Authenticator>,
+    /// 断线重连用的会话
+    pub sessions: Arc<SessionStore>,
+    /// 角色名单、封禁和禁言
+    pub moderation: Arc<Moderation>,
+}
+
+impl ConnectionSettings {
+    /// 对端多久不响应（不读也不写）就视为失联，心跳关闭时为 `None`
+    pub fn peer_timeout(&self) -> Option<Duration> {
+        if self.heartbeat_interval.is_zero() {
+            return None;
+        }
+        Some(self.heartbeat_interval * self.heartbeat_misses.max(1))
+    }
+}
+
+impl Default for ConnectionSettings {
+    fn default() -> Self {
+        ConnectionSettings {
+            client_queue_size: 100,
+            client_queue_bytes: 1024 * 1024,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            slow_consumer: SlowConsumerPolicy::default(),
+            queue_metrics: Arc::new(QueueMetrics::default()),
+            login_timeout: Duration::from_secs(60),
+            idle_timeout: Duration::ZERO,
+            heartbeat_interval: Duration::from_secs(30),
+            heartbeat_misses: 3,
+            tcp_keepalive: Duration::from_secs(60),
+            write_batch_size: 64,
+            write_batch_delay: Duration::ZERO,
+            sanitize: SanitizePolicy::default(),
+            usernames: Arc::new(UsernameRegistry::default()),
+            rate_limiter: Arc::new(RateLimiter::default()),
+            authenticator: Arc::new(OpenAuthenticator),
+            sessions: Arc::new(SessionStore::default()),
+            moderation: Arc::new(Moderation::default()),
+        }
+    }
 }
 
-pub type SharedContacts = Arc<Mutex<HashMap<String, ClientInfo>>>;
\ No newline at end of file
+/// 一个连接处理任务需要的全部共享状态，每个连接持有一份 clone
+#[derive(Clone)]
+pub struct ConnectionContext<B> {
+    pub backend: B,
+    pub commands: Arc<CommandRegistry<B>>,
+    pub settings: ConnectionSettings,
+    /// 变为 true 时连接应当发完队列中的消息后主动断开
+    pub shutdown: watch::Receiver<bool>,
+}
+
+/// 等待关闭信号。发送端被 drop 也视为关闭
+pub async fn shutdown_requested(shutdown: &mut watch::Receiver<bool>) {
+    // 不能把 wait_for 返回的 Ref 留在 select! 里跨 await，它不是 Send
+    let _ = shutdown.wait_for(|stop| *stop).await;
+}
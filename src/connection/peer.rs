@@ -0,0 +1,92 @@
+// src/connection/peer.rs
+
+// 连接的对端：TCP（IPv4 / IPv6）或 Unix 域套接字。
+// 按 IP 计算的东西（连接数上限、限速、IP 封禁）只对 TCP 对端生效，Unix 套接字上的本机工具不受影响。
+
+use std::fmt;
+use std::net::{ IpAddr, SocketAddr };
+use std::path::Path;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PeerAddr {
+    Tcp(SocketAddr),
+    /// Unix 域套接字。客户端一般是未命名的 socket，所以记录监听路径和对端进程（能取到时）
+    Unix {
+        path: Arc<Path>,
+        pid: Option<i32>,
+        uid: Option<u32>,
+    },
+}
+
+impl PeerAddr {
+    /// 对端 IP，Unix 套接字为 `None`。
+    /// 双栈监听（`[::]`）收到的 IPv4 连接是 `::ffff:a.b.c.d`，这里换回 IPv4，封禁和限额才能对上
+    pub fn ip(&self) -> Option<IpAddr> {
+        match self {
+            PeerAddr::Tcp(addr) => Some(addr.ip().to_canonical()),
+            PeerAddr::Unix { .. } => None,
+        }
+    }
+}
+
+impl From<SocketAddr> for PeerAddr {
+    fn from(addr: SocketAddr) -> Self {
+        PeerAddr::Tcp(addr)
+    }
+}
+
+impl fmt::Display for PeerAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PeerAddr::Tcp(addr) => write!(f, "{addr}"),
+            PeerAddr::Unix { path, pid, uid } => {
+                write!(f, "unix:{}", path.display())?;
+                match (pid, uid) {
+                    (Some(pid), Some(uid)) => write!(f, " (pid {pid}, uid {uid})"),
+                    (None, Some(uid)) => write!(f, " (uid {uid})"),
+                    _ => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unix(pid: Option<i32>, uid: Option<u32>) -> PeerAddr {
+        PeerAddr::Unix { path: Arc::from(Path::new("/tmp/chat.sock")), pid, uid }
+    }
+
+    #[test]
+    fn ip_is_canonical() {
+        let mapped: SocketAddr = "[::ffff:192.0.2.7]:4000".parse().unwrap();
+        assert_eq!(PeerAddr::from(mapped).ip(), Some(IpAddr::from([192, 0, 2, 7])));
+
+        let v4: SocketAddr = "192.0.2.7:4000".parse().unwrap();
+        assert_eq!(PeerAddr::from(v4).ip(), Some(IpAddr::from([192, 0, 2, 7])));
+        // 普通的 IPv6 地址保持不变
+        let v6: SocketAddr = "[2001:db8::1]:4000".parse().unwrap();
+        assert_eq!(PeerAddr::from(v6).ip(), Some("2001:db8::1".parse().unwrap()));
+        let loopback: SocketAddr = "[::1]:4000".parse().unwrap();
+        assert_eq!(PeerAddr::from(loopback).ip(), Some("::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn unix_peer_has_no_ip() {
+        assert_eq!(unix(Some(42), Some(1000)).ip(), None);
+        assert_eq!(unix(None, None).ip(), None);
+    }
+
+    #[test]
+    fn display() {
+        let tcp: SocketAddr = "[::1]:4000".parse().unwrap();
+        assert_eq!(PeerAddr::from(tcp).to_string(), "[::1]:4000");
+        let full = unix(Some(42), Some(1000));
+        assert_eq!(full.to_string(), "unix:/tmp/chat.sock (pid 42, uid 1000)");
+        assert_eq!(unix(None, Some(1000)).to_string(), "unix:/tmp/chat.sock (uid 1000)");
+        assert_eq!(unix(None, None).to_string(), "unix:/tmp/chat.sock");
+    }
+}
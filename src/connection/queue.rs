@@ -0,0 +1,642 @@
+// src/connection/queue.rs
+
+// 每个客户端的待发送消息队列。
+//
+// 队列同时限制消息条数和积压的字节数，任一项达到上限即视为已满，
+// 所以少量超大消息也不能让一个读得慢的客户端占用大量内存。
+// 与 mpsc 不同，队列满时的行为由 SlowConsumerPolicy 决定，而不是一律等待（拖住所有发送者）
+// 或一律静默丢弃。被丢弃的消息会被计数，接收端在下一次 recv 时先收到一条 MessagesDropped 通知。
+// 队列中保存的是 Arc<SharedMessage>，同一条广播在所有接收者的队列里共享一份。
+
+use crate::codec::SharedMessage;
+use crate::protocol::ServerMessage;
+use anyhow::{ Result, anyhow, bail };
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{ AtomicU64, Ordering };
+use std::sync::{ Arc, Mutex };
+use std::time::{ Duration, Instant };
+use tokio::sync::Notify;
+
+/// 客户端队列已满时的处理策略
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(tag = "policy", rename_all = "kebab-case")]
+pub enum SlowConsumerPolicy {
+    /// 丢弃新消息
+    #[default]
+    DropNewest,
+    /// 环形缓冲：丢弃队列中最旧的消息，为新消息腾出位置
+    DropOldest,
+    /// 丢弃新消息，累计丢弃 `max_drops` 条后断开该客户端
+    Disconnect {
+        max_drops: u64,
+    },
+    /// 最多等待 `timeout_ms` 毫秒，仍然没有空位就丢弃新消息。
+    /// 不能等待的发送者（Hub）按 DropNewest 处理
+    Wait {
+        timeout_ms: u64,
+    },
+}
+
+impl fmt::Display for SlowConsumerPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SlowConsumerPolicy::DropNewest => f.write_str("drop-newest"),
+            SlowConsumerPolicy::DropOldest => f.write_str("drop-oldest"),
+            SlowConsumerPolicy::Disconnect { max_drops } => write!(f, "disconnect:{max_drops}"),
+            SlowConsumerPolicy::Wait { timeout_ms } => write!(f, "wait:{timeout_ms}"),
+        }
+    }
+}
+
+/// 命令行写法：`drop-newest`、`drop-oldest`、`disconnect:N`、`wait:MS`
+impl FromStr for SlowConsumerPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, arg) = match s.split_once(':') {
+            Some((name, arg)) => (name, Some(arg)),
+            None => (s, None),
+        };
+        let number = |what: &str| -> Result<u64> {
+            let arg = arg.ok_or_else(|| anyhow!("'{name}' needs a {what}, e.g. {name}:100"))?;
+            arg.parse().map_err(|_| anyhow!("invalid {what} '{arg}'"))
+        };
+
+        let policy = match name {
+            "drop-newest" => SlowConsumerPolicy::DropNewest,
+            "drop-oldest" => SlowConsumerPolicy::DropOldest,
+            "disconnect" => SlowConsumerPolicy::Disconnect { max_drops: number("drop count")? },
+            "wait" => SlowConsumerPolicy::Wait { timeout_ms: number("timeout in ms")? },
+            _ => bail!(
+                "unknown slow consumer policy '{s}' (expected drop-newest, drop-oldest, disconnect:N or wait:MS)"
+            ),
+        };
+        let takes_arg = !matches!(
+            policy,
+            SlowConsumerPolicy::DropNewest | SlowConsumerPolicy::DropOldest
+        );
+        if arg.is_some() && !takes_arg {
+            bail!("'{name}' does not take an argument");
+        }
+        Ok(policy)
+    }
+}
+
+/// 整个服务器范围内的队列计数器
+#[derive(Debug, Default)]
+pub struct QueueMetrics {
+    dropped: AtomicU64,
+    disconnected: AtomicU64,
+}
+
+impl QueueMetrics {
+    /// 因队列已满被丢弃的消息总数
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// 因 Disconnect 策略被断开的客户端总数
+    pub fn disconnected(&self) -> u64 {
+        self.disconnected.load(Ordering::Relaxed)
+    }
+}
+
+struct State {
+    queue: VecDeque<Arc<SharedMessage>>,
+    /// 队列中所有消息的字节数之和
+    bytes: usize,
+    /// 尚未通知客户端的丢弃数
+    unreported: u64,
+    /// 该客户端累计丢弃数
+    dropped: u64,
+    senders: usize,
+    receiver_closed: bool,
+    /// Disconnect 策略触发或被管理员踢出时的断开原因，接收端应当断开
+    kicked: Option<String>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    capacity: usize,
+    max_bytes: usize,
+    policy: SlowConsumerPolicy,
+    metrics: Arc<QueueMetrics>,
+    /// 有新消息、发送端全部关闭或被踢出时唤醒接收端
+    readable: Notify,
+    /// 有空位或接收端关闭时唤醒等待中的发送端（Wait 策略）
+    writable: Notify,
+}
+
+impl State {
+    /// 再放入 `size` 字节的消息是否会超过上限。
+    /// 队列为空时总能放入，否则单条超过 `max_bytes` 的消息永远发不出去
+    fn is_full(&self, shared: &Shared, size: usize) -> bool {
+        !self.queue.is_empty()
+            && (self.queue.len() >= shared.capacity || self.bytes + size > shared.max_bytes)
+    }
+
+    fn push(&mut self, msg: Arc<SharedMessage>, size: usize) {
+        self.bytes += size;
+        self.queue.push_back(msg);
+    }
+
+    fn pop(&mut self) -> Option<Arc<SharedMessage>> {
+        let msg = self.queue.pop_front()?;
+        self.bytes -= msg.size();
+        Some(msg)
+    }
+}
+
+/// 创建一个客户端队列：最多 `capacity` 条消息、`max_bytes` 字节
+pub fn client_queue(
+    capacity: usize,
+    max_bytes: usize,
+    policy: SlowConsumerPolicy,
+    metrics: Arc<QueueMetrics>
+) -> (ClientSender, ClientReceiver) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            queue: VecDeque::with_capacity(capacity.min(1024)),
+            bytes: 0,
+            unreported: 0,
+            dropped: 0,
+            senders: 1,
+            receiver_closed: false,
+            kicked: None,
+        }),
+        capacity: capacity.max(1),
+        max_bytes,
+        policy,
+        metrics,
+        readable: Notify::new(),
+        writable: Notify::new(),
+    });
+    (ClientSender { shared: shared.clone() }, ClientReceiver { shared, finished: false })
+}
+
+/// 队列的发送端，由后端持有，可以廉价地 clone
+pub struct ClientSender {
+    shared: Arc<Shared>,
+}
+
+impl ClientSender {
+    /// 发送一条消息。只有 Wait 策略会在队列已满时等待（有上限），其余策略立即返回
+    pub async fn send(&self, msg: impl Into<Arc<SharedMessage>>) {
+        let msg = msg.into();
+        match self.shared.policy {
+            SlowConsumerPolicy::Wait { timeout_ms } => {
+                let deadline = Instant::now() + Duration::from_millis(timeout_ms);
+                self.send_until(msg, deadline).await;
+            }
+            _ => self.try_send(msg),
+        }
+    }
+
+    /// Wait 策略：等到有空位或 `deadline`，仍然没有空位就丢弃。
+    /// 期间客户端被踢出或断开时放弃发送
+    async fn send_until(&self, msg: Arc<SharedMessage>, deadline: Instant) {
+        let size = msg.size();
+        let mut msg = Some(msg);
+        let wait = async {
+            loop {
+                // 先注册等待再检查，避免错过检查和等待之间的唤醒
+                let writable = self.shared.writable.notified();
+                {
+                    let mut state = self.shared.state.lock().unwrap();
+                    if state.receiver_closed || state.kicked.is_some() {
+                        return;
+                    }
+                    if !state.is_full(&self.shared, size) {
+                        state.push(msg.take().expect("message is sent once"), size);
+                        drop(state);
+                        self.shared.readable.notify_one();
+                        return;
+                    }
+                }
+                writable.await;
+            }
+        };
+        if tokio::time::timeout_at(deadline.into(), wait).await.is_err() {
+            let mut state = self.shared.state.lock().unwrap();
+            if !state.receiver_closed && state.kicked.is_none() {
+                self.record_drop(&mut state);
+            }
+        }
+    }
+
+    /// 队列有空位时放入消息并返回 true，已满时什么也不做。
+    /// 接收端已经关闭或被踢出时也返回 true：这条消息不需要再等
+    fn offer(&self, msg: &Arc<SharedMessage>) -> bool {
+        let size = msg.size();
+        let mut state = self.shared.state.lock().unwrap();
+        if state.receiver_closed || state.kicked.is_some() {
+            return true;
+        }
+        if state.is_full(&self.shared, size) {
+            return false;
+        }
+        state.push(msg.clone(), size);
+        drop(state);
+        self.shared.readable.notify_one();
+        true
+    }
+
+    /// 不等待地发送一条消息，队列已满时按策略处理（Wait 策略按 DropNewest 处理）
+    pub fn try_send(&self, msg: impl Into<Arc<SharedMessage>>) {
+        let msg = msg.into();
+        // 在加锁前算好大小（可能需要序列化）
+        let size = msg.size();
+        let mut state = self.shared.state.lock().unwrap();
+        if state.receiver_closed || state.kicked.is_some() {
+            return;
+        }
+
+        if state.is_full(&self.shared, size) {
+            match self.shared.policy {
+                SlowConsumerPolicy::DropOldest => {
+                    // 按字节限制时，一条大消息可能要挤掉好几条旧消息
+                    while state.is_full(&self.shared, size) {
+                        state.pop();
+                        self.record_drop(&mut state);
+                    }
+                    state.push(msg, size);
+                }
+                _ => self.record_drop(&mut state),
+            }
+        } else {
+            state.push(msg, size);
+        }
+        drop(state);
+        self.shared.readable.notify_one();
+    }
+
+    /// 该客户端累计被丢弃的消息数
+    pub fn dropped(&self) -> u64 {
+        self.shared.state.lock().unwrap().dropped
+    }
+
+    /// 让客户端断开：清空队列，接收端下一次 recv 返回一条以 `reason` 为原因的 `Disconnected`
+    pub fn kick(&self, reason: impl Into<String>) {
+        let mut state = self.shared.state.lock().unwrap();
+        if state.receiver_closed || state.kicked.is_some() {
+            return;
+        }
+        state.kicked = Some(reason.into());
+        state.queue.clear();
+        state.bytes = 0;
+        drop(state);
+        self.shared.readable.notify_one();
+        self.shared.writable.notify_waiters();
+    }
+
+    /// 接收端是否已经关闭（客户端已断开）
+    pub fn is_closed(&self) -> bool {
+        self.shared.state.lock().unwrap().receiver_closed
+    }
+
+    fn record_drop(&self, state: &mut State) {
+        state.dropped += 1;
+        state.unreported += 1;
+        self.shared.metrics.dropped.fetch_add(1, Ordering::Relaxed);
+
+        if let SlowConsumerPolicy::Disconnect { max_drops } = self.shared.policy
+            && state.dropped >= max_drops
+            && state.kicked.is_none()
+        {
+            state.kicked = Some(format!(
+                "Disconnected: {} messages dropped because you are reading too slowly.",
+                state.dropped
+            ));
+            state.queue.clear();
+            state.bytes = 0;
+            self.shared.metrics.disconnected.fetch_add(1, Ordering::Relaxed);
+            self.shared.writable.notify_waiters();
+        }
+        self.shared.readable.notify_one();
+    }
+}
+
+/// 把同一条消息发给多个客户端。
+///
+/// 先把消息放进所有还有空位的队列，读得快的客户端不用排在读得慢的后面；
+/// Wait 策略下队列已满的接收者共用同一个截止时间，所以一条广播最多等待一个 timeout_ms，
+/// 而不是每个读得慢的接收者各等一次
+pub async fn send_all(senders: &[ClientSender], msg: impl Into<Arc<SharedMessage>>) {
+    let msg = msg.into();
+    let start = Instant::now();
+    let mut full = Vec::new();
+    for tx in senders {
+        match tx.shared.policy {
+            SlowConsumerPolicy::Wait { timeout_ms } => {
+                if !tx.offer(&msg) {
+                    full.push((tx, start + Duration::from_millis(timeout_ms)));
+                }
+            }
+            _ => tx.try_send(msg.clone()),
+        }
+    }
+    for (tx, deadline) in full {
+        tx.send_until(msg.clone(), deadline).await;
+    }
+}
+
+impl Clone for ClientSender {
+    fn clone(&self) -> Self {
+        self.shared.state.lock().unwrap().senders += 1;
+        ClientSender { shared: self.shared.clone() }
+    }
+}
+
+impl Drop for ClientSender {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.senders -= 1;
+        if state.senders == 0 {
+            drop(state);
+            self.shared.readable.notify_one();
+        }
+    }
+}
+
+impl fmt::Debug for ClientSender {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClientSender").field("policy", &self.shared.policy).finish_non_exhaustive()
+    }
+}
+
+/// 队列的接收端，由连接任务持有
+pub struct ClientReceiver {
+    shared: Arc<Shared>,
+    /// 已经返回过断开通知或发送端已全部关闭
+    finished: bool,
+}
+
+impl ClientReceiver {
+    /// 取出下一条要发给客户端的消息。
+    ///
+    /// 有消息被丢弃时先返回一条 `MessagesDropped`；被 Disconnect 策略或 `ClientSender::kick` 踢出时
+    /// 返回一条 `Disconnected`，调用者发出后应当关闭连接。所有发送端关闭后返回 `None`。
+    /// 该方法可以安全地用在 `tokio::select!` 中。
+    pub async fn recv(&mut self) -> Option<Arc<SharedMessage>> {
+        loop {
+            if self.finished {
+                return None;
+            }
+            {
+                let mut state = self.shared.state.lock().unwrap();
+                if let Some(reason) = state.kicked.clone() {
+                    self.finished = true;
+                    return Some(SharedMessage::new(ServerMessage::Disconnected { reason }));
+                }
+                if state.unreported > 0 {
+                    let count = std::mem::take(&mut state.unreported);
+                    return Some(SharedMessage::new(ServerMessage::MessagesDropped { count }));
+                }
+                if let Some(msg) = state.pop() {
+                    drop(state);
+                    self.shared.writable.notify_one();
+                    return Some(msg);
+                }
+                if state.senders == 0 {
+                    self.finished = true;
+                    return None;
+                }
+            }
+            // notify_one 会保存一个许可，所以检查和等待之间的唤醒不会丢失
+            self.shared.readable.notified().await;
+        }
+    }
+
+    /// 不等待地取出一条已经在队列中的消息（关闭时用来排空队列）
+    pub fn try_recv(&mut self) -> Option<Arc<SharedMessage>> {
+        let msg = self.shared.state.lock().unwrap().pop();
+        if msg.is_some() {
+            self.shared.writable.notify_one();
+        }
+        msg
+    }
+
+    /// 该客户端累计被丢弃的消息数
+    pub fn dropped(&self) -> u64 {
+        self.shared.state.lock().unwrap().dropped
+    }
+}
+
+impl Drop for ClientReceiver {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.receiver_closed = true;
+        state.queue.clear();
+        state.bytes = 0;
+        drop(state);
+        self.shared.writable.notify_waiters();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue(capacity: usize, policy: SlowConsumerPolicy) -> (ClientSender, ClientReceiver) {
+        client_queue(capacity, usize::MAX, policy, Arc::default())
+    }
+
+    fn message(n: usize) -> Arc<SharedMessage> {
+        SharedMessage::new(ServerMessage::error(format!("message {n}")))
+    }
+
+    /// 取出下一条消息并检查它就是 `expected`
+    async fn expect(rx: &mut ClientReceiver, expected: &Arc<SharedMessage>) {
+        let msg = rx.recv().await.expect("queue is open");
+        assert!(Arc::ptr_eq(&msg, expected), "unexpected {:?}", msg.message());
+    }
+
+    async fn expect_dropped(rx: &mut ClientReceiver, count: u64) {
+        let msg = rx.recv().await.expect("queue is open");
+        match msg.message() {
+            ServerMessage::MessagesDropped { count: got } => assert_eq!(*got, count),
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn drop_newest_keeps_queued_messages() {
+        let (tx, mut rx) = queue(2, SlowConsumerPolicy::DropNewest);
+        let msgs: Vec<_> = (0..3).map(message).collect();
+        for msg in &msgs {
+            tx.send(msg.clone()).await;
+        }
+
+        expect_dropped(&mut rx, 1).await;
+        expect(&mut rx, &msgs[0]).await;
+        expect(&mut rx, &msgs[1]).await;
+        assert_eq!(rx.dropped(), 1);
+    }
+
+    #[tokio::test]
+    async fn drop_oldest_makes_room() {
+        let (tx, mut rx) = queue(2, SlowConsumerPolicy::DropOldest);
+        let msgs: Vec<_> = (0..3).map(message).collect();
+        for msg in &msgs {
+            tx.send(msg.clone()).await;
+        }
+
+        expect_dropped(&mut rx, 1).await;
+        expect(&mut rx, &msgs[1]).await;
+        expect(&mut rx, &msgs[2]).await;
+    }
+
+    #[tokio::test]
+    async fn disconnect_after_max_drops() {
+        let metrics = Arc::new(QueueMetrics::default());
+        let policy = SlowConsumerPolicy::Disconnect { max_drops: 2 };
+        let (tx, mut rx) = client_queue(1, usize::MAX, policy, metrics.clone());
+        for n in 0..4 {
+            tx.send(message(n)).await;
+        }
+
+        let msg = rx.recv().await.unwrap();
+        assert!(matches!(msg.message(), ServerMessage::Disconnected { .. }));
+        assert!(rx.recv().await.is_none());
+        // 断开之后的消息不再计数
+        assert_eq!((rx.dropped(), metrics.dropped(), metrics.disconnected()), (2, 2, 1));
+    }
+
+    #[tokio::test]
+    async fn wait_delivers_when_receiver_catches_up() {
+        let (tx, mut rx) = queue(1, SlowConsumerPolicy::Wait { timeout_ms: 5_000 });
+        let first = message(0);
+        let second = message(1);
+        tx.send(first.clone()).await;
+
+        let reader = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            expect(&mut rx, &first).await;
+            rx
+        });
+        tx.send(second.clone()).await;
+        let mut rx = reader.await.unwrap();
+        expect(&mut rx, &second).await;
+        assert_eq!(rx.dropped(), 0);
+    }
+
+    #[tokio::test]
+    async fn wait_drops_after_timeout() {
+        let (tx, mut rx) = queue(1, SlowConsumerPolicy::Wait { timeout_ms: 20 });
+        let first = message(0);
+        tx.send(first.clone()).await;
+
+        let start = Instant::now();
+        tx.send(message(1)).await;
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        expect_dropped(&mut rx, 1).await;
+        expect(&mut rx, &first).await;
+    }
+
+    #[tokio::test]
+    async fn wait_gives_up_when_kicked() {
+        let (tx, mut rx) = queue(1, SlowConsumerPolicy::Wait { timeout_ms: 60_000 });
+        tx.send(message(0)).await;
+
+        let kicker = tx.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            kicker.kick("bye");
+        });
+        tokio::time::timeout(Duration::from_secs(5), tx.send(message(1)))
+            .await
+            .expect("a kicked queue must not keep the sender waiting");
+
+        let msg = rx.recv().await.unwrap();
+        assert!(matches!(msg.message(), ServerMessage::Disconnected { reason } if reason == "bye"));
+        assert!(rx.recv().await.is_none());
+        assert_eq!(rx.dropped(), 0);
+    }
+
+    #[tokio::test]
+    async fn send_all_shares_one_deadline() {
+        let policy = SlowConsumerPolicy::Wait { timeout_ms: 100 };
+        let (slow, fast): (Vec<_>, Vec<_>) = (0..6)
+            .map(|_| queue(1, policy))
+            .enumerate()
+            .partition(|(i, _)| i % 2 == 0);
+        let senders: Vec<ClientSender> = slow
+            .iter()
+            .chain(&fast)
+            .map(|(_, (tx, _))| tx.clone())
+            .collect();
+        for (_, (tx, _)) in &slow {
+            tx.send(message(0)).await;
+        }
+
+        let start = Instant::now();
+        let msg = message(1);
+        send_all(&senders, msg.clone()).await;
+        // 三个读得慢的接收者共用一个超时，而不是各等一次
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(100));
+        assert!(elapsed < Duration::from_millis(250), "took {elapsed:?}");
+
+        for (_, (_, mut rx)) in fast {
+            expect(&mut rx, &msg).await;
+        }
+        for (_, (_, mut rx)) in slow {
+            expect_dropped(&mut rx, 1).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn byte_cap_limits_queue() {
+        let msgs: Vec<_> = (0..3).map(message).collect();
+        let size = msgs[0].size();
+        let metrics = Arc::default();
+        let (tx, mut rx) = client_queue(100, 2 * size, SlowConsumerPolicy::DropNewest, metrics);
+        for msg in &msgs {
+            tx.send(msg.clone()).await;
+        }
+
+        expect_dropped(&mut rx, 1).await;
+        expect(&mut rx, &msgs[0]).await;
+        expect(&mut rx, &msgs[1]).await;
+
+        // 单条超过上限的消息在队列为空时仍然可以放入
+        let big = SharedMessage::new(ServerMessage::error("x".repeat(10 * size)));
+        tx.send(big.clone()).await;
+        expect(&mut rx, &big).await;
+    }
+
+    #[tokio::test]
+    async fn drop_oldest_respects_byte_cap() {
+        let msgs: Vec<_> = (0..3).map(message).collect();
+        let size = msgs[0].size();
+        let metrics = Arc::default();
+        let (tx, mut rx) = client_queue(100, 3 * size, SlowConsumerPolicy::DropOldest, metrics);
+        for msg in &msgs {
+            tx.send(msg.clone()).await;
+        }
+        // 一条大消息挤掉所有旧消息
+        let big = SharedMessage::new(ServerMessage::error("x".repeat(2 * size)));
+        tx.send(big.clone()).await;
+
+        expect_dropped(&mut rx, 3).await;
+        expect(&mut rx, &big).await;
+    }
+
+    #[test]
+    fn parses_policies() {
+        for policy in [
+            SlowConsumerPolicy::DropNewest,
+            SlowConsumerPolicy::DropOldest,
+            SlowConsumerPolicy::Disconnect { max_drops: 10 },
+            SlowConsumerPolicy::Wait { timeout_ms: 250 },
+        ] {
+            assert_eq!(policy.to_string().parse::<SlowConsumerPolicy>().unwrap(), policy);
+        }
+        for invalid in ["wait", "wait:soon", "drop-newest:1", "block"] {
+            assert!(invalid.parse::<SlowConsumerPolicy>().is_err(), "{invalid}");
+        }
+    }
+}
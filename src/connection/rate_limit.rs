@@ -0,0 +1,602 @@
+// src/connection/rate_limit.rs
+
+// 防刷屏：对客户端发来的每条消息（广播、私聊、命令）做令牌桶限速。
+//
+// 每个用户一个桶（按用户名的规范化键，同一用户的所有连接共享），每个 IP 一个桶（同一 IP 的所有连接共享）；
+// 两个桶都有令牌时消息才会放行。超限时的处理由 RateLimitAction 决定。
+// 桶和禁言状态都保存在服务器范围的 RateLimiter 里，断线重连既不会补满令牌，也不会解除禁言；
+// 补满且没有禁言的条目和新建的没有区别，由 RateLimiter 在表变大时统一清理。
+
+use crate::protocol::ServerMessage;
+use anyhow::{ Result, anyhow, bail };
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::atomic::{ AtomicU64, Ordering };
+use std::sync::{ Arc, Mutex };
+use std::time::{ Duration, Instant };
+
+/// 清理用户表和 IP 表的最低阈值，条目数不到这个数时不清理
+const PRUNE_THRESHOLD: usize = 1024;
+
+/// `mute:SECS` 的上限（365 天）
+pub const MAX_MUTE_SECS: u64 = 365 * 24 * 60 * 60;
+
+/// 一个令牌桶的参数：每秒补充 `rate` 个令牌，最多攒 `burst` 个
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "RawRateLimit")]
+pub struct RateLimit {
+    pub rate: f64,
+    pub burst: u32,
+}
+
+impl RateLimit {
+    /// `rate` 必须大于 0，`burst` 至少为 1
+    pub fn new(rate: f64, burst: u32) -> Result<Self> {
+        // NaN 和 0 一样，永远补充不了令牌
+        if rate <= 0.0 || rate.is_nan() || burst == 0 {
+            bail!("rate and burst must both be positive, got rate = {rate}, burst = {burst}");
+        }
+        Ok(RateLimit { rate, burst })
+    }
+}
+
+/// 配置文件中的写法，反序列化后经过 `RateLimit::new` 校验
+#[derive(Deserialize)]
+struct RawRateLimit {
+    rate: f64,
+    burst: u32,
+}
+
+impl TryFrom<RawRateLimit> for RateLimit {
+    type Error = anyhow::Error;
+
+    fn try_from(raw: RawRateLimit) -> Result<Self> {
+        RateLimit::new(raw.rate, raw.burst)
+    }
+}
+
+/// 命令行写法：`RATE:BURST`，例如 `5:10`
+impl FromStr for RateLimit {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (rate, burst) = s
+            .split_once(':')
+            .ok_or_else(|| anyhow!("expected RATE:BURST, got '{s}'"))?;
+        let rate: f64 = rate.parse().map_err(|_| anyhow!("invalid rate '{rate}'"))?;
+        let burst: u32 = burst.parse().map_err(|_| anyhow!("invalid burst '{burst}'"))?;
+        RateLimit::new(rate, burst)
+    }
+}
+
+/// 超过限速时的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(tag = "action", rename_all = "kebab-case")]
+pub enum RateLimitAction {
+    /// 照常处理，但提醒客户端
+    Warn,
+    /// 丢弃超限的消息
+    #[default]
+    Drop,
+    /// 丢弃超限的消息，并在 `secs` 秒内丢弃该用户的所有消息
+    Mute {
+        secs: u64,
+    },
+    /// 断开连接
+    Disconnect,
+}
+
+impl RateLimitAction {
+    /// 禁言时长不能为 0，也不能超过 `MAX_MUTE_SECS`
+    pub fn validate(&self) -> Result<()> {
+        if let RateLimitAction::Mute { secs } = *self
+            && !(1..=MAX_MUTE_SECS).contains(&secs)
+        {
+            bail!("mute duration must be between 1 and {MAX_MUTE_SECS} seconds, got {secs}");
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for RateLimitAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RateLimitAction::Warn => f.write_str("warn"),
+            RateLimitAction::Drop => f.write_str("drop"),
+            RateLimitAction::Mute { secs } => write!(f, "mute:{secs}"),
+            RateLimitAction::Disconnect => f.write_str("disconnect"),
+        }
+    }
+}
+
+/// 命令行写法：`warn`、`drop`、`mute:SECS`、`disconnect`
+impl FromStr for RateLimitAction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once(':') {
+            None if s == "warn" => Ok(RateLimitAction::Warn),
+            None if s == "drop" => Ok(RateLimitAction::Drop),
+            None if s == "disconnect" => Ok(RateLimitAction::Disconnect),
+            None if s == "mute" => bail!("'mute' needs a duration in seconds, e.g. mute:30"),
+            Some(("mute", secs)) => {
+                let secs = secs.parse().map_err(|_| anyhow!("invalid mute duration '{secs}'"))?;
+                let action = RateLimitAction::Mute { secs };
+                action.validate()?;
+                Ok(action)
+            }
+            _ => bail!(
+                "unknown rate limit action '{s}' (expected warn, drop, mute:SECS or disconnect)"
+            ),
+        }
+    }
+}
+
+/// 限速配置，`per_user` / `per_ip` 为空表示不限制
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    pub per_user: Option<RateLimit>,
+    pub per_ip: Option<RateLimit>,
+    pub on_violation: RateLimitAction,
+}
+
+impl RateLimitConfig {
+    /// 检查反序列化时无法检查的取值（令牌桶参数在反序列化时已经校验过）
+    pub fn validate(&self) -> Result<()> {
+        self.on_violation.validate()
+    }
+}
+
+/// 整个服务器范围内各种处理方式的触发次数
+#[derive(Debug, Default)]
+pub struct RateLimitMetrics {
+    warned: AtomicU64,
+    dropped: AtomicU64,
+    muted: AtomicU64,
+    disconnected: AtomicU64,
+}
+
+impl RateLimitMetrics {
+    /// 超限但照常处理（Warn）的消息数
+    pub fn warned(&self) -> u64 {
+        self.warned.load(Ordering::Relaxed)
+    }
+
+    /// 因超限或禁言被丢弃的消息数
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// 被禁言的次数
+    pub fn muted(&self) -> u64 {
+        self.muted.load(Ordering::Relaxed)
+    }
+
+    /// 因超限被断开的连接数
+    pub fn disconnected(&self) -> u64 {
+        self.disconnected.load(Ordering::Relaxed)
+    }
+}
+
+/// `ConnectionLimiter::check` 的结果
+#[derive(Debug)]
+pub enum Verdict {
+    /// 放行
+    Allow,
+    /// 放行，同时把提醒发给客户端
+    Warn(ServerMessage),
+    /// 丢弃这条消息；连续超限时只通知一次
+    Drop(Option<ServerMessage>),
+    /// 发出通知后断开连接
+    Disconnect(ServerMessage),
+}
+
+#[derive(Debug)]
+pub(super) struct TokenBucket {
+    limit: RateLimit,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    pub(super) fn new(limit: RateLimit) -> Self {
+        TokenBucket::new_at(limit, Instant::now())
+    }
+
+    /// 在 `now` 时刻装满的桶
+    fn new_at(limit: RateLimit, now: Instant) -> Self {
+        TokenBucket { limit, tokens: limit.burst as f64, updated: now }
+    }
+
+    /// 按经过的时间补充令牌，返回是否至少有一个令牌
+    pub(super) fn refill(&mut self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.limit.rate).min(self.limit.burst as f64);
+        self.updated = now;
+        self.tokens >= 1.0
+    }
+
+    /// 有令牌时扣掉一个并返回 true
+    pub(super) fn take(&mut self, now: Instant) -> bool {
+        if !self.refill(now) {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+
+    /// 桶是否已经补满（丢掉它和新建一个没有区别）
+    pub(super) fn is_full(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        self.tokens >= self.limit.burst as f64
+    }
+}
+
+/// 一个用户的限速状态，同一用户的所有连接共享
+#[derive(Debug)]
+struct UserEntry {
+    bucket: Option<TokenBucket>,
+    muted_until: Option<Instant>,
+    /// 本轮连续超限是否已经通知过客户端
+    notified: bool,
+}
+
+impl UserEntry {
+    /// 条目还有没有保留的必要：桶没补满或者还在禁言中
+    fn is_active(&mut self, now: Instant) -> bool {
+        self.muted_until.is_some_and(|until| until > now)
+            || self.bucket.as_mut().is_some_and(|bucket| !bucket.is_full(now))
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// 规范化键 -> 用户的桶和禁言状态
+    users: HashMap<String, UserEntry>,
+    ips: HashMap<IpAddr, TokenBucket>,
+    /// 两张表的条目数之和超过这个大小时清理一次
+    prune_at: usize,
+}
+
+impl State {
+    /// 删掉已经补满、也没有禁言的条目
+    fn prune(&mut self, now: Instant) {
+        if self.users.len() + self.ips.len() < self.prune_at.max(PRUNE_THRESHOLD) {
+            return;
+        }
+        self.users.retain(|_, entry| entry.is_active(now));
+        self.ips.retain(|_, bucket| !bucket.is_full(now));
+        self.prune_at = (self.users.len() + self.ips.len()) * 2;
+    }
+}
+
+/// 服务器范围的限速状态：配置、按用户和按 IP 共享的令牌桶、禁言和计数器
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    state: Mutex<State>,
+    metrics: RateLimitMetrics,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        RateLimiter { config, ..Default::default() }
+    }
+
+    pub fn config(&self) -> &RateLimitConfig {
+        &self.config
+    }
+
+    pub fn metrics(&self) -> &RateLimitMetrics {
+        &self.metrics
+    }
+
+    /// 为一条登录成功的连接创建限速器。`user_key` 是用户名的规范化键，
+    /// `ip` 为 `None`（Unix 套接字）时只按用户限速
+    pub fn connect(self: &Arc<Self>, user_key: String, ip: Option<IpAddr>) -> ConnectionLimiter {
+        ConnectionLimiter { limiter: self.clone(), user_key, ip }
+    }
+
+    fn check(&self, user_key: &str, ip: Option<IpAddr>, now: Instant) -> Verdict {
+        let config = &self.config;
+        if config.per_user.is_none() && config.per_ip.is_none() {
+            return Verdict::Allow;
+        }
+        let metrics = &self.metrics;
+        let mut state = self.state.lock().unwrap();
+        state.prune(now);
+        let State { users, ips, .. } = &mut *state;
+
+        let user = match users.get_mut(user_key) {
+            Some(user) => user,
+            None => {
+                let entry = UserEntry {
+                    bucket: config.per_user.map(|limit| TokenBucket::new_at(limit, now)),
+                    muted_until: None,
+                    notified: false,
+                };
+                users.entry(user_key.to_string()).or_insert(entry)
+            }
+        };
+        if let Some(until) = user.muted_until {
+            if now < until {
+                metrics.dropped.fetch_add(1, Ordering::Relaxed);
+                return Verdict::Drop(None);
+            }
+            user.muted_until = None;
+        }
+
+        let ip = match (config.per_ip, ip) {
+            (Some(limit), Some(ip)) => {
+                Some(ips.entry(ip).or_insert_with(|| TokenBucket::new_at(limit, now)))
+            }
+            _ => None,
+        };
+        if take_token(user.bucket.as_mut(), ip, now) {
+            user.notified = false;
+            return Verdict::Allow;
+        }
+
+        let first = !std::mem::replace(&mut user.notified, true);
+        match config.on_violation {
+            RateLimitAction::Warn => {
+                metrics.warned.fetch_add(1, Ordering::Relaxed);
+                if first {
+                    Verdict::Warn(ServerMessage::error("You are sending messages too fast."))
+                } else {
+                    Verdict::Allow
+                }
+            }
+            RateLimitAction::Drop => {
+                metrics.dropped.fetch_add(1, Ordering::Relaxed);
+                Verdict::Drop(first.then(|| {
+                    ServerMessage::error("You are sending messages too fast. Message dropped.")
+                }))
+            }
+            RateLimitAction::Mute { secs } => {
+                metrics.dropped.fetch_add(1, Ordering::Relaxed);
+                metrics.muted.fetch_add(1, Ordering::Relaxed);
+                // secs 在加载配置时已经限制在 MAX_MUTE_SECS 以内，这里只是防止溢出
+                user.muted_until = now.checked_add(Duration::from_secs(secs));
+                Verdict::Drop(Some(ServerMessage::error(format!(
+                    "You are sending messages too fast and have been muted for {secs} seconds."
+                ))))
+            }
+            RateLimitAction::Disconnect => {
+                metrics.disconnected.fetch_add(1, Ordering::Relaxed);
+                Verdict::Disconnect(ServerMessage::Disconnected {
+                    reason: "Disconnected: you are sending messages too fast.".to_string(),
+                })
+            }
+        }
+    }
+}
+
+/// 用户桶和 IP 桶都有令牌时各扣一个
+fn take_token(user: Option<&mut TokenBucket>, ip: Option<&mut TokenBucket>, now: Instant) -> bool {
+    match (user, ip) {
+        (Some(user), Some(ip)) => {
+            if !user.refill(now) || !ip.refill(now) {
+                return false;
+            }
+            user.tokens -= 1.0;
+            ip.tokens -= 1.0;
+            true
+        }
+        (Some(bucket), None) | (None, Some(bucket)) => bucket.take(now),
+        (None, None) => true,
+    }
+}
+
+/// 单条连接的限速器：记住这条连接的用户和 IP，状态都在 RateLimiter 里
+#[derive(Debug)]
+pub struct ConnectionLimiter {
+    limiter: Arc<RateLimiter>,
+    user_key: String,
+    ip: Option<IpAddr>,
+}
+
+impl ConnectionLimiter {
+    /// 客户端每发来一条消息调用一次，决定如何处理它
+    pub fn check(&mut self) -> Verdict {
+        self.limiter.check(&self.user_key, self.ip, Instant::now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    const IP: Option<IpAddr> = Some(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)));
+
+    fn limit(rate: f64, burst: u32) -> RateLimit {
+        RateLimit::new(rate, burst).unwrap()
+    }
+
+    fn limiter(
+        per_user: Option<RateLimit>,
+        per_ip: Option<RateLimit>,
+        on_violation: RateLimitAction
+    ) -> RateLimiter {
+        RateLimiter::new(RateLimitConfig { per_user, per_ip, on_violation })
+    }
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn bucket_allows_burst_then_refills() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new_at(limit(2.0, 3), start);
+        for _ in 0..3 {
+            assert!(bucket.take(start));
+        }
+        assert!(!bucket.take(start));
+        // 每秒 2 个，500ms 补充一个
+        assert!(!bucket.take(start + ms(400)));
+        assert!(bucket.take(start + ms(500)));
+        assert!(!bucket.take(start + ms(500)));
+        // 再久也只攒 burst 个
+        let later = start + Duration::from_secs(60);
+        assert!(bucket.is_full(later));
+        for _ in 0..3 {
+            assert!(bucket.take(later));
+        }
+        assert!(!bucket.take(later));
+    }
+
+    #[test]
+    fn warn_notifies_once_and_lets_messages_through() {
+        let limiter = limiter(Some(limit(1.0, 1)), None, RateLimitAction::Warn);
+        let now = Instant::now();
+        assert!(matches!(limiter.check("alice", IP, now), Verdict::Allow));
+        assert!(matches!(limiter.check("alice", IP, now), Verdict::Warn(_)));
+        assert!(matches!(limiter.check("alice", IP, now), Verdict::Allow));
+        assert_eq!(limiter.metrics().warned(), 2);
+    }
+
+    #[test]
+    fn drop_notifies_once_per_streak() {
+        let limiter = limiter(Some(limit(1.0, 1)), None, RateLimitAction::Drop);
+        let now = Instant::now();
+        assert!(matches!(limiter.check("alice", IP, now), Verdict::Allow));
+        assert!(matches!(limiter.check("alice", IP, now), Verdict::Drop(Some(_))));
+        assert!(matches!(limiter.check("alice", IP, now), Verdict::Drop(None)));
+        // 补到令牌后重新开始计算，下一轮超限会再通知一次
+        let later = now + Duration::from_secs(1);
+        assert!(matches!(limiter.check("alice", IP, later), Verdict::Allow));
+        assert!(matches!(limiter.check("alice", IP, later), Verdict::Drop(Some(_))));
+        assert_eq!(limiter.metrics().dropped(), 3);
+    }
+
+    #[test]
+    fn mute_drops_everything_until_it_expires() {
+        let limiter = limiter(Some(limit(10.0, 1)), None, RateLimitAction::Mute { secs: 5 });
+        let now = Instant::now();
+        assert!(matches!(limiter.check("alice", IP, now), Verdict::Allow));
+        assert!(matches!(limiter.check("alice", IP, now), Verdict::Drop(Some(_))));
+        // 桶早就补满了，但禁言还没到期
+        let muted = now + Duration::from_secs(4);
+        assert!(matches!(limiter.check("alice", IP, muted), Verdict::Drop(None)));
+        let expired = now + Duration::from_secs(5);
+        assert!(matches!(limiter.check("alice", IP, expired), Verdict::Allow));
+        assert_eq!((limiter.metrics().muted(), limiter.metrics().dropped()), (1, 2));
+    }
+
+    #[test]
+    fn disconnect_on_violation() {
+        let limiter = limiter(Some(limit(1.0, 2)), None, RateLimitAction::Disconnect);
+        let now = Instant::now();
+        for _ in 0..2 {
+            assert!(matches!(limiter.check("alice", IP, now), Verdict::Allow));
+        }
+        assert!(matches!(limiter.check("alice", IP, now), Verdict::Disconnect(_)));
+        assert_eq!(limiter.metrics().disconnected(), 1);
+    }
+
+    #[test]
+    fn reconnecting_keeps_bucket_and_mute() {
+        let action = RateLimitAction::Mute { secs: 60 };
+        let limiter = Arc::new(limiter(Some(limit(1.0, 2)), None, action));
+        let mut first = limiter.connect("alice".to_string(), IP);
+        assert!(matches!(first.check(), Verdict::Allow));
+        assert!(matches!(first.check(), Verdict::Allow));
+        drop(first);
+
+        // 同一用户的新连接没有新的令牌
+        let mut second = limiter.connect("alice".to_string(), None);
+        assert!(matches!(second.check(), Verdict::Drop(Some(_))));
+        drop(second);
+        let mut third = limiter.connect("alice".to_string(), IP);
+        assert!(matches!(third.check(), Verdict::Drop(None)));
+
+        // 其他用户不受影响
+        let mut bob = limiter.connect("bob".to_string(), IP);
+        assert!(matches!(bob.check(), Verdict::Allow));
+    }
+
+    #[test]
+    fn ip_bucket_is_shared_by_users_and_outlives_connections() {
+        let limiter = Arc::new(limiter(None, Some(limit(1.0, 2)), RateLimitAction::Drop));
+        assert!(matches!(limiter.connect("alice".to_string(), IP).check(), Verdict::Allow));
+        assert!(matches!(limiter.connect("bob".to_string(), IP).check(), Verdict::Allow));
+        // 两条连接都已经关闭，这个 IP 的桶仍然是空的
+        assert!(matches!(limiter.connect("carol".to_string(), IP).check(), Verdict::Drop(_)));
+
+        // Unix 套接字没有 IP，不受 IP 限速影响
+        assert!(matches!(limiter.connect("carol".to_string(), None).check(), Verdict::Allow));
+    }
+
+    #[test]
+    fn both_buckets_must_have_tokens() {
+        let limiter = limiter(Some(limit(1.0, 1)), Some(limit(1.0, 3)), RateLimitAction::Drop);
+        let now = Instant::now();
+        assert!(matches!(limiter.check("alice", IP, now), Verdict::Allow));
+        // 用户桶为空时不放行，也不扣 IP 桶的令牌
+        assert!(matches!(limiter.check("alice", IP, now), Verdict::Drop(_)));
+        assert!(matches!(limiter.check("bob", IP, now), Verdict::Allow));
+        assert!(matches!(limiter.check("carol", IP, now), Verdict::Allow));
+        // IP 桶为空时，新用户也不放行
+        assert!(matches!(limiter.check("dave", IP, now), Verdict::Drop(_)));
+    }
+
+    #[test]
+    fn prunes_refilled_entries() {
+        let action = RateLimitAction::Mute { secs: 3600 };
+        let limiter = limiter(Some(limit(1.0, 1)), Some(limit(1000.0, 10_000)), action);
+        let now = Instant::now();
+        limiter.check("user0", IP, now);
+        assert!(matches!(limiter.check("user0", IP, now), Verdict::Drop(Some(_))));
+        // 加上 IP 桶正好达到清理阈值
+        for n in 1..PRUNE_THRESHOLD - 1 {
+            limiter.check(&format!("user{n}"), IP, now);
+        }
+
+        // 一分钟后其他用户的桶和 IP 桶都补满了，只有被禁言的 user0 需要保留
+        let later = now + Duration::from_secs(60);
+        limiter.check("newcomer", IP, later);
+        let state = limiter.state.lock().unwrap();
+        let mut users: Vec<&str> = state.users.keys().map(String::as_str).collect();
+        users.sort();
+        assert_eq!(users, ["newcomer", "user0"]);
+        assert_eq!(state.ips.len(), 1);
+    }
+
+    #[test]
+    fn rejects_invalid_limits() {
+        assert!(RateLimit::new(0.0, 1).is_err());
+        assert!(RateLimit::new(-1.0, 1).is_err());
+        assert!(RateLimit::new(f64::NAN, 1).is_err());
+        assert!(RateLimit::new(1.0, 0).is_err());
+        assert!("5:0".parse::<RateLimit>().is_err());
+        assert_eq!("5:10".parse::<RateLimit>().unwrap(), limit(5.0, 10));
+
+        let parse = |toml: &str| toml::from_str::<RateLimitConfig>(toml);
+        assert!(parse("per_user = { rate = 10.0, burst = 0 }").is_err());
+        assert!(parse("per_ip = { rate = 0.0, burst = 5 }").is_err());
+        assert!(parse("per_user = { rate = 10.0, burst = 20 }").is_ok());
+    }
+
+    #[test]
+    fn rejects_unbounded_mute() {
+        assert!("mute:0".parse::<RateLimitAction>().is_err());
+        assert!("mute:18446744073709551615".parse::<RateLimitAction>().is_err());
+        assert!(format!("mute:{MAX_MUTE_SECS}").parse::<RateLimitAction>().is_ok());
+
+        let toml = "[on_violation]\naction = \"mute\"\nsecs = 9223372036854775807";
+        let config: RateLimitConfig = toml::from_str(toml).unwrap();
+        assert!(config.validate().is_err());
+
+        // 即使绕过了校验，超大的禁言时长也不会让 check panic
+        let limiter = limiter(Some(limit(1.0, 1)), None, RateLimitAction::Mute { secs: u64::MAX });
+        let now = Instant::now();
+        limiter.check("alice", None, now);
+        assert!(matches!(limiter.check("alice", None, now), Verdict::Drop(Some(_))));
+    }
+}
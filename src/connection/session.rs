@@ -0,0 +1,435 @@
+// src/connection/session.rs
+
+// 断线重连：登录成功后服务器发给客户端一个 HMAC 签名的会话令牌。
+//
+// 连接断开（EOF、I/O 错误、心跳超时）时用户不会马上下线：用户名继续被占用，后端仍然往
+// 它的消息队列里投递消息，队列的接收端暂存在这里。客户端在宽限期内带着令牌重新连接，
+// 就能接回原来的用户名和队列，断线期间积压的消息随后照常发出；超过宽限期才真正注销。
+//
+// 旧连接还没被发现断开（半开连接）时，用令牌恢复会先把旧连接踢掉再接管。
+// 每次恢复都会换一个新的会话 ID 和令牌，旧令牌随即失效。
+
+use super::queue::ClientReceiver;
+use crate::auth::moderation::Role;
+use crate::auth::rules::NameReservation;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64;
+use hmac::{ Hmac, Mac };
+use rand_core::{ OsRng, RngCore };
+use serde::Deserialize;
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{ AtomicU64, Ordering };
+use std::sync::{ Arc, Mutex };
+use std::time::Duration;
+use tokio::sync::Notify;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// 踢掉旧连接后最多等它交出会话多久
+const TAKEOVER_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SessionConfig {
+    /// 是否发放会话令牌、允许断线重连
+    pub enabled: bool,
+    /// 断线后保留会话的秒数
+    pub grace_secs: u64,
+    /// 签名密钥；不设置时每次启动随机生成（会话本来就不会跨进程保留）
+    pub secret: Option<String>,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        SessionConfig { enabled: false, grace_secs: 30, secret: None }
+    }
+}
+
+/// 断线后暂存的会话：用户名的占用、角色和仍在接收消息的队列
+pub struct ParkedSession {
+    pub username: String,
+    pub role: Role,
+    pub reservation: NameReservation,
+    pub rx: ClientReceiver,
+}
+
+enum Slot {
+    /// 连接还在，通知它让出会话
+    Active(Arc<Notify>),
+    /// 连接已断开，等待恢复或过期
+    Parked(ParkedSession),
+}
+
+/// 恢复、过期的会话数
+#[derive(Debug, Default)]
+pub struct SessionMetrics {
+    resumed: AtomicU64,
+    expired: AtomicU64,
+}
+
+impl SessionMetrics {
+    /// 在宽限期内恢复的会话数
+    pub fn resumed(&self) -> u64 {
+        self.resumed.load(Ordering::Relaxed)
+    }
+
+    /// 超过宽限期被注销的会话数
+    pub fn expired(&self) -> u64 {
+        self.expired.load(Ordering::Relaxed)
+    }
+}
+
+/// 所有会话，按会话 ID 索引
+pub struct SessionStore {
+    config: SessionConfig,
+    key: Vec<u8>,
+    slots: Mutex<HashMap<String, Slot>>,
+    /// 有会话被暂存或结束时唤醒等待接管的连接
+    released: Notify,
+    metrics: SessionMetrics,
+}
+
+/// 不打印签名密钥
+impl fmt::Debug for SessionStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SessionStore")
+            .field("enabled", &self.enabled())
+            .field("grace_secs", &self.config.grace_secs)
+            .field("sessions", &self.slots.lock().unwrap().len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for SessionStore {
+    fn default() -> Self {
+        SessionStore::new(SessionConfig::default())
+    }
+}
+
+impl SessionStore {
+    pub fn new(config: SessionConfig) -> Self {
+        let key = match &config.secret {
+            Some(secret) => secret.as_bytes().to_vec(),
+            None => random_bytes(32),
+        };
+        SessionStore {
+            config,
+            key,
+            slots: Mutex::default(),
+            released: Notify::new(),
+            metrics: SessionMetrics::default(),
+        }
+    }
+
+    pub fn config(&self) -> &SessionConfig {
+        &self.config
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.config.enabled && self.config.grace_secs > 0
+    }
+
+    pub fn grace(&self) -> Duration {
+        Duration::from_secs(self.config.grace_secs)
+    }
+
+    pub fn metrics(&self) -> &SessionMetrics {
+        &self.metrics
+    }
+
+    /// 为刚登录（或刚恢复）的连接创建会话，返回会话和要发给客户端的令牌
+    pub fn issue(self: &Arc<Self>, username: &str) -> (Session, String) {
+        let id = hex(&random_bytes(16));
+        let takeover = Arc::new(Notify::new());
+        self.slots.lock().unwrap().insert(id.clone(), Slot::Active(takeover.clone()));
+        let token = self.sign(&id, username);
+        let session = Session { store: self.clone(), id, takeover, parked: false };
+        (session, token)
+    }
+
+    /// 校验令牌的签名，返回它所属的会话。调用者检查完用户名（封禁、证书）后再用 `resume` 取回会话
+    pub fn verify(&self, token: &str) -> Result<SessionClaim, &'static str> {
+        self.verify_token(token).ok_or("Invalid session token.")
+    }
+
+    /// 取回 `claim` 对应的会话。旧连接还在时先让它断开，再等它把会话交出来
+    pub async fn resume(&self, claim: SessionClaim) -> Result<ParkedSession, &'static str> {
+        let SessionClaim { id, .. } = claim;
+        let wait = tokio::time::timeout(TAKEOVER_TIMEOUT, async {
+            loop {
+                // 先注册等待再检查，避免错过检查和等待之间的唤醒
+                let released = self.released.notified();
+                {
+                    let mut slots = self.slots.lock().unwrap();
+                    match slots.remove(&id) {
+                        Some(Slot::Parked(session)) => return Some(session),
+                        Some(Slot::Active(takeover)) => {
+                            takeover.notify_one();
+                            slots.insert(id.clone(), Slot::Active(takeover));
+                        }
+                        None => return None,
+                    }
+                }
+                released.await;
+            }
+        });
+        match wait.await {
+            Ok(Some(session)) => {
+                self.metrics.resumed.fetch_add(1, Ordering::Relaxed);
+                Ok(session)
+            }
+            Ok(None) => Err("Session has expired."),
+            Err(_) => Err("Session is still in use, please try again."),
+        }
+    }
+
+    /// 宽限期结束时调用：会话还没被恢复就取出来，由调用者注销
+    pub fn expire(&self, id: &str) -> Option<ParkedSession> {
+        let mut slots = self.slots.lock().unwrap();
+        match slots.remove(id)? {
+            Slot::Parked(session) => {
+                self.metrics.expired.fetch_add(1, Ordering::Relaxed);
+                Some(session)
+            }
+            active => {
+                slots.insert(id.to_string(), active);
+                None
+            }
+        }
+    }
+
+    /// 令牌：base64url(会话 ID:用户名) + "." + base64url(HMAC-SHA256)
+    fn sign(&self, id: &str, username: &str) -> String {
+        let payload = format!("{id}:{username}");
+        let mut mac = self.mac();
+        mac.update(payload.as_bytes());
+        format!("{}.{}", BASE64.encode(payload), BASE64.encode(mac.finalize().into_bytes()))
+    }
+
+    /// 校验签名，返回令牌里的会话 ID 和用户名
+    fn verify_token(&self, token: &str) -> Option<SessionClaim> {
+        let (payload, signature) = token.trim().split_once('.')?;
+        let payload = BASE64.decode(payload).ok()?;
+        let signature = BASE64.decode(signature).ok()?;
+        let mut mac = self.mac();
+        mac.update(&payload);
+        // verify_slice 是常数时间比较
+        mac.verify_slice(&signature).ok()?;
+        let payload = String::from_utf8(payload).ok()?;
+        // 会话 ID 是十六进制，不含 `:`，用户名里可能有
+        let (id, username) = payload.split_once(':')?;
+        Some(SessionClaim { id: id.to_string(), username: username.to_string() })
+    }
+
+    fn mac(&self) -> HmacSha256 {
+        HmacSha256::new_from_slice(&self.key).expect("HMAC accepts keys of any length")
+    }
+}
+
+/// 签名有效的会话令牌：会话 ID 和签发时的用户名
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionClaim {
+    id: String,
+    username: String,
+}
+
+impl SessionClaim {
+    /// 令牌签发给的用户名
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+}
+
+/// 一条连接持有的会话。连接正常结束时 drop 即删除；断线时用 `park` 暂存
+pub struct Session {
+    store: Arc<SessionStore>,
+    id: String,
+    takeover: Arc<Notify>,
+    parked: bool,
+}
+
+impl Session {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// 有人用令牌恢复这个会话时完成，当前连接应当让出会话
+    pub async fn taken_over(&self) {
+        self.takeover.notified().await;
+    }
+
+    /// 暂存断线的会话，返回会话 ID，宽限期结束后用它调用 `SessionStore::expire`
+    pub fn park(mut self, session: ParkedSession) -> String {
+        self.parked = true;
+        self.store.slots.lock().unwrap().insert(self.id.clone(), Slot::Parked(session));
+        self.store.released.notify_waiters();
+        std::mem::take(&mut self.id)
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        if !self.parked {
+            self.store.slots.lock().unwrap().remove(&self.id);
+            self.store.released.notify_waiters();
+        }
+    }
+}
+
+fn random_bytes(len: usize) -> Vec<u8> {
+    let mut bytes = vec![0; len];
+    OsRng.fill_bytes(&mut bytes);
+    bytes
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::rules::UsernameRegistry;
+    use crate::connection::queue::{ SlowConsumerPolicy, client_queue };
+
+    fn store(secret: &str) -> Arc<SessionStore> {
+        Arc::new(SessionStore::new(SessionConfig {
+            enabled: true,
+            grace_secs: 30,
+            secret: Some(secret.to_string()),
+        }))
+    }
+
+    fn parked(username: &str) -> ParkedSession {
+        let registry = Arc::new(UsernameRegistry::default());
+        let (_tx, rx) = client_queue(10, 1024, SlowConsumerPolicy::default(), Arc::default());
+        ParkedSession {
+            username: username.to_string(),
+            role: Role::Member,
+            reservation: registry.reserve(username).unwrap(),
+            rx,
+        }
+    }
+
+    /// 把令牌的某一部分解码后交给 `edit` 修改，再编码回去（签名不变）
+    fn tamper(token: &str, part: usize, edit: impl FnOnce(&mut Vec<u8>)) -> String {
+        let mut parts: Vec<Vec<u8>> = token
+            .split('.')
+            .map(|part| BASE64.decode(part).unwrap())
+            .collect();
+        edit(&mut parts[part]);
+        parts.iter().map(|part| BASE64.encode(part)).collect::<Vec<_>>().join(".")
+    }
+
+    #[test]
+    fn verifies_signed_token() {
+        let store = store("secret");
+        let (session, token) = store.issue("alice:smith");
+        let claim = store.verify(&token).unwrap();
+        assert_eq!(claim.username(), "alice:smith");
+        assert_eq!(claim.id, session.id());
+        // 客户端可能带上首尾空白
+        assert_eq!(store.verify(&format!(" {token}\n")).unwrap(), claim);
+    }
+
+    #[test]
+    fn rejects_tampered_tokens() {
+        let store = store("secret");
+        let (_session, token) = store.issue("alice");
+
+        // 改用户名
+        let renamed = tamper(&token, 0, |payload| {
+            let len = payload.len();
+            payload[len - 5..].copy_from_slice(b"admin");
+        });
+        assert!(store.verify(&renamed).is_err());
+        // 改签名
+        let forged = tamper(&token, 1, |mac| mac[0] ^= 1);
+        assert!(store.verify(&forged).is_err());
+        // 截短签名
+        let truncated = tamper(&token, 1, |mac| mac.truncate(16));
+        assert!(store.verify(&truncated).is_err());
+
+        for garbage in ["", ".", "abc", "abc.def", "not base64!.x"] {
+            assert!(store.verify(garbage).is_err(), "{garbage:?}");
+        }
+    }
+
+    #[test]
+    fn rejects_tokens_signed_with_another_key() {
+        let (_session, token) = store("secret").issue("alice");
+        assert!(store("other secret").verify(&token).is_err());
+        // 不设置密钥时每个实例随机生成，令牌不能跨实例使用
+        let random = Arc::new(SessionStore::default());
+        let (_session, token) = random.issue("alice");
+        assert!(random.verify(&token).is_ok());
+        assert!(Arc::new(SessionStore::default()).verify(&token).is_err());
+    }
+
+    #[tokio::test]
+    async fn resumes_parked_session_once() {
+        let store = store("secret");
+        let (session, token) = store.issue("alice");
+        session.park(parked("alice"));
+
+        let resumed = store.resume(store.verify(&token).unwrap()).await.unwrap();
+        assert_eq!(resumed.username, "alice");
+        // 令牌只能用一次
+        let again = store.resume(store.verify(&token).unwrap()).await;
+        assert_eq!(again.err(), Some("Session has expired."));
+        assert_eq!(store.metrics().resumed(), 1);
+    }
+
+    #[tokio::test]
+    async fn rejects_unknown_session_id() {
+        let store = store("secret");
+        // 签名正确，但会话 ID 从来没有发放过
+        let token = store.sign("0123456789abcdef", "alice");
+        let claim = store.verify(&token).unwrap();
+        assert_eq!(store.resume(claim).await.err(), Some("Session has expired."));
+    }
+
+    #[tokio::test]
+    async fn expired_session_cannot_be_resumed() {
+        let store = store("secret");
+        let (session, token) = store.issue("alice");
+        let id = session.park(parked("alice"));
+
+        let expired = store.expire(&id).unwrap();
+        assert_eq!(expired.username, "alice");
+        assert!(store.expire(&id).is_none());
+        let resumed = store.resume(store.verify(&token).unwrap()).await;
+        assert_eq!(resumed.err(), Some("Session has expired."));
+        assert_eq!(store.metrics().expired(), 1);
+    }
+
+    #[tokio::test]
+    async fn active_session_is_not_expired() {
+        let store = store("secret");
+        let (session, token) = store.issue("alice");
+        // 恢复后的连接还在使用这个会话，宽限期到了也不注销
+        assert!(store.expire(session.id()).is_none());
+        // 连接正常结束后会话被删除，令牌随之失效
+        drop(session);
+        let resumed = store.resume(store.verify(&token).unwrap()).await;
+        assert_eq!(resumed.err(), Some("Session has expired."));
+    }
+
+    #[tokio::test]
+    async fn resume_takes_over_active_session() {
+        let store = store("secret");
+        let (session, token) = store.issue("alice");
+        let claim = store.verify(&token).unwrap();
+
+        // 旧连接收到接管通知后暂存会话
+        let old = tokio::spawn(async move {
+            session.taken_over().await;
+            session.park(parked("alice"));
+        });
+        let resumed = store.resume(claim).await.unwrap();
+        assert_eq!(resumed.username, "alice");
+        old.await.unwrap();
+    }
+}
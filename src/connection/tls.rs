@@ -0,0 +1,292 @@
+// src/connection/tls.rs
+
+// TLS 终止：[[listeners]] 中 tls = true 的端口先完成 TLS 握手，再在加密流上跑原来的线路协议
+// （line / json / websocket，websocket 即 wss）。
+//
+// 可选双向 TLS：设置 client_ca_path 后要求客户端出示由该 CA 签发的证书，证书的 CN 就是登录用户名，
+// 客户端登录时使用的名字必须与它相同（按规范化键比较，见 auth/username.rs）。
+// 本地测试用的自签名证书可以用仓库根目录的 gen_certs.sh 生成。
+
+use anyhow::{ Context, Result, anyhow };
+use serde::Deserialize;
+use std::fmt;
+use std::path::{ Path, PathBuf };
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{ AsyncRead, AsyncWrite };
+use tokio_rustls::rustls::crypto::ring;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{ CertificateDer, PrivateKeyDer };
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::{ RootCertStore, ServerConfig };
+use tokio_rustls::server::TlsStream;
+use x509_parser::prelude::{ FromDer, X509Certificate };
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TlsConfig {
+    /// 服务器证书链（PEM），第一张是服务器自己的证书
+    pub cert_path: Option<PathBuf>,
+    /// 服务器私钥（PEM，PKCS#8 / PKCS#1 / SEC1）
+    pub key_path: Option<PathBuf>,
+    /// 设置后开启双向 TLS，客户端证书必须由这里的 CA 签发
+    pub client_ca_path: Option<PathBuf>,
+    /// 双向 TLS 下是否必须出示客户端证书；为 false 时没有证书的客户端按普通方式登录
+    pub require_client_cert: bool,
+    /// TLS 握手必须在多少秒内完成
+    pub handshake_timeout_secs: u64,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        TlsConfig {
+            cert_path: None,
+            key_path: None,
+            client_ca_path: None,
+            require_client_cert: true,
+            handshake_timeout_secs: 10,
+        }
+    }
+}
+
+/// 所有 TLS 端口共享的握手配置
+#[derive(Clone)]
+pub struct TlsAcceptor {
+    inner: tokio_rustls::TlsAcceptor,
+    handshake_timeout: Duration,
+    mutual: bool,
+}
+
+impl fmt::Debug for TlsAcceptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TlsAcceptor")
+            .field("handshake_timeout", &self.handshake_timeout)
+            .field("mutual", &self.mutual)
+            .finish_non_exhaustive()
+    }
+}
+
+impl TlsAcceptor {
+    /// 读取证书和私钥；配置不完整或文件无法解析时返回错误
+    pub fn from_config(config: &TlsConfig) -> Result<Self> {
+        let (Some(cert_path), Some(key_path)) = (&config.cert_path, &config.key_path) else {
+            return Err(
+                anyhow!("TLS needs a certificate and a private key ([tls] cert_path and key_path)")
+            );
+        };
+        let certs = load_certs(cert_path)?;
+        let key = PrivateKeyDer::from_pem_file(key_path)
+            .with_context(|| format!("failed to read private key {}", key_path.display()))?;
+
+        let provider = Arc::new(ring::default_provider());
+        let builder = ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()?;
+        let builder = match &config.client_ca_path {
+            Some(ca_path) => {
+                let mut roots = RootCertStore::empty();
+                for cert in load_certs(ca_path)? {
+                    roots
+                        .add(cert)
+                        .with_context(|| format!("invalid CA certificate in {}", ca_path.display()))?;
+                }
+                let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider);
+                let verifier = if config.require_client_cert {
+                    verifier.build()?
+                } else {
+                    verifier.allow_unauthenticated().build()?
+                };
+                builder.with_client_cert_verifier(verifier)
+            }
+            None => builder.with_no_client_auth(),
+        };
+        let server_config = builder
+            .with_single_cert(certs, key)
+            .context("the TLS certificate does not match the private key")?;
+
+        Ok(TlsAcceptor {
+            inner: tokio_rustls::TlsAcceptor::from(Arc::new(server_config)),
+            handshake_timeout: Duration::from_secs(config.handshake_timeout_secs),
+            mutual: config.client_ca_path.is_some(),
+        })
+    }
+
+    /// 是否校验客户端证书
+    pub fn mutual(&self) -> bool {
+        self.mutual
+    }
+
+    /// 完成 TLS 握手，返回加密流和客户端证书的 CN（没有出示证书时为 `None`）
+    pub(crate) async fn accept<S>(&self, socket: S) -> Result<(TlsStream<S>, Option<String>)>
+        where S: AsyncRead + AsyncWrite + Unpin
+    {
+        let stream = tokio::time::timeout(self.handshake_timeout, self.inner.accept(socket))
+            .await
+            .map_err(|_| anyhow!("TLS handshake timed out"))??;
+        // 证书链已经在握手时校验过，这里只取 CN
+        let common_name = stream
+            .get_ref()
+            .1
+            .peer_certificates()
+            .and_then(|certs| certs.first())
+            .map(|cert| common_name(cert))
+            .transpose()?;
+        Ok((stream, common_name))
+    }
+}
+
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .with_context(|| format!("failed to read certificates from {}", path.display()))?;
+    if certs.is_empty() {
+        return Err(anyhow!("no certificates found in {}", path.display()));
+    }
+    Ok(certs)
+}
+
+/// 客户端证书主题中的 CN
+fn common_name(cert: &CertificateDer<'_>) -> Result<String> {
+    let (_, cert) = X509Certificate::from_der(cert.as_ref())
+        .map_err(|e| anyhow!("malformed client certificate: {e}"))?;
+    let cn = cert
+        .subject()
+        .iter_common_name()
+        .next()
+        .ok_or_else(|| anyhow!("client certificate has no common name"))?;
+    Ok(cn.as_str().map_err(|e| anyhow!("unreadable common name: {e}"))?.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{ AsyncReadExt, AsyncWriteExt };
+    use tokio_rustls::TlsConnector;
+    use tokio_rustls::rustls::ClientConfig;
+    use tokio_rustls::rustls::pki_types::ServerName;
+
+    // testdata/tls 下是用 openssl 生成的测试证书（P-256，有效期 100 年）：
+    // ca.pem 签发了 server.pem（localhost）、alice.pem（CN=alice）和 nocn.pem（没有 CN）
+    fn testdata(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/tls").join(name)
+    }
+
+    fn config(client_ca: bool) -> TlsConfig {
+        TlsConfig {
+            cert_path: Some(testdata("server.pem")),
+            key_path: Some(testdata("server.key")),
+            client_ca_path: client_ca.then(|| testdata("ca.pem")),
+            ..TlsConfig::default()
+        }
+    }
+
+    fn error(config: &TlsConfig) -> String {
+        format!("{:#}", TlsAcceptor::from_config(config).unwrap_err())
+    }
+
+    #[test]
+    fn needs_cert_and_key() {
+        assert!(error(&TlsConfig::default()).contains("cert_path and key_path"));
+        let no_key = TlsConfig { key_path: None, ..config(false) };
+        assert!(error(&no_key).contains("cert_path and key_path"));
+        let no_cert = TlsConfig { cert_path: None, ..config(false) };
+        assert!(error(&no_cert).contains("cert_path and key_path"));
+    }
+
+    #[test]
+    fn reports_missing_files() {
+        let missing_cert = TlsConfig { cert_path: Some(testdata("missing.pem")), ..config(false) };
+        assert!(error(&missing_cert).contains("failed to read certificates from"));
+        let missing_key = TlsConfig { key_path: Some(testdata("missing.key")), ..config(false) };
+        assert!(error(&missing_key).contains("failed to read private key"));
+        // 私钥文件里没有证书
+        let not_a_cert = TlsConfig { cert_path: Some(testdata("server.key")), ..config(false) };
+        assert!(error(&not_a_cert).contains("no certificates found"));
+    }
+
+    #[test]
+    fn rejects_mismatched_key() {
+        let mismatched = TlsConfig { key_path: Some(testdata("alice.key")), ..config(false) };
+        assert!(error(&mismatched).contains("does not match the private key"));
+    }
+
+    #[test]
+    fn loads_certificate() {
+        assert!(!TlsAcceptor::from_config(&config(false)).unwrap().mutual());
+        assert!(TlsAcceptor::from_config(&config(true)).unwrap().mutual());
+    }
+
+    #[test]
+    fn extracts_common_name() {
+        let alice = load_certs(&testdata("alice.pem")).unwrap();
+        assert_eq!(common_name(&alice[0]).unwrap(), "alice");
+        let server = load_certs(&testdata("server.pem")).unwrap();
+        assert_eq!(common_name(&server[0]).unwrap(), "localhost");
+
+        let nocn = load_certs(&testdata("nocn.pem")).unwrap();
+        assert!(common_name(&nocn[0]).unwrap_err().to_string().contains("no common name"));
+        assert!(common_name(&CertificateDer::from(vec![0x30, 0x00])).is_err());
+    }
+
+    /// 在内存管道上完成一次握手，返回服务器看到的客户端 CN
+    async fn handshake(
+        acceptor: &TlsAcceptor,
+        client_cert: Option<&str>
+    ) -> Result<Option<String>> {
+        let mut roots = RootCertStore::empty();
+        for cert in load_certs(&testdata("ca.pem"))? {
+            roots.add(cert)?;
+        }
+        let builder = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(roots);
+        let client_config = match client_cert {
+            Some(name) => {
+                let certs = load_certs(&testdata(&format!("{name}.pem")))?;
+                let key = PrivateKeyDer::from_pem_file(testdata(&format!("{name}.key")))?;
+                builder.with_client_auth_cert(certs, key)?
+            }
+            None => builder.with_no_client_auth(),
+        };
+        let connector = TlsConnector::from(Arc::new(client_config));
+
+        let (client, server) = tokio::io::duplex(16 * 1024);
+        let client = async move {
+            let mut stream = connector.connect(ServerName::try_from("localhost")?, client).await?;
+            stream.write_all(b"ping").await?;
+            // TLS 1.3 下客户端证书被拒绝时，要读一次才能收到服务器的 alert
+            let mut buf = [0; 4];
+            stream.read_exact(&mut buf).await?;
+            anyhow::Ok(())
+        };
+        let server = async move {
+            let (mut stream, common_name) = acceptor.accept(server).await?;
+            let mut buf = [0; 4];
+            stream.read_exact(&mut buf).await?;
+            stream.write_all(&buf).await?;
+            stream.flush().await?;
+            anyhow::Ok(common_name)
+        };
+        let (client, server) = tokio::join!(client, server);
+        client?;
+        server
+    }
+
+    #[tokio::test]
+    async fn handshake_without_client_auth() {
+        let acceptor = TlsAcceptor::from_config(&config(false)).unwrap();
+        assert_eq!(handshake(&acceptor, None).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn mutual_tls_reports_common_name() {
+        let acceptor = TlsAcceptor::from_config(&config(true)).unwrap();
+        assert_eq!(handshake(&acceptor, Some("alice")).await.unwrap().as_deref(), Some("alice"));
+        // 必须出示证书
+        assert!(handshake(&acceptor, None).await.is_err());
+
+        let optional = TlsConfig { require_client_cert: false, ..config(true) };
+        let acceptor = TlsAcceptor::from_config(&optional).unwrap();
+        assert_eq!(handshake(&acceptor, None).await.unwrap(), None);
+        assert_eq!(handshake(&acceptor, Some("alice")).await.unwrap().as_deref(), Some("alice"));
+    }
+}
@@ -0,0 +1,45 @@
+// src/lib.rs
+
+// 聊天服务器库。chat_server 二进制只是它的薄封装，
+// 其他服务也可以直接内嵌：
+//
+//     let server = ChatServer::builder(MutexBackend::new())
+//         .listen("127.0.0.1:8080", Protocol::Json)
+//         .build();
+//     server.run().await?;
+
+pub mod auth;
+pub mod backend;
+pub mod codec;
+pub mod command;
+pub mod config;
+pub mod connection;
+pub mod message;
+pub mod protocol;
+pub mod server;
+pub mod utils;
+
+pub use backend::{ ActorBackend, Backend, MutexBackend };
+pub use command::{ Command, CommandContext, CommandFuture, CommandRegistry };
+pub use server::{ ChatServer, ChatServerBuilder, Protocol };
+
+use crate::config::{ LogConfig, LogFormat };
+use tracing::Level;
+use tracing_subscriber::FmtSubscriber;
+
+/// 按配置初始化 tracing 日志系统
+pub fn init_tracing(log: &LogConfig) -> anyhow::Result<()> {
+    let level: Level = log.level
+        .parse()
+        .map_err(|_| anyhow::anyhow!("invalid log level '{}'", log.level))?;
+    let builder = FmtSubscriber::builder()
+        .with_max_level(level) // 设置默认日志级别
+        .with_ansi(log.ansi);
+
+    match log.format {
+        LogFormat::Full => tracing::subscriber::set_global_default(builder.finish())?,
+        LogFormat::Compact => tracing::subscriber::set_global_default(builder.compact().finish())?,
+        LogFormat::Json => tracing::subscriber::set_global_default(builder.json().finish())?,
+    }
+    Ok(())
+}
@@ -1,74 +1,361 @@
 // src/main.rs
 
-// 声明项目的顶层模块。Rust会据此查找对应的文件或目录。
-
-mod auth;
-mod connection;
-mod message;
-mod test;
-mod utils;
-// 引入需要的类型和函数
-use crate::connection::SharedContacts;
-use crate::utils::color::{ GREEN, RED, RESET };
-use std::collections::HashMap;
-use std::sync::{ Arc, Mutex };
-use tokio::net::TcpListener;
-
-use tracing::{ Level, error, info, warn };
-use tracing_subscriber::FmtSubscriber;
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // 初始化 tracing 日志系统
-    let subscriber = FmtSubscriber::builder()
-        .with_max_level(Level::INFO) // 设置默认日志级别
-        .with_ansi(true)
-        .finish();
-    tracing::subscriber::set_global_default(subscriber)?;
-
-    // 初始化共享状态
-    let contact: SharedContacts = Arc::new(Mutex::new(HashMap::new()));
-    let listener = TcpListener::bind("127.0.0.1:8080").await?;
-
-    //println!("{GREEN}Chat server started on 127.0.0.1:8080{RESET}");
-    //info!("{GREEN}Chat server started on 127.0.0.1:8080{RESET}"); // tracing 宏在处理这个字符串时，会对其进行转义，以防止恶意的格式化字符串注入
-    //info!
-    info!("Chat server started on 127.0.0.1:8080");
-    loop {
-        // 等待新的客户端连接
-        let (socket, addr) = match listener.accept().await {
-            Ok(res) => res,
-            Err(e) => {
-                //eprintln!("{RED}Failed to accept connection: {}{RESET}", e);
-                error!("{RED}Failed to accept connection: {}{RESET}", e);
-
-                continue;
-            }
+// 聊天服务器：后端、监听地址、队列容量和日志格式都可以通过配置文件或命令行选择
+
+use clap::Parser;
+use std::path::{ Path, PathBuf };
+use std::time::Duration;
+use websocket::auth::accounts::AccountStore;
+use websocket::auth::authenticator::{ AllowlistAuthenticator, AuthMethod, OpenAuthenticator };
+use websocket::auth::moderation::Moderation;
+use websocket::auth::token::TokenAuthenticator;
+use websocket::config::{ BackendKind, ListenerConfig, LogFormat, ServerConfig, load_config };
+use websocket::connection::queue::SlowConsumerPolicy;
+use websocket::connection::rate_limit::{ RateLimit, RateLimitAction };
+use websocket::connection::tls::TlsAcceptor;
+use websocket::utils::sanitize::SanitizePolicy;
+use websocket::{ ActorBackend, Backend, ChatServer, MutexBackend };
+
+/// 未指定 --config 时，如果当前目录下存在该文件就加载它
+const DEFAULT_CONFIG_PATH: &str = "server.toml";
+
+#[derive(Debug, Parser)]
+#[command(about = "Multi-protocol chat server")]
+struct Cli {
+    /// TOML 配置文件路径
+    #[arg(short, long)]
+    config: Option<String>,
+
+    /// 状态后端：mutex | actor
+    #[arg(short, long)]
+    backend: Option<BackendKind>,
+
+    /// 监听地址，格式 PROTOCOL=ADDR，可重复，例如 --listen json=127.0.0.1:8080、
+    /// --listen json=[::1]:8080、--listen line=unix:/tmp/chat.sock；
+    /// TLS 端口写作 PROTOCOL+tls=ADDR，例如 --listen json+tls=127.0.0.1:8443
+    #[arg(short, long = "listen", value_name = "PROTOCOL=ADDR")]
+    listeners: Vec<ListenerConfig>,
+
+    /// 每个客户端消息队列的容量
+    #[arg(long)]
+    client_queue_size: Option<usize>,
+
+    /// 每个客户端消息队列最多积压的字节数
+    #[arg(long)]
+    client_queue_bytes: Option<usize>,
+
+    /// 客户端发来的单条消息（一帧 / 一行）的最大字节数
+    #[arg(long)]
+    max_frame_size: Option<usize>,
+
+    /// 客户端队列已满时的策略：drop-newest | drop-oldest | disconnect:N | wait:MS
+    #[arg(long)]
+    slow_consumer: Option<SlowConsumerPolicy>,
+
+    /// Hub 邮箱容量（仅 actor 后端）
+    #[arg(long)]
+    hub_mailbox_size: Option<usize>,
+
+    /// 登录超时（秒）
+    #[arg(long)]
+    login_timeout_secs: Option<u64>,
+
+    /// 登录后多少秒没有发消息就断开，0 表示不限制
+    #[arg(long)]
+    idle_timeout_secs: Option<u64>,
+
+    /// 心跳间隔（秒），0 表示不发心跳
+    #[arg(long)]
+    heartbeat_interval_secs: Option<u64>,
+
+    /// 对端沉默超过多少个心跳间隔就断开
+    #[arg(long)]
+    heartbeat_misses: Option<u32>,
+
+    /// TCP keepalive 的空闲时间和探测间隔（秒），0 表示不开启
+    #[arg(long)]
+    tcp_keepalive_secs: Option<u64>,
+
+    /// 关闭时等待客户端发完消息的最长秒数
+    #[arg(long)]
+    drain_timeout_secs: Option<u64>,
+
+    /// 每个连接一次最多合并写出的消息条数
+    #[arg(long)]
+    write_batch_size: Option<usize>,
+
+    /// 凑批时最多等待的毫秒数，0 表示不等待
+    #[arg(long)]
+    write_batch_delay_ms: Option<u64>,
+
+    /// 用户名和消息中终端控制字符的处理：strip | escape | reject
+    #[arg(long)]
+    sanitize: Option<SanitizePolicy>,
+
+    /// 身份认证方式：open | accounts | allowlist | token
+    #[arg(long)]
+    auth: Option<AuthMethod>,
+
+    /// allowlist 认证方式下允许登录的用户名，可重复
+    #[arg(long = "allow", value_name = "NAME")]
+    allowlist: Vec<String>,
+
+    /// token 认证方式的 HS256 签名密钥
+    #[arg(long, value_name = "SECRET")]
+    token_secret: Option<String>,
+
+    /// 使用账号认证（--auth accounts），账号保存在这个文件里
+    #[arg(long, value_name = "PATH")]
+    accounts_file: Option<PathBuf>,
+
+    /// 账号认证下是否允许不带密码的访客登录
+    #[arg(long)]
+    allow_guests: Option<bool>,
+
+    /// 管理员用户名，可重复（验证过身份后生效，open 认证方式下无效）
+    #[arg(long = "admin", value_name = "NAME")]
+    admins: Vec<String>,
+
+    /// 版主用户名，可重复
+    #[arg(long = "moderator", value_name = "NAME")]
+    moderators: Vec<String>,
+
+    /// 封禁名单文件
+    #[arg(long, value_name = "PATH")]
+    bans_file: Option<PathBuf>,
+
+    /// 断线后保留会话的秒数，客户端在这段时间内可以凭令牌重连；0 表示不保留
+    #[arg(long)]
+    session_grace_secs: Option<u64>,
+
+    /// 每个用户的限速，格式 RATE:BURST（每秒补充 RATE 条，最多连发 BURST 条）
+    #[arg(long, value_name = "RATE:BURST")]
+    rate_limit_user: Option<RateLimit>,
+
+    /// 每个 IP 的限速（同一 IP 的所有连接共享），格式 RATE:BURST
+    #[arg(long, value_name = "RATE:BURST")]
+    rate_limit_ip: Option<RateLimit>,
+
+    /// 超过限速时的处理：warn | drop | mute:SECS | disconnect
+    #[arg(long)]
+    rate_limit_action: Option<RateLimitAction>,
+
+    /// 所有端口加起来最多同时打开的连接数，0 表示不限制
+    #[arg(long)]
+    max_connections: Option<usize>,
+
+    /// 单个 IP 最多同时打开的连接数，0 表示不限制
+    #[arg(long)]
+    max_connections_per_ip: Option<usize>,
+
+    /// 单个 IP 建立新连接的速率，格式 RATE:BURST
+    #[arg(long, value_name = "RATE:BURST")]
+    accept_rate: Option<RateLimit>,
+
+    /// TLS 证书链（PEM）
+    #[arg(long, value_name = "PATH")]
+    tls_cert: Option<PathBuf>,
+
+    /// TLS 私钥（PEM）
+    #[arg(long, value_name = "PATH")]
+    tls_key: Option<PathBuf>,
+
+    /// 开启双向 TLS，客户端证书必须由这个 CA 签发，证书的 CN 就是用户名
+    #[arg(long, value_name = "PATH")]
+    tls_client_ca: Option<PathBuf>,
+
+    /// 日志级别：trace | debug | info | warn | error
+    #[arg(long)]
+    log_level: Option<String>,
+
+    /// 日志格式：full | compact | json
+    #[arg(long)]
+    log_format: Option<LogFormat>,
+}
+
+impl Cli {
+    /// 加载配置文件，再用命令行参数覆盖
+    fn into_config(self) -> anyhow::Result<ServerConfig> {
+        let mut config = match &self.config {
+            Some(path) => load_config(path)?,
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => load_config(DEFAULT_CONFIG_PATH)?,
+            None => ServerConfig::default(),
         };
 
-        //info!("{GREEN}>>> New client connected from: {}{RESET}", addr);
-        info!(peer_addr = %addr, "New client connected"); // 使用结构化方式
-        // 为新连接克隆共享状态的Arc指针
-        let contact_clone = Arc::clone(&contact);
-
-        // 为每个连接创建一个独立的异步任务
-        tokio::spawn(async move {
-            // 使用正确的、解耦后的函数路径
-            if
-                let Err(e) = connection::client::handle_connection(
-                    socket,
-                    addr,
-                    contact_clone
-                ).await
-            {
-                // 如果是 IO 错误（如 Broken pipe），则记录为警告
-                // 其他错误记录为错误
-                if let Some(io_err) = e.downcast_ref::<std::io::Error>() {
-                    warn!(peer_addr = %addr, error = %io_err, "Connection handler finished with an I/O error");
-                } else {
-                    error!(peer_addr = %addr, error = ?e, "Connection handler failed with an unexpected error");
-                }
-                //eprintln!("{RED}Error handling connection from {}: {}{RESET}", addr, e);
-            }
-        });
+        if let Some(backend) = self.backend {
+            config.backend = backend;
+        }
+        if !self.listeners.is_empty() {
+            config.listeners = self.listeners;
+        }
+        if let Some(size) = self.client_queue_size {
+            config.client_queue_size = size;
+        }
+        if let Some(bytes) = self.client_queue_bytes {
+            config.client_queue_bytes = bytes;
+        }
+        if let Some(size) = self.max_frame_size {
+            config.max_frame_size = size;
+        }
+        if let Some(policy) = self.slow_consumer {
+            config.slow_consumer = policy;
+        }
+        if let Some(size) = self.hub_mailbox_size {
+            config.hub_mailbox_size = size;
+        }
+        if let Some(secs) = self.login_timeout_secs {
+            config.login_timeout_secs = secs;
+        }
+        if let Some(secs) = self.idle_timeout_secs {
+            config.idle_timeout_secs = secs;
+        }
+        if let Some(secs) = self.heartbeat_interval_secs {
+            config.heartbeat_interval_secs = secs;
+        }
+        if let Some(misses) = self.heartbeat_misses {
+            config.heartbeat_misses = misses;
+        }
+        if let Some(secs) = self.tcp_keepalive_secs {
+            config.tcp_keepalive_secs = secs;
+        }
+        if let Some(secs) = self.drain_timeout_secs {
+            config.drain_timeout_secs = secs;
+        }
+        if let Some(size) = self.write_batch_size {
+            config.write_batch_size = size;
+        }
+        if let Some(ms) = self.write_batch_delay_ms {
+            config.write_batch_delay_ms = ms;
+        }
+        if let Some(policy) = self.sanitize {
+            config.sanitize = policy;
+        }
+        if let Some(path) = self.accounts_file {
+            config.auth.method = AuthMethod::Accounts;
+            config.accounts.path = path;
+        }
+        // 显式的 --auth 优先于 --accounts-file 隐含的方式
+        if let Some(method) = self.auth {
+            config.auth.method = method;
+        }
+        config.auth.allowlist.extend(self.allowlist);
+        if let Some(secret) = self.token_secret {
+            config.auth.token.secret = Some(secret);
+        }
+        if let Some(allow) = self.allow_guests {
+            config.accounts.allow_guests = allow;
+        }
+        config.moderation.admins.extend(self.admins);
+        config.moderation.moderators.extend(self.moderators);
+        if let Some(path) = self.bans_file {
+            config.moderation.bans_path = path;
+        }
+        if let Some(secs) = self.session_grace_secs {
+            config.sessions.enabled = secs > 0;
+            config.sessions.grace_secs = secs;
+        }
+        if let Some(limit) = self.rate_limit_user {
+            config.rate_limit.per_user = Some(limit);
+        }
+        if let Some(limit) = self.rate_limit_ip {
+            config.rate_limit.per_ip = Some(limit);
+        }
+        if let Some(action) = self.rate_limit_action {
+            config.rate_limit.on_violation = action;
+        }
+        if let Some(max) = self.max_connections {
+            config.connection_limits.max_connections = max;
+        }
+        if let Some(max) = self.max_connections_per_ip {
+            config.connection_limits.per_ip = max;
+        }
+        if let Some(rate) = self.accept_rate {
+            config.connection_limits.accept_rate = Some(rate);
+        }
+        if let Some(path) = self.tls_cert {
+            config.tls.cert_path = Some(path);
+        }
+        if let Some(path) = self.tls_key {
+            config.tls.key_path = Some(path);
+        }
+        if let Some(path) = self.tls_client_ca {
+            config.tls.client_ca_path = Some(path);
+        }
+        if let Some(level) = self.log_level {
+            config.log.level = level;
+        }
+        if let Some(format) = self.log_format {
+            config.log.format = format;
+        }
+        Ok(config)
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let config = Cli::parse().into_config()?;
+    websocket::init_tracing(&config.log)?;
+
+    match config.backend {
+        BackendKind::Mutex => run(MutexBackend::new(), &config).await,
+        BackendKind::Actor => run(ActorBackend::spawn(config.hub_mailbox_size), &config).await,
+    }
+}
+
+async fn run<B: Backend>(backend: B, config: &ServerConfig) -> anyhow::Result<()> {
+    // 每个端口对应一种线路协议，所有客户端共享同一个聊天室
+    let mut builder = ChatServer::builder(backend)
+        .client_queue_size(config.client_queue_size)
+        .client_queue_bytes(config.client_queue_bytes)
+        .max_frame_size(config.max_frame_size)
+        .slow_consumer_policy(config.slow_consumer)
+        .login_timeout(Duration::from_secs(config.login_timeout_secs))
+        .idle_timeout(Duration::from_secs(config.idle_timeout_secs))
+        .heartbeat(Duration::from_secs(config.heartbeat_interval_secs), config.heartbeat_misses)
+        .tcp_keepalive(Duration::from_secs(config.tcp_keepalive_secs))
+        .drain_timeout(Duration::from_secs(config.drain_timeout_secs))
+        .write_batch_size(config.write_batch_size)
+        .write_batch_delay(Duration::from_millis(config.write_batch_delay_ms))
+        .sanitize_policy(config.sanitize)
+        .username_rules(config.usernames.clone())
+        .moderation(Moderation::open(config.moderation.clone(), &config.usernames)?)
+        .sessions(config.sessions.clone())
+        .rate_limit(config.rate_limit.clone())
+        .connection_limits(config.connection_limits.clone());
+    builder = match config.auth.method {
+        AuthMethod::Open => builder.authenticator(OpenAuthenticator),
+        AuthMethod::Accounts => {
+            let accounts = AccountStore::open(config.accounts.clone(), &config.usernames)?;
+            tracing::info!(
+                path = %config.accounts.path.display(),
+                accounts = accounts.len(),
+                allow_guests = config.accounts.allow_guests,
+                "Accounts loaded"
+            );
+            builder.authenticator(accounts)
+        }
+        AuthMethod::Allowlist => {
+            anyhow::ensure!(
+                !config.auth.allowlist.is_empty(),
+                "allowlist authentication needs at least one name ([auth] allowlist or --allow)"
+            );
+            builder.authenticator(AllowlistAuthenticator::new(&config.auth.allowlist, &config.usernames))
+        }
+        AuthMethod::Token => {
+            builder.authenticator(TokenAuthenticator::new(&config.auth.token, &config.usernames)?)
+        }
+    };
+    // 只有存在 TLS 端口时才加载证书，没有配置证书的纯文本部署不受影响
+    if config.listeners.iter().any(|listener| listener.tls) {
+        builder = builder.tls(TlsAcceptor::from_config(&config.tls)?);
+    }
+    for listener in &config.listeners {
+        let addr = listener.addr.clone();
+        builder = match (listener.tls, listener.slow_consumer) {
+            (true, policy) => builder.listen_tls(addr, listener.protocol, policy),
+            (false, Some(policy)) => builder.listen_with_policy(addr, listener.protocol, policy),
+            (false, None) => builder.listen(addr, listener.protocol),
+        };
     }
+    builder.build().run().await
 }
@@ -1,12 +1,24 @@
 // src/message/brodcast.rs
 
-use crate::connection::{ ClientInfo, SharedContacts };
-use tokio::sync::mpsc::Sender;
+use crate::connection::SharedContacts;
+use crate::codec::SharedMessage;
+use crate::connection::queue::{ ClientSender, send_all };
+use crate::protocol::ServerMessage;
 pub async fn broadcast_to_others(contact: &SharedContacts, sender_username: &str, msg: String) {
+    let format_msg = ServerMessage::NewMessage {
+        from: sender_username.to_string(),
+        content: msg,
+    };
+    send_to_others(contact, sender_username, format_msg).await;
+}
+
+/// 把一条服务器消息发给除 `sender_username` 以外的所有在线用户
+pub async fn send_to_others(contact: &SharedContacts, sender_username: &str, msg: ServerMessage) {
+    // 只构造一次，所有接收者共享同一份消息和编码结果
+    let msg = SharedMessage::new(msg);
     // 收集所有需要接收消息的客户端的 Sender
     // 使用一个独立的作用域来确保锁尽快被释放
-    let format_msg = format!("[{sender_username}]: {msg}");
-    let receivers: Vec<Sender<String>> = {
+    let receivers: Vec<ClientSender> = {
         let guard = contact.lock().unwrap();
         guard
             .iter()
@@ -15,9 +27,31 @@ pub async fn broadcast_to_others(contact: &SharedContacts, sender_username: &str
             .collect()
     };
 
-    // 异步地将消息发送给所有接收者
-    for tx in receivers {
-        // 忽略发送错误，因为接收方可能已经下线
-        let _ = tx.send(format_msg.clone()).await;
+    // 队列满时按接收方的 SlowConsumerPolicy 处理，整条广播最多等待一个超时时间
+    send_all(&receivers, msg).await;
+}
+
+/// 私聊：只发给目标用户。目标不在线时返回 false
+pub async fn send_private(
+    contact: &SharedContacts,
+    sender_username: &str,
+    target: &str,
+    msg: String
+) -> bool {
+    let receiver = {
+        let guard = contact.lock().unwrap();
+        guard.get(target).map(|info| info.tx.clone())
+    };
+
+    match receiver {
+        Some(tx) => {
+            let private_msg = ServerMessage::PrivateMessage {
+                from: sender_username.to_string(),
+                content: msg,
+            };
+            tx.send(private_msg).await;
+            true
+        }
+        None => false,
     }
 }
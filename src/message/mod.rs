@@ -1 +1,2 @@
-pub mod broadcast;
\ No newline at end of file
+pub mod broadcast;
+pub mod room;
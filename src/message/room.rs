@@ -0,0 +1,34 @@
+// src/message/room.rs
+
+use crate::connection::{ SharedContacts, SharedRooms };
+use crate::codec::SharedMessage;
+use crate::connection::queue::{ ClientSender, send_all };
+use crate::protocol::ServerMessage;
+
+/// 把一条服务器消息发给房间内除 `except` 以外的所有成员。房间不存在时什么都不做
+pub async fn send_to_room(
+    contact: &SharedContacts,
+    rooms: &SharedRooms,
+    room: &str,
+    except: &str,
+    msg: ServerMessage
+) {
+    let msg = SharedMessage::new(msg);
+    // 加锁顺序固定为 rooms -> contact，避免死锁
+    let receivers: Vec<ClientSender> = {
+        let rooms = rooms.lock().unwrap();
+        let Some(members) = rooms.get(room) else {
+            return;
+        };
+        let contact = contact.lock().unwrap();
+        members
+            .iter()
+            .filter(|name| *name != except)
+            .filter_map(|name| contact.get(name))
+            .map(|info| info.tx.clone())
+            .collect()
+    };
+
+    // 队列满时按接收方的 SlowConsumerPolicy 处理，整条广播最多等待一个超时时间
+    send_all(&receivers, msg).await;
+}
@@ -0,0 +1,151 @@
+// src/protocol.rs
+
+// 结构化应用协议 (v0.4)：客户端和服务器之间交换的所有消息类型。
+// 具体的线路格式（行文本 / 长度前缀 JSON / WebSocket）由 codec 模块负责。
+
+use serde::{ Deserialize, Serialize };
+
+/// 客户端发来的单条消息（一帧 / 一行）默认的最大字节数，可以通过 `max_frame_size` 配置。
+/// 超过上限时服务器回复 `Disconnected` 后断开连接
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 64 * 1024;
+
+/// 客户端 -> 服务器
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum ClientMessage {
+    /// 登录，必须是连接上的第一条消息。
+    /// 服务器开启账号模式时，已注册的用户名必须带上密码；不带密码即以访客身份登录
+    Login {
+        username: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        password: Option<String>,
+    },
+    /// 注册账号并登录，只能在登录之前发送
+    Register {
+        username: String,
+        password: String,
+    },
+    /// 断线后用 `SessionToken` 中的令牌恢复原来的会话，代替 `Login`
+    Resume {
+        token: String,
+    },
+    /// 发给所有其他在线用户
+    Broadcast {
+        content: String,
+    },
+    /// 私聊
+    Whisper {
+        to: String,
+        content: String,
+    },
+    /// 心跳：服务器回复 `Pong`
+    Ping,
+    /// 对服务器 `Ping` 的回复
+    Pong,
+    /// 主动退出：会话立即结束，不保留给断线重连
+    Quit,
+}
+
+/// 服务器 -> 客户端
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum ServerMessage {
+    /// 登录成功
+    Welcome {
+        username: String,
+    },
+    /// 会话令牌，紧跟在 `Welcome` 之后发出。断线后 `grace_secs` 秒内可以用它 `Resume`，
+    /// 每次恢复后都会收到一个新令牌
+    SessionToken {
+        token: String,
+        grace_secs: u64,
+    },
+    /// 有用户上线，`online` 为当前在线人数（含该用户）
+    UserJoined {
+        username: String,
+        online: usize,
+    },
+    /// 有用户下线，`online` 为当前在线人数
+    UserLeft {
+        username: String,
+        online: usize,
+    },
+    NewMessage {
+        from: String,
+        content: String,
+    },
+    PrivateMessage {
+        from: String,
+        content: String,
+    },
+    /// 私聊发送成功后回显给发送者
+    PrivateMessageSent {
+        to: String,
+        content: String,
+    },
+    /// 当前在线用户列表（/list 的回复），`guests` 是其中以访客身份登录的用户
+    UserList {
+        users: Vec<String>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        guests: Vec<String>,
+    },
+    /// 加入房间成功，`members` 为加入后的成员列表
+    JoinedRoom {
+        room: String,
+        members: Vec<String>,
+    },
+    /// 离开房间成功
+    LeftRoom {
+        room: String,
+    },
+    /// 有用户加入了自己所在的房间
+    RoomUserJoined {
+        room: String,
+        username: String,
+    },
+    /// 有用户离开了自己所在的房间（包括断开连接）
+    RoomUserLeft {
+        room: String,
+        username: String,
+    },
+    /// 房间内的消息
+    RoomMessage {
+        room: String,
+        from: String,
+        content: String,
+    },
+    /// 房间成员列表（/list <room> 的回复）
+    RoomMembers {
+        room: String,
+        members: Vec<String>,
+    },
+    /// 当前所有房间（/rooms 的回复）
+    RoomList {
+        rooms: Vec<String>,
+    },
+    /// 只发给单个客户端的系统提示（如 /help 的输出）
+    Info {
+        msg: String,
+    },
+    Error {
+        msg: String,
+    },
+    /// 由于客户端读取太慢，有 `count` 条消息被丢弃
+    MessagesDropped {
+        count: u64,
+    },
+    /// 服务器即将关闭这条连接，之后不会再有任何消息
+    Disconnected {
+        reason: String,
+    },
+    /// 心跳：客户端应当回复 `Pong`，连续多次没有回应的连接会被断开
+    Ping,
+    /// 对客户端 `Ping` 的回复
+    Pong,
+}
+
+impl ServerMessage {
+    pub fn error(msg: impl Into<String>) -> Self {
+        ServerMessage::Error { msg: msg.into() }
+    }
+}
@@ -0,0 +1,695 @@
+// src/server.rs
+
+// ChatServer：把监听端口、线路协议、状态后端和斜杠命令组装在一起。
+// chat_server 二进制以及需要内嵌聊天服务的程序都通过它启动。
+
+use crate::auth::authenticator::Authenticator;
+use crate::auth::moderation::Moderation;
+use crate::auth::rules::{ UsernameRegistry, UsernameRules };
+use crate::backend::Backend;
+use crate::codec::{ Codec, JsonCodec, LineCodec, Transport, WebSocketCodec };
+use crate::command::{ Command, CommandRegistry };
+use crate::connection::limits::{ ConnectionGate, ConnectionLimits, Rejection };
+use crate::connection::listener::{ Listener, UNIX_PREFIX };
+#[cfg(unix)]
+use crate::connection::listener::UnixSocketListener;
+use crate::connection::peer::PeerAddr;
+use crate::connection::queue::{ QueueMetrics, SlowConsumerPolicy };
+use crate::connection::rate_limit::{ RateLimitConfig, RateLimiter };
+use crate::connection::session::{ SessionConfig, SessionStore };
+use crate::connection::tls::TlsAcceptor;
+use crate::connection::{ ConnectionContext, ConnectionSettings, shutdown_requested };
+use crate::connection::client::handle_connection;
+use crate::utils::sanitize::SanitizePolicy;
+use anyhow::{ Context, Result, anyhow };
+use bytes::BytesMut;
+use serde::Deserialize;
+use std::fmt;
+use std::future::Future;
+#[cfg(unix)]
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{ Duration, Instant };
+use tokio::io::{ AsyncRead, AsyncWrite };
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+use tracing::{ debug, error, info, warn };
+
+/// accept 出错（如文件描述符耗尽）后第一次重试前的等待时间，连续出错时逐次加倍
+const ACCEPT_BACKOFF_MIN: Duration = Duration::from_millis(100);
+/// accept 出错后重试间隔的上限
+const ACCEPT_BACKOFF_MAX: Duration = Duration::from_secs(1);
+
+/// 一个监听端口使用的线路协议
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    /// 以换行符分隔的纯文本 (nc / telnet)
+    Line,
+    /// 4 字节长度前缀 + JSON
+    Json,
+    /// RFC 6455 WebSocket，每条消息一个 JSON
+    WebSocket,
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Protocol::Line => "line",
+            Protocol::Json => "json",
+            Protocol::WebSocket => "websocket",
+        })
+    }
+}
+
+impl FromStr for Protocol {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "line" => Ok(Protocol::Line),
+            "json" => Ok(Protocol::Json),
+            "websocket" => Ok(Protocol::WebSocket),
+            _ => Err(anyhow!("unknown protocol '{s}' (expected line, json or websocket)")),
+        }
+    }
+}
+
+enum Bind {
+    Addr(String),
+    Listener(TcpListener),
+}
+
+struct ListenerSpec {
+    bind: Bind,
+    protocol: Protocol,
+    /// 覆盖服务器级别的慢消费者策略
+    slow_consumer: Option<SlowConsumerPolicy>,
+    /// 是否先做 TLS 握手
+    tls: bool,
+}
+
+/// `ChatServer` 的构建器
+pub struct ChatServerBuilder<B> {
+    backend: B,
+    listeners: Vec<ListenerSpec>,
+    commands: CommandRegistry<B>,
+    settings: ConnectionSettings,
+    connection_limits: ConnectionLimits,
+    drain_timeout: Duration,
+    tls: Option<TlsAcceptor>,
+}
+
+impl<B: Backend> ChatServerBuilder<B> {
+    /// 在 `addr` 上监听，使用 `protocol` 协议。`addr` 可以是 IPv4 / IPv6 地址（如 `[::1]:8080`），
+    /// 也可以是 `unix:` 开头的 Unix 套接字路径（如 `unix:/tmp/chat.sock`）
+    pub fn listen(mut self, addr: impl Into<String>, protocol: Protocol) -> Self {
+        self.listeners.push(ListenerSpec {
+            bind: Bind::Addr(addr.into()),
+            protocol,
+            slow_consumer: None,
+            tls: false,
+        });
+        self
+    }
+
+    /// 与 `listen` 相同，但该端口上的客户端使用自己的慢消费者策略
+    pub fn listen_with_policy(
+        mut self,
+        addr: impl Into<String>,
+        protocol: Protocol,
+        policy: SlowConsumerPolicy
+    ) -> Self {
+        self.listeners.push(ListenerSpec {
+            bind: Bind::Addr(addr.into()),
+            protocol,
+            slow_consumer: Some(policy),
+            tls: false,
+        });
+        self
+    }
+
+    /// 与 `listen` 相同，但客户端要先完成 TLS 握手（证书由 `tls` 设置）；
+    /// `policy` 覆盖服务器级别的慢消费者策略
+    pub fn listen_tls(
+        mut self,
+        addr: impl Into<String>,
+        protocol: Protocol,
+        policy: Option<SlowConsumerPolicy>
+    ) -> Self {
+        self.listeners.push(ListenerSpec {
+            bind: Bind::Addr(addr.into()),
+            protocol,
+            slow_consumer: policy,
+            tls: true,
+        });
+        self
+    }
+
+    /// 使用一个已经绑定好的监听器（例如内嵌时绑定到端口 0）
+    pub fn listener(mut self, listener: TcpListener, protocol: Protocol) -> Self {
+        self.listeners.push(ListenerSpec {
+            bind: Bind::Listener(listener),
+            protocol,
+            slow_consumer: None,
+            tls: false,
+        });
+        self
+    }
+
+    /// 每个客户端消息队列的容量
+    pub fn client_queue_size(mut self, size: usize) -> Self {
+        self.settings.client_queue_size = size;
+        self
+    }
+
+    /// 注册一条斜杠命令，同名的内置命令会被替换
+    pub fn command(mut self, command: impl Command<B>) -> Self {
+        self.commands.register(command);
+        self
+    }
+
+    /// 每个客户端队列最多积压的字节数，与 `client_queue_size` 任一项达到上限即视为队列已满
+    pub fn client_queue_bytes(mut self, bytes: usize) -> Self {
+        self.settings.client_queue_bytes = bytes;
+        self
+    }
+
+    /// 客户端发来的单条消息（一帧 / 一行）的最大字节数，超过后回复 `Disconnected` 并断开
+    pub fn max_frame_size(mut self, size: usize) -> Self {
+        self.settings.max_frame_size = size;
+        self
+    }
+
+    /// 客户端队列已满时的默认处理策略
+    pub fn slow_consumer_policy(mut self, policy: SlowConsumerPolicy) -> Self {
+        self.settings.slow_consumer = policy;
+        self
+    }
+
+    /// 连接建立后必须在这段时间内完成登录
+    pub fn login_timeout(mut self, timeout: Duration) -> Self {
+        self.settings.login_timeout = timeout;
+        self
+    }
+
+    /// 用户名和消息中终端控制字符（ESC 序列等）的处理方式
+    pub fn sanitize_policy(mut self, policy: SanitizePolicy) -> Self {
+        self.settings.sanitize = policy;
+        self
+    }
+
+    /// 用户名规则：长度、允许的字符、保留名、大小写和同形字是否视为同一个名字
+    pub fn username_rules(mut self, rules: UsernameRules) -> Self {
+        self.settings.usernames = Arc::new(UsernameRegistry::new(rules));
+        self
+    }
+
+    /// 客户端发消息的限速（按用户、按 IP）以及超限时的处理方式
+    pub fn rate_limit(mut self, config: RateLimitConfig) -> Self {
+        self.settings.rate_limiter = Arc::new(RateLimiter::new(config));
+        self
+    }
+
+    /// 每个连接一次最多合并写出多少条消息
+    pub fn write_batch_size(mut self, size: usize) -> Self {
+        self.settings.write_batch_size = size.max(1);
+        self
+    }
+
+    /// 凑批时最多等待多久再写出，用少量延迟换更少的系统调用；默认不等待
+    pub fn write_batch_delay(mut self, delay: Duration) -> Self {
+        self.settings.write_batch_delay = delay;
+        self
+    }
+
+    /// 登录后这么久没有发过消息（心跳不算）就断开，`Duration::ZERO` 表示不限制
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.settings.idle_timeout = timeout;
+        self
+    }
+
+    /// 对端沉默超过 `interval` 就发送心跳 Ping，沉默超过 `misses` 个间隔就断开并注销；
+    /// `interval` 为 0 时关闭心跳
+    pub fn heartbeat(mut self, interval: Duration, misses: u32) -> Self {
+        self.settings.heartbeat_interval = interval;
+        self.settings.heartbeat_misses = misses;
+        self
+    }
+
+    /// TCP 连接空闲 `time` 后开始发送 keepalive 探测，连续几次没有回应就由内核断开。
+    /// 不支持心跳的线路协议（行协议）靠它清理半开的连接；`Duration::ZERO` 表示不开启
+    pub fn tcp_keepalive(mut self, time: Duration) -> Self {
+        self.settings.tcp_keepalive = time;
+        self
+    }
+
+    /// 身份认证方式，例如 `AccountStore`、`AllowlistAuthenticator`、`TokenAuthenticator`
+    /// 或自己实现的 `Authenticator`；不设置时只要用户名合法就能登录
+    pub fn authenticator(mut self, authenticator: impl Authenticator) -> Self {
+        self.settings.authenticator = Arc::new(authenticator);
+        self
+    }
+
+    /// 角色名单和封禁名单（由 `Moderation::open` 加载），不设置时没有管理员，也没有封禁
+    pub fn moderation(mut self, moderation: Moderation) -> Self {
+        self.settings.moderation = Arc::new(moderation);
+        self
+    }
+
+    /// 断线重连：登录后发放会话令牌，断线的用户在宽限期内可以凭令牌接回原来的会话
+    pub fn sessions(mut self, config: SessionConfig) -> Self {
+        self.settings.sessions = Arc::new(SessionStore::new(config));
+        self
+    }
+
+    /// 连接总数、单个 IP 的连接数和建立连接速率的上限，所有端口共用
+    pub fn connection_limits(mut self, limits: ConnectionLimits) -> Self {
+        self.connection_limits = limits;
+        self
+    }
+
+    /// TLS 端口使用的证书（由 `TlsAcceptor::from_config` 加载）
+    pub fn tls(mut self, acceptor: TlsAcceptor) -> Self {
+        self.tls = Some(acceptor);
+        self
+    }
+
+    /// 关闭时等待客户端发完队列中消息的最长时间，超时的连接会被强制断开
+    pub fn drain_timeout(mut self, timeout: Duration) -> Self {
+        self.drain_timeout = timeout;
+        self
+    }
+
+    pub fn build(self) -> ChatServer<B> {
+        ChatServer {
+            backend: self.backend,
+            listeners: self.listeners,
+            commands: Arc::new(self.commands),
+            settings: self.settings,
+            gate: Arc::new(ConnectionGate::new(self.connection_limits)),
+            drain_timeout: self.drain_timeout,
+            tls: self.tls,
+        }
+    }
+}
+
+/// 聊天服务器：一个状态后端 + 若干个监听端口，所有端口的客户端共享同一个聊天室
+pub struct ChatServer<B> {
+    backend: B,
+    listeners: Vec<ListenerSpec>,
+    commands: Arc<CommandRegistry<B>>,
+    settings: ConnectionSettings,
+    gate: Arc<ConnectionGate>,
+    drain_timeout: Duration,
+    tls: Option<TlsAcceptor>,
+}
+
+impl<B: Backend> ChatServer<B> {
+    pub fn builder(backend: B) -> ChatServerBuilder<B> {
+        ChatServerBuilder {
+            backend,
+            listeners: Vec::new(),
+            commands: CommandRegistry::with_builtins(),
+            settings: ConnectionSettings::default(),
+            connection_limits: ConnectionLimits::default(),
+            drain_timeout: Duration::from_secs(10),
+            tls: None,
+        }
+    }
+
+    /// 所有客户端队列共享的计数器（丢弃的消息数、被断开的慢消费者数），可以在 `run` 之前取出
+    pub fn queue_metrics(&self) -> Arc<QueueMetrics> {
+        self.settings.queue_metrics.clone()
+    }
+
+    /// 限速器，可以从中读取各种处理方式的触发次数
+    pub fn rate_limiter(&self) -> Arc<RateLimiter> {
+        self.settings.rate_limiter.clone()
+    }
+
+    /// 连接计数，可以从中读取当前连接数和被拒绝的连接数
+    pub fn connection_gate(&self) -> Arc<ConnectionGate> {
+        self.gate.clone()
+    }
+
+    /// 绑定所有端口并运行，直到收到 SIGINT / SIGTERM 后优雅关闭。
+    /// 只有绑定失败（或 TLS 端口没有设置证书）时才会返回错误。
+    pub async fn run(self) -> Result<()> {
+        self.run_until(shutdown_signal()).await
+    }
+
+    /// 与 `run` 相同，但在 `signal` 完成时开始关闭（内嵌时可以用自己的关闭信号）。
+    ///
+    /// 关闭流程：停止接收新连接 -> 通知每个连接 -> 连接发完队列中的消息后发送
+    /// `Disconnected` 并关闭 socket -> 超过 drain_timeout 仍未结束的连接被强制中止。
+    pub async fn run_until(self, signal: impl Future<Output = ()>) -> Result<()> {
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let mut tasks = JoinSet::new();
+
+        let limits = self.settings.rate_limiter.config();
+        if limits.per_user.is_some() || limits.per_ip.is_some() {
+            info!(
+                per_user = ?limits.per_user,
+                per_ip = ?limits.per_ip,
+                on_violation = %limits.on_violation,
+                "Rate limiting enabled"
+            );
+        }
+
+        let authenticator = &self.settings.authenticator;
+        if authenticator.name() != "open" {
+            info!(method = authenticator.name(), "Authentication enabled");
+        }
+
+        let moderation = &self.settings.moderation;
+        if moderation.has_staff() || moderation.ban_count() > 0 {
+            info!(
+                admins = moderation.config().admins.len(),
+                moderators = moderation.config().moderators.len(),
+                bans = moderation.ban_count(),
+                "Moderation enabled"
+            );
+        }
+        if moderation.has_staff() && authenticator.name() == "open" {
+            warn!("Authentication is open, admins and moderators will not get their roles");
+        }
+
+        let sessions = &self.settings.sessions;
+        if sessions.enabled() {
+            info!(grace_secs = sessions.grace().as_secs(), "Session resumption enabled");
+        }
+
+        let caps = self.gate.limits();
+        if caps.max_connections > 0 || caps.per_ip > 0 || caps.accept_rate.is_some() {
+            info!(
+                max_connections = caps.max_connections,
+                per_ip = caps.per_ip,
+                accept_rate = ?caps.accept_rate,
+                "Connection limits enabled"
+            );
+        }
+
+        for ListenerSpec { bind, protocol, slow_consumer, tls } in self.listeners {
+            let tls = match (tls, &self.tls) {
+                (false, _) => None,
+                (true, Some(acceptor)) => Some(acceptor.clone()),
+                (true, None) => {
+                    anyhow::bail!("a TLS listener needs a certificate ([tls] cert_path and key_path)")
+                }
+            };
+            let mut settings = self.settings.clone();
+            if let Some(policy) = slow_consumer {
+                settings.slow_consumer = policy;
+            }
+            let ctx = ConnectionContext {
+                backend: self.backend.clone(),
+                commands: self.commands.clone(),
+                settings,
+                shutdown: shutdown_rx.clone(),
+            };
+            let port = Port {
+                ctx,
+                gate: self.gate.clone(),
+                drain_timeout: self.drain_timeout,
+                tls,
+            };
+            // 所有端口（TCP、IPv6、Unix 套接字）共享同一个后端
+            match bind {
+                Bind::Addr(addr) => match addr.strip_prefix(UNIX_PREFIX) {
+                    #[cfg(unix)]
+                    Some(path) => {
+                        let listener = UnixSocketListener::bind(Path::new(path))
+                            .with_context(|| format!("failed to bind {addr}"))?;
+                        spawn_port(&mut tasks, listener, protocol, port)?
+                    }
+                    #[cfg(not(unix))]
+                    Some(_) => anyhow::bail!("Unix domain sockets are not supported on this platform"),
+                    None => {
+                        let listener = TcpListener::bind(&addr)
+                            .await
+                            .with_context(|| format!("failed to bind {addr}"))?;
+                        spawn_port(&mut tasks, listener, protocol, port)?
+                    }
+                },
+                Bind::Listener(listener) => spawn_port(&mut tasks, listener, protocol, port)?,
+            }
+        }
+
+        signal.await;
+        info!("Shutdown signal received, no longer accepting connections.");
+        let started = Instant::now();
+        let _ = shutdown_tx.send(true);
+
+        let mut stats = DrainStats::default();
+        while let Some(result) = tasks.join_next().await {
+            if let Ok(port) = result {
+                stats.connections += port.connections;
+                stats.aborted += port.aborted;
+            }
+        }
+        let metrics = &self.settings.queue_metrics;
+        let flood = self.settings.rate_limiter.metrics();
+        let rejected = self.gate.metrics();
+        let resumption = self.settings.sessions.metrics();
+        info!(
+            connections = stats.connections,
+            drained = stats.connections - stats.aborted,
+            aborted = stats.aborted,
+            sessions_resumed = resumption.resumed(),
+            sessions_expired = resumption.expired(),
+            rejected_server_full = rejected.rejected_full(),
+            rejected_per_ip = rejected.rejected_per_ip(),
+            rejected_accept_rate = rejected.rejected_rate(),
+            dropped_messages = metrics.dropped(),
+            slow_consumers_disconnected = metrics.disconnected(),
+            rate_limit_warned = flood.warned(),
+            rate_limit_dropped = flood.dropped(),
+            rate_limit_muted = flood.muted(),
+            rate_limit_disconnected = flood.disconnected(),
+            elapsed_ms = started.elapsed().as_millis() as u64,
+            "Chat server shut down."
+        );
+        Ok(())
+    }
+}
+
+/// 等待 Ctrl-C (SIGINT) 或 SIGTERM
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!(error = %e, "Failed to listen for Ctrl-C");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(e) => {
+                error!(error = %e, "Failed to listen for SIGTERM");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+/// 一个端口关闭时的统计
+#[derive(Debug, Default)]
+struct DrainStats {
+    /// 收到关闭信号时仍然打开的连接数
+    connections: usize,
+    /// 超过 drain_timeout 被强制中止的连接数
+    aborted: usize,
+}
+
+/// 一个端口的接收循环需要的共享状态
+struct Port<B> {
+    ctx: ConnectionContext<B>,
+    gate: Arc<ConnectionGate>,
+    drain_timeout: Duration,
+    /// 不为空时先做 TLS 握手
+    tls: Option<TlsAcceptor>,
+}
+
+/// 按端口的线路协议选择 Codec，启动该端口的接收循环
+fn spawn_port<L, B>(
+    tasks: &mut JoinSet<DrainStats>,
+    listener: L,
+    protocol: Protocol,
+    port: Port<B>
+) -> Result<()>
+    where L: Listener, B: Backend
+{
+    info!(
+        addr = %listener.local_addr()?,
+        protocol = %protocol,
+        tls = port.tls.is_some(),
+        mutual_tls = port.tls.as_ref().is_some_and(TlsAcceptor::mutual),
+        backend = port.ctx.backend.name(),
+        slow_consumer = %port.ctx.settings.slow_consumer,
+        "Chat server listening"
+    );
+    let max = port.ctx.settings.max_frame_size;
+    match protocol {
+        Protocol::Line => {
+            let codec = move || LineCodec::with_max_frame_size(max);
+            tasks.spawn(serve(listener, port, codec))
+        }
+        Protocol::Json => {
+            let codec = move || JsonCodec::with_max_frame_size(max);
+            tasks.spawn(serve(listener, port, codec))
+        }
+        Protocol::WebSocket => {
+            let codec = move || WebSocketCodec::with_max_frame_size(max);
+            tasks.spawn(serve(listener, port, codec))
+        }
+    };
+    Ok(())
+}
+
+/// 一个端口的接收循环，`make_codec` 决定该端口使用的线路协议。
+/// 收到关闭信号后停止接收，等待已有连接在 `drain_timeout` 内结束。
+async fn serve<L, B, C, F>(listener: L, port: Port<B>, make_codec: F) -> DrainStats
+    where L: Listener, B: Backend, C: Codec, F: Fn() -> C
+{
+    let Port { ctx, gate, drain_timeout, tls } = port;
+    let mut shutdown = ctx.shutdown.clone();
+    let mut connections = JoinSet::new();
+    let mut backoff = ACCEPT_BACKOFF_MIN;
+
+    loop {
+        // 等待新的客户端连接，同时回收已经结束的连接任务
+        let (socket, addr) = tokio::select! {
+            result = listener.accept() => match result {
+                Ok(res) => {
+                    backoff = ACCEPT_BACKOFF_MIN;
+                    res
+                }
+                Err(e) => {
+                    // 文件描述符耗尽时监听 socket 一直可读，立即重试只会空转并刷屏日志，
+                    // 等一会儿让已有的连接释放描述符
+                    error!(error = %e, retry_in = ?backoff, "Failed to accept connection");
+                    tokio::select! {
+                        _ = tokio::time::sleep(backoff) => {}
+                        _ = shutdown_requested(&mut shutdown) => break,
+                    }
+                    backoff = (backoff * 2).min(ACCEPT_BACKOFF_MAX);
+                    continue;
+                }
+            },
+            Some(_) = connections.join_next() => continue,
+            _ = shutdown_requested(&mut shutdown) => break,
+        };
+
+        let permit = match gate.admit(addr.ip()) {
+            Ok(permit) => permit,
+            Err(rejection) => {
+                debug!(peer_addr = %addr, reason = %rejection, "Connection rejected");
+                // 握手之前没法发送加密的通知，TLS 端口直接关闭
+                if tls.is_none() {
+                    refuse::<L, C>(socket, make_codec(), rejection);
+                }
+                continue;
+            }
+        };
+
+        let keepalive = ctx.settings.tcp_keepalive;
+        if !keepalive.is_zero()
+            && let Err(e) = L::set_keepalive(&socket, keepalive)
+        {
+            debug!(peer_addr = %addr, error = %e, "Failed to enable TCP keepalive");
+        }
+
+        let ctx = ctx.clone();
+        let codec = make_codec();
+        let Some(acceptor) = tls.clone() else {
+            let transport = Transport::new(socket, codec);
+            info!(peer_addr = %addr, protocol = transport.protocol(), "New client connected");
+            // 为每个连接创建一个独立的异步任务
+            connections.spawn(async move {
+                let _permit = permit;
+                run_connection(transport, addr, None, ctx).await;
+            });
+            continue;
+        };
+
+        // 握手放在连接自己的任务里，慢速握手不会阻塞接收循环
+        connections.spawn(async move {
+            let _permit = permit;
+            let (stream, certificate) = match acceptor.accept(socket).await {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    debug!(peer_addr = %addr, error = %e, "TLS handshake failed");
+                    return;
+                }
+            };
+            let transport = Transport::new(stream, codec);
+            info!(
+                peer_addr = %addr,
+                protocol = transport.protocol(),
+                client_cert = certificate.as_deref(),
+                "New TLS client connected"
+            );
+            run_connection(transport, addr, certificate, ctx).await;
+        });
+    }
+
+    // 不再接收新连接
+    drop(listener);
+
+    let mut stats = DrainStats {
+        connections: connections.len(),
+        aborted: 0,
+    };
+    let drain = async {
+        while connections.join_next().await.is_some() {}
+    };
+    if tokio::time::timeout(drain_timeout, drain).await.is_err() {
+        stats.aborted = connections.len();
+        warn!(aborted = stats.aborted, "Drain timeout elapsed, aborting remaining connections.");
+        connections.shutdown().await;
+    }
+    stats
+}
+
+/// 处理一个连接直到结束，记录非正常结束的原因
+async fn run_connection<S, C, B>(
+    transport: Transport<S, C>,
+    addr: PeerAddr,
+    certificate: Option<String>,
+    ctx: ConnectionContext<B>
+)
+    where S: AsyncRead + AsyncWrite + Unpin, C: Codec, B: Backend
+{
+    if let Err(e) = handle_connection(transport, addr.clone(), certificate, ctx).await {
+        // 如果是 IO 错误（如 Broken pipe），则记录为警告
+        // 其他错误记录为错误
+        if let Some(io_err) = e.downcast_ref::<std::io::Error>() {
+            warn!(peer_addr = %addr, error = %io_err, "Connection handler finished with an I/O error");
+        } else {
+            error!(peer_addr = %addr, error = ?e, "Connection handler failed with an unexpected error");
+        }
+    }
+}
+
+/// 告诉被拒绝的客户端原因后直接关闭 socket。
+/// 只做一次非阻塞写：通知很短，一定能放进内核发送缓冲区；不为被拒绝的连接创建任务，
+/// 连接洪水时也不会因此多占用文件描述符
+fn refuse<L: Listener, C: Codec>(socket: L::Stream, mut codec: C, rejection: Rejection) {
+    let mut notice = BytesMut::new();
+    codec.refuse(rejection.notice(), &mut notice);
+    L::write_now(socket, &notice);
+}
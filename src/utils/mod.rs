@@ -1 +1,2 @@
-pub mod color;
\ No newline at end of file
+pub mod color;
+pub mod sanitize;
@@ -0,0 +1,220 @@
+// src/utils/sanitize.rs
+
+// 清理用户输入中的终端控制字符。
+//
+// 行协议的客户端直接在终端里显示服务器转发的文本，如果原样转发，任何人都可以通过
+// ESC 序列清屏、伪造 `[Server]` 行、修改窗口标题等等。用户名和消息在广播之前
+// 都要经过这里，处理方式由 SanitizePolicy 决定。制表符是唯一保留的控制字符。
+
+use anyhow::{ Result, anyhow };
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::fmt;
+use std::iter::Peekable;
+use std::str::{ Chars, FromStr };
+
+/// 遇到控制字符时的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SanitizePolicy {
+    /// 删除控制字符；ESC 开头的完整序列（CSI、OSC 等）整个删除
+    #[default]
+    Strip,
+    /// 把控制字符替换成可见的转义形式（如 `\u{1b}`），其余字符原样保留
+    Escape,
+    /// 拒绝包含控制字符的输入
+    Reject,
+}
+
+impl fmt::Display for SanitizePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SanitizePolicy::Strip => "strip",
+            SanitizePolicy::Escape => "escape",
+            SanitizePolicy::Reject => "reject",
+        })
+    }
+}
+
+impl FromStr for SanitizePolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "strip" => Ok(SanitizePolicy::Strip),
+            "escape" => Ok(SanitizePolicy::Escape),
+            "reject" => Ok(SanitizePolicy::Reject),
+            _ => Err(anyhow!("unknown sanitize policy '{s}' (expected strip, escape or reject)")),
+        }
+    }
+}
+
+/// 按 `policy` 清理 `input`。Reject 策略下输入含有控制字符时返回 `None`；
+/// 不含控制字符的输入不会被复制
+pub fn sanitize(input: &str, policy: SanitizePolicy) -> Option<Cow<'_, str>> {
+    if !input.chars().any(is_unsafe) {
+        return Some(Cow::Borrowed(input));
+    }
+    match policy {
+        SanitizePolicy::Strip => Some(Cow::Owned(strip(input))),
+        SanitizePolicy::Escape => Some(Cow::Owned(escape(input))),
+        SanitizePolicy::Reject => None,
+    }
+}
+
+/// 与 `sanitize` 相同，但接收并返回 `String`，不含控制字符时直接返回原字符串
+pub fn sanitize_owned(input: String, policy: SanitizePolicy) -> Option<String> {
+    let cleaned = match sanitize(&input, policy)? {
+        Cow::Borrowed(_) => None,
+        Cow::Owned(cleaned) => Some(cleaned),
+    };
+    Some(cleaned.unwrap_or(input))
+}
+
+/// C0、DEL 和 C1 控制字符，制表符除外
+fn is_unsafe(c: char) -> bool {
+    c.is_control() && c != '\t'
+}
+
+fn escape(input: &str) -> String {
+    let mut out = String::with_capacity(input.len() + 8);
+    for c in input.chars() {
+        if is_unsafe(c) {
+            out.extend(c.escape_default());
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+fn strip(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => skip_escape(&mut chars),
+            // 8 位的 CSI
+            '\u{9b}' => skip_csi(&mut chars),
+            // 8 位的 DCS、SOS、OSC、PM、APC，都以字符串结束符收尾
+            '\u{90}' | '\u{98}' | '\u{9d}' | '\u{9e}' | '\u{9f}' => skip_string(&mut chars),
+            c if is_unsafe(c) => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// 跳过 ESC 之后的部分
+fn skip_escape(chars: &mut Peekable<Chars<'_>>) {
+    match chars.next() {
+        Some('[') => skip_csi(chars),
+        // OSC、DCS、SOS、PM、APC
+        Some(']' | 'P' | 'X' | '^' | '_') => skip_string(chars),
+        // nF 序列：若干中间字节 (0x20-0x2F) 加一个结束字节，如 `ESC ( B`
+        Some('\x20'..='\x2f') => {
+            while chars.next_if(|c| matches!(c, '\x20'..='\x2f')).is_some() {}
+            chars.next();
+        }
+        // 其余都是 ESC 加一个字符，如 `ESC c`（重置终端）
+        _ => {}
+    }
+}
+
+/// CSI：参数字节 (0x30-0x3F)、中间字节 (0x20-0x2F)，最后一个结束字节 (0x40-0x7E)
+fn skip_csi(chars: &mut Peekable<Chars<'_>>) {
+    while chars.next_if(|c| matches!(c, '\x20'..='\x3f')).is_some() {}
+    chars.next_if(|c| matches!(c, '\x40'..='\x7e'));
+}
+
+/// OSC 等字符串序列：直到 BEL、ST (`ESC \` 或 0x9C) 为止；没有结束符时跳过剩下的全部内容
+fn skip_string(chars: &mut Peekable<Chars<'_>>) {
+    while let Some(c) = chars.next() {
+        match c {
+            '\x07' | '\u{9c}' => return,
+            '\x1b' => {
+                chars.next_if_eq(&'\\');
+                return;
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strip(input: &str) -> String {
+        sanitize(input, SanitizePolicy::Strip).unwrap().into_owned()
+    }
+
+    fn escape(input: &str) -> String {
+        sanitize(input, SanitizePolicy::Escape).unwrap().into_owned()
+    }
+
+    #[test]
+    fn plain_text_is_borrowed() {
+        let text = "hello\tworld，你好";
+        for policy in [SanitizePolicy::Strip, SanitizePolicy::Escape, SanitizePolicy::Reject] {
+            assert!(matches!(sanitize(text, policy), Some(Cow::Borrowed(t)) if t == text));
+        }
+    }
+
+    #[test]
+    fn strips_csi_sequences() {
+        assert_eq!(strip("\x1b[2J\x1b[Hhello"), "hello");
+        assert_eq!(strip("\x1b[31mred\x1b[0m"), "red");
+        assert_eq!(strip("a\x1b[38;2;255;0;0mb"), "ab");
+        assert_eq!(strip("\x1b[?25lhidden cursor"), "hidden cursor");
+        // 8 位 CSI
+        assert_eq!(strip("\u{9b}2Jcleared"), "cleared");
+        // 没有结束字节的 CSI 只删掉已有的部分
+        assert_eq!(strip("text\x1b[12"), "text");
+    }
+
+    #[test]
+    fn strips_osc_sequences() {
+        // 以 BEL 结束的窗口标题
+        assert_eq!(strip("\x1b]0;pwned\x07hi"), "hi");
+        // 以 ST 结束的超链接
+        assert_eq!(strip("\x1b]8;;http://evil\x1b\\link\x1b]8;;\x1b\\"), "link");
+        // 8 位 OSC 和 ST
+        assert_eq!(strip("\u{9d}0;title\u{9c}after"), "after");
+        // 没有结束符时吞掉剩余内容，而不是把负载当作文本留下
+        assert_eq!(strip("before\x1b]0;never ends"), "before");
+    }
+
+    #[test]
+    fn strips_other_controls() {
+        // 用回车和换行伪造一条服务器消息
+        assert_eq!(strip("hi\r\n[Server] admin has joined."), "hi[Server] admin has joined.");
+        assert_eq!(strip("bell\x07 back\x08space del\x7f"), "bell backspace del");
+        assert_eq!(strip("\x1bcreset"), "reset");
+        assert_eq!(strip("\x1b(Bcharset"), "charset");
+        assert_eq!(strip("tab\tkept"), "tab\tkept");
+    }
+
+    #[test]
+    fn escapes_to_visible_form() {
+        assert_eq!(escape("\x1b[2Jhi"), "\\u{1b}[2Jhi");
+        assert_eq!(escape("\x1b]0;title\x07"), "\\u{1b}]0;title\\u{7}");
+        assert_eq!(escape("a\r\nb"), "a\\r\\nb");
+        assert_eq!(escape("\u{9b}31m"), "\\u{9b}31m");
+    }
+
+    #[test]
+    fn rejects_control_sequences() {
+        assert!(sanitize("\x1b[31mred", SanitizePolicy::Reject).is_none());
+        assert!(sanitize("\x1b]0;title\x07", SanitizePolicy::Reject).is_none());
+        assert!(sanitize("line\nbreak", SanitizePolicy::Reject).is_none());
+    }
+
+    #[test]
+    fn parses_policy_names() {
+        for policy in [SanitizePolicy::Strip, SanitizePolicy::Escape, SanitizePolicy::Reject] {
+            assert_eq!(policy.to_string().parse::<SanitizePolicy>().unwrap(), policy);
+        }
+        assert!("remove".parse::<SanitizePolicy>().is_err());
+    }
+}